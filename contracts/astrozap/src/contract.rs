@@ -1,64 +1,426 @@
+use std::convert::TryFrom;
 use std::str::FromStr;
 
 use cosmwasm_std::{
-    entry_point, to_binary, Addr, Binary, Decimal256, Deps, DepsMut, Env, MessageInfo, Reply,
-    Response, StdError, StdResult, SubMsgExecutionResponse, Uint128,
+    attr, entry_point, from_binary, to_binary, Addr, Api, Attribute, Binary, BlockInfo, Coin,
+    CosmosMsg, Decimal, Decimal256, Deps, DepsMut, Env, Fraction, MessageInfo, QuerierWrapper,
+    Reply, Response, StdError, StdResult, Storage, SubMsg, SubMsgExecutionResponse, Uint128,
+    Uint512,
 };
+use cw20::{Cw20ReceiveMsg, Expiration};
 
-use astroport::factory::PairType;
+use astroport::asset::{AssetInfo as LegacyAssetInfo, PairInfo};
+use astroport::factory::{FeeInfoResponse, PairType};
+use astroport::generator::Cw20HookMsg as GeneratorHookMsg;
+use astroport::pair::PoolResponse;
+use astroport::router::SwapOperation;
 
-use cw_asset::{Asset, AssetInfo, AssetList};
+use cw_asset::{Asset, AssetInfo, AssetList, AssetListUnchecked};
 
 use crate::helpers::{
-    build_provide_liquidity_submsgs, build_swap_submsgs, event_contains_attr, handle_deposits,
-    query_pair, query_pool, query_simulation, unwrap_reply, bigint_to_uint128
+    build_claim_rewards_submsgs, build_provide_liquidity_submsgs, build_route_swap_submsgs,
+    build_swap_submsgs, build_unstake_msg, build_withdraw_liquidity_submsgs, find_event,
+    handle_deposits, parse_swap_event, parse_swap_operation, parse_withdraw_liquidity_event,
+    query_factory_pair, query_fee_info, query_generator, query_generator_deposit, query_pair,
+    query_pending_rewards, query_pool, query_route_simulation, query_simulation, query_stable_amp,
+    read_attribute, unwrap_reply,
 };
-use crate::math::Quadratic;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SimulateEnterResponse};
-use crate::state::{CacheData, CACHE};
+use crate::math::{
+    compute_stable_provide, compute_stable_spot_price, compute_xyk_provide, isqrt, Quadratic,
+    Solution, StableSwap, XykPair,
+};
+use crate::msg::{
+    ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, Referrer,
+    SimulateEnterResponse, SimulateExitResponse, SimulateMigrateLiquidityResponse, SolverInfo,
+    StakeInfoResponse,
+};
+use crate::state::{
+    CacheData, Config, ExitCacheData, PendingEnter, CACHE, CONFIG, EXIT_CACHE, REWARDS, SHARES,
+    STORED_CONFIG, TOTAL_SHARES, ZAP_ID,
+};
+
+/// The maximum protocol fee that can be charged on deposits, in basis points
+const MAX_FEE_BPS: u64 = 100;
+
+/// The maximum referral fee that can be charged on deposits, in basis points. The referral fee is
+/// set by whoever builds the message (e.g. a frontend) rather than by the owner, so it is capped
+/// separately, and lower
+const MAX_REFERRAL_FEE_BPS: u64 = 50;
+
+/// Vault shares that are assumed to exist in addition to those issued, backed by one liquidity
+/// token that is also assumed to exist. See `compute_vault_shares`
+const VIRTUAL_SHARES: Uint128 = Uint128::new(1_000_000);
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    _deps: DepsMut,
+    deps: DepsMut,
     _env: Env,
     _info: MessageInfo,
-    _msg: InstantiateMsg,
+    msg: InstantiateMsg,
 ) -> StdResult<Response> {
-    Ok(Response::new()) // do nothing
+    assert_fee_bps(msg.fee_bps)?;
+    let config = Config {
+        factory: deps.api.addr_validate(&msg.factory)?,
+        router: deps.api.addr_validate(&msg.router)?,
+        owner: deps.api.addr_validate(&msg.owner)?,
+        fee_bps: msg.fee_bps,
+        fee_collector: deps.api.addr_validate(&msg.fee_collector)?,
+        paused: false,
+        allowlist: validate_addrs(deps.api, msg.allowlist.unwrap_or_default())?,
+        denylist: validate_addrs(deps.api, msg.denylist.unwrap_or_default())?,
+        keepers: validate_addrs(deps.api, msg.keepers.unwrap_or_default())?,
+    };
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
     let api = deps.api;
     match msg {
-        ExecuteMsg::Enter {
+        ExecuteMsg::Receive(cw20_msg) => receive(deps, env, info, cw20_msg),
+        ExecuteMsg::Enter { ref pair, .. } => {
+            let pair_addr = api.addr_validate(pair)?;
+            let params = EnterParams::from_msg(api, &info.funds, msg)?;
+            enter(deps, env, info.sender, info.funds.into(), pair_addr, params)
+        }
+        ExecuteMsg::EnterByAssets {
+            ref asset_infos,
+            ref pair_type,
+            deadline,
+            ..
+        } => {
+            // Check the deadline before looking up the pair, so that an expired message fails
+            // without querying the factory
+            assert_deadline(&env.block, deadline)?;
+            let config = CONFIG.load(deps.storage)?;
+            let pair_addr = resolve_pair(
+                &deps.querier,
+                &config.factory,
+                &[
+                    asset_infos[0].check(api, None)?,
+                    asset_infos[1].check(api, None)?,
+                ],
+                pair_type.clone(),
+            )?;
+            let params = EnterParams::from_msg(api, &info.funds, msg)?;
+            enter(deps, env, info.sender, info.funds.into(), pair_addr, params)
+        }
+        ExecuteMsg::EnterViaRouter {
             pair,
+            deposit,
+            route,
+            minimum_received,
+            recipient,
+            auto_stake,
+            provide_slippage_tolerance,
+        } => enter_via_router(
+            deps,
+            env,
+            info.sender,
+            info.funds.into(),
+            api.addr_validate(&pair)?,
+            deposit.check(api, None)?,
+            route,
+            minimum_received,
+            recipient
+                .map(|recipient| api.addr_validate(&recipient))
+                .transpose()?,
+            auto_stake.unwrap_or(false),
+            provide_slippage_tolerance,
+        ),
+        ExecuteMsg::EnterMany {
+            targets,
             deposits,
+            recipient,
+            auto_stake,
+            provide_slippage_tolerance,
+        } => enter_many(
+            deps,
+            env,
+            info.sender,
+            info.funds.into(),
+            targets
+                .into_iter()
+                .map(|target| {
+                    Ok((
+                        api.addr_validate(&target.pair)?,
+                        target.weight,
+                        target.minimum_received,
+                    ))
+                })
+                .collect::<StdResult<Vec<_>>>()?,
+            deposits.check(api, None)?,
+            recipient
+                .map(|recipient| api.addr_validate(&recipient))
+                .transpose()?,
+            auto_stake.unwrap_or(false),
+            provide_slippage_tolerance,
+        ),
+        ExecuteMsg::MigrateLiquidity {
+            from_pair,
+            to_pair,
+            amount,
+            minimum_received,
+            route,
+        } => migrate_liquidity(
+            deps,
+            env,
+            info.sender,
+            info.funds.into(),
+            api.addr_validate(&from_pair)?,
+            api.addr_validate(&to_pair)?,
+            amount,
             minimum_received,
+            route,
+        ),
+        ExecuteMsg::Unstake { lp_token, shares } => unstake(
+            deps,
+            env,
+            info.sender,
+            api.addr_validate(&lp_token)?,
+            shares,
+        ),
+        ExecuteMsg::Compound {
+            pair,
+            routes,
+            minimum_received,
+        } => compound(
+            deps,
+            env,
+            info.sender,
+            api.addr_validate(&pair)?,
+            routes,
+            minimum_received,
+        ),
+        ExecuteMsg::UpdateConfig {
+            owner,
+            fee_bps,
+            fee_collector,
+            paused,
+            allowlist,
+            denylist,
+            keepers,
+        } => update_config(
+            deps,
+            info.sender,
+            owner.map(|owner| api.addr_validate(&owner)).transpose()?,
+            fee_bps,
+            fee_collector
+                .map(|fee_collector| api.addr_validate(&fee_collector))
+                .transpose()?,
+            paused,
+            allowlist
+                .map(|allowlist| validate_addrs(api, allowlist))
+                .transpose()?,
+            denylist
+                .map(|denylist| validate_addrs(api, denylist))
+                .transpose()?,
+            keepers
+                .map(|keepers| validate_addrs(api, keepers))
+                .transpose()?,
+        ),
+    }
+}
+
+fn receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> StdResult<Response> {
+    let api = deps.api;
+    let received_asset = Asset::cw20(info.sender, cw20_msg.amount);
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::Enter {
+            pair,
+            minimum_received,
+            recipient,
+            auto_stake,
+            deadline,
+            max_spread,
+            belief_price,
+            provide_slippage_tolerance,
+            referrer,
         } => enter(
             deps,
             env,
-            info,
+            api.addr_validate(&cw20_msg.sender)?,
+            AssetList::from(vec![received_asset.clone()]),
             api.addr_validate(&pair)?,
-            deposits.check(api, None)?,
+            EnterParams {
+                deposits: AssetList::from(vec![received_asset]),
+                minimum_received,
+                recipient_addr: validate_addr(api, recipient)?,
+                auto_stake: auto_stake.unwrap_or(false),
+                initial_price: None,
+                deadline,
+                max_spread,
+                belief_price,
+                provide_slippage_tolerance,
+                referrer: validate_referrer(api, referrer)?,
+            },
+        ),
+        Cw20HookMsg::EnterViaRouter {
+            pair,
+            route,
+            minimum_received,
+            recipient,
+            auto_stake,
+            provide_slippage_tolerance,
+        } => enter_via_router(
+            deps,
+            env,
+            api.addr_validate(&cw20_msg.sender)?,
+            AssetList::from(vec![received_asset.clone()]),
+            api.addr_validate(&pair)?,
+            received_asset,
+            route,
+            minimum_received,
+            recipient
+                .map(|recipient| api.addr_validate(&recipient))
+                .transpose()?,
+            auto_stake.unwrap_or(false),
+            provide_slippage_tolerance,
+        ),
+        Cw20HookMsg::MigrateLiquidity {
+            from_pair,
+            to_pair,
+            minimum_received,
+            route,
+        } => migrate_liquidity(
+            deps,
+            env,
+            api.addr_validate(&cw20_msg.sender)?,
+            AssetList::from(vec![received_asset]),
+            api.addr_validate(&from_pair)?,
+            api.addr_validate(&to_pair)?,
+            cw20_msg.amount,
+            minimum_received,
+            route,
+        ),
+        Cw20HookMsg::Stake { pair } => stake(
+            deps,
+            env,
+            api.addr_validate(&cw20_msg.sender)?,
+            api.addr_validate(&pair)?,
+            received_asset,
+        ),
+        Cw20HookMsg::Exit {
+            pair,
+            ask_asset,
+            minimum_received,
+        } => exit(
+            deps,
+            api.addr_validate(&cw20_msg.sender)?,
+            received_asset,
+            api.addr_validate(&pair)?,
+            ask_asset.check(api, None)?,
             minimum_received,
         ),
     }
 }
 
+/// The parameters of `enter` other than the pair, as validated from `ExecuteMsg::Enter`,
+/// `ExecuteMsg::EnterByAssets`, or `Cw20HookMsg::Enter`
+struct EnterParams {
+    deposits: AssetList,
+    minimum_received: Option<Uint128>,
+    recipient_addr: Option<Addr>,
+    auto_stake: bool,
+    initial_price: Option<Decimal>,
+    deadline: Option<Expiration>,
+    max_spread: Option<Decimal>,
+    belief_price: Option<Decimal>,
+    provide_slippage_tolerance: Option<Decimal>,
+    referrer: Option<(Addr, u64)>,
+}
+
+impl EnterParams {
+    /// Validate the parameters of `ExecuteMsg::Enter` or `ExecuteMsg::EnterByAssets`, which only
+    /// differ in how the pair is specified
+    fn from_msg(api: &dyn Api, funds: &[Coin], msg: ExecuteMsg) -> StdResult<Self> {
+        match msg {
+            ExecuteMsg::Enter {
+                deposits,
+                minimum_received,
+                recipient,
+                auto_stake,
+                initial_price,
+                deadline,
+                max_spread,
+                belief_price,
+                provide_slippage_tolerance,
+                referrer,
+                ..
+            }
+            | ExecuteMsg::EnterByAssets {
+                deposits,
+                minimum_received,
+                recipient,
+                auto_stake,
+                initial_price,
+                deadline,
+                max_spread,
+                belief_price,
+                provide_slippage_tolerance,
+                referrer,
+                ..
+            } => Ok(EnterParams {
+                deposits: deposits_or_funds(api, deposits, funds)?,
+                minimum_received,
+                recipient_addr: validate_addr(api, recipient)?,
+                auto_stake: auto_stake.unwrap_or(false),
+                initial_price,
+                deadline,
+                max_spread,
+                belief_price,
+                provide_slippage_tolerance,
+                referrer: validate_referrer(api, referrer)?,
+            }),
+            _ => Err(StdError::generic_err("not an enter message")),
+        }
+    }
+}
+
 fn enter(
     deps: DepsMut,
     env: Env,
-    info: MessageInfo,
+    user_addr: Addr,
+    mut received_assets: AssetList,
     pair_addr: Addr,
-    mut deposits: AssetList,
-    minimum_received: Option<Uint128>,
+    params: EnterParams,
 ) -> StdResult<Response> {
+    let EnterParams {
+        mut deposits,
+        minimum_received,
+        recipient_addr,
+        auto_stake,
+        initial_price,
+        deadline,
+        max_spread,
+        belief_price,
+        provide_slippage_tolerance,
+        referrer,
+    } = params;
+
+    // The deadline must not have passed. We check this before anything else, so that a transaction
+    // that sat in the mempool for too long is not executed against stale prices
+    assert_deadline(&env.block, deadline)?;
+    // The recipient must not be this contract
+    assert_recipient(&env, recipient_addr.as_ref())?;
+
+    // The contract must not be paused, and the pair must be allowed
+    let config = CONFIG.load(deps.storage)?;
+    assert_pair_allowed(&config, &pair_addr)?;
+
     let pair_info = query_pair(&deps.querier, &pair_addr)?;
     let pool_info = query_pool(&deps.querier, &pair_addr)?;
     let pool_assets = AssetList::from_legacy(&pool_info.assets);
 
-    // The pair must be of xyz type
+    // The pair must be registered with the factory
+    assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+    // The pair must be of XYK or stableswap type
     assert_pair_type(&pair_info.pair_type)?;
     // Each deposited asset must be contained by the pool
     assert_deposit_types(&pool_assets, &deposits)?;
@@ -67,87 +429,1380 @@ fn enter(
     assert_deposit_number(&deposits)?;
 
     // Handle deposits
-    // If the user claims to have deposited a CW20 token, we draw it from the user's wallet (user
-    // must have approved allowance)
-    // If the user claims to have deposited a native coin, we assert that the exact coin was indeed
-    // sent alone with `info.funds`
+    // If the user claims to have deposited an asset that the contract has already received (native
+    // coins sent along with `info.funds`, or a CW20 token sent via the `Receive` hook), we assert
+    // that the exact asset was indeed received
+    // If the user claims to have deposited any other CW20 token, we draw it from the user's wallet
+    // (user must have approved allowance)
     let deposit_msgs = handle_deposits(
         &deposits,
-        &mut info.funds.into(),
-        &info.sender,
+        &mut received_assets,
+        &user_addr,
         &env.contract.address,
     )?;
 
-    // Compute the optimal swap that will yield the most liquidity tokens, and deduct the amount
-    // that will be sent out from available assets
-    // Then, deduct the offer asset from the user's available assets (as they will be sent out)
-    let offer_asset = compute_offer_asset(&pool_assets, &deposits)?;
+    // Deduct the protocol fee, and the referral fee if a referrer is specified, from the deposits
+    let (protocol_fee, referral_fee, fee_msgs) =
+        deduct_fees(&config, &mut deposits, referrer.as_ref())?;
 
-    // Build submsgs
-    //
-    // If no swap is needed (i.e. offer amount is calculated to be zero), we simply provide the
-    // liquidity, and deduct the assets to be provided from the list of available assets
-    //
-    // If a swap is needed, we execute the swap, and deduct the offer asset from the list of
-    // available assets
-    let submsgs = if offer_asset.amount.is_zero() {
-        build_provide_liquidity_submsgs(&pair_addr, &mut deposits)?
+    let recipient_addr = recipient_addr.unwrap_or_else(|| user_addr.clone());
+    let auto_stake_receiver = if auto_stake {
+        Some(&recipient_addr)
+    } else {
+        None
+    };
+
+    let (submsgs, attrs) = if pool_info.total_share.is_zero() {
+        // If the pool is empty, there is no price to balance the deposits against. We provide the
+        // two deposits directly as the initial liquidity, in the ratio specified by the user, and
+        // deduct the assets to be provided from the list of available assets. The excess, if any,
+        // is left in the list to be refunded
+        let (mut assets_to_provide, initial_price) =
+            compute_initial_liquidity(&pool_assets, &deposits, initial_price)?;
+        for asset in &assets_to_provide {
+            deposits.deduct(asset)?;
+        }
+
+        let submsgs = build_provide_liquidity_submsgs(
+            &pair_addr,
+            &mut assets_to_provide,
+            auto_stake_receiver,
+            None,
+        )?;
+
+        (
+            submsgs,
+            vec![attr("initial_price", initial_price.to_string())],
+        )
     } else {
-        build_swap_submsgs(&pair_addr, &mut deposits, &offer_asset)?
+        assert_no_initial_price(initial_price)?;
+        build_enter_submsgs(
+            &deps.querier,
+            deps.storage,
+            &pair_info,
+            &pool_info,
+            &mut deposits,
+            auto_stake_receiver,
+            belief_price,
+            max_spread,
+            provide_slippage_tolerance,
+        )?
     };
 
     // Cache necessary data so that they can be accessed when handling reply
     let cache = CacheData {
-        user_addr: info.sender,
+        user_addr,
+        recipient_addr,
         pair_addr: pair_addr.clone(),
         liquidity_token_addr: pair_info.liquidity_token,
         assets: deposits.clone(),
         minimum_received,
+        auto_stake,
+        provide_slippage_tolerance,
+        intermediate_asset: None,
+        source_pair_addr: None,
+        routes: vec![],
+        pending: vec![],
+        compounding: false,
     };
-    CACHE.save(deps.storage, &cache)?;
+    start_zap(deps.storage, &cache)?;
 
     Ok(Response::new()
         .add_messages(deposit_msgs)
+        .add_messages(fee_msgs)
         .add_submessages(submsgs)
         .add_attribute("action", "astrozap/execute/enter")
-        .add_attribute("assets_deposited", deposits.to_string()))
+        .add_attribute("assets_deposited", deposits.to_string())
+        .add_attribute("protocol_fee", protocol_fee.to_string())
+        .add_attribute("referral_fee", referral_fee.to_string())
+        .add_attributes(attrs))
 }
 
-/// Assert the given Astroport pair is of the XYK type
+#[allow(clippy::too_many_arguments)]
+fn update_config(
+    deps: DepsMut,
+    sender_addr: Addr,
+    owner: Option<Addr>,
+    fee_bps: Option<u64>,
+    fee_collector: Option<Addr>,
+    paused: Option<bool>,
+    allowlist: Option<Vec<Addr>>,
+    denylist: Option<Vec<Addr>>,
+    keepers: Option<Vec<Addr>>,
+) -> StdResult<Response> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if sender_addr != config.owner {
+        return Err(StdError::generic_err("unauthorized: sender is not owner"));
+    }
+
+    if let Some(owner) = owner {
+        config.owner = owner;
+    }
+    if let Some(fee_bps) = fee_bps {
+        assert_fee_bps(fee_bps)?;
+        config.fee_bps = fee_bps;
+    }
+    if let Some(fee_collector) = fee_collector {
+        config.fee_collector = fee_collector;
+    }
+    if let Some(paused) = paused {
+        config.paused = paused;
+    }
+    if let Some(allowlist) = allowlist {
+        config.allowlist = allowlist;
+    }
+    if let Some(denylist) = denylist {
+        config.denylist = denylist;
+    }
+    if let Some(keepers) = keepers {
+        config.keepers = keepers;
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", "astrozap/execute/update_config"))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn enter_via_router(
+    deps: DepsMut,
+    env: Env,
+    user_addr: Addr,
+    mut received_assets: AssetList,
+    pair_addr: Addr,
+    deposit: Asset,
+    route: Vec<SwapOperation>,
+    minimum_received: Option<Uint128>,
+    recipient_addr: Option<Addr>,
+    auto_stake: bool,
+    provide_slippage_tolerance: Option<Decimal>,
+) -> StdResult<Response> {
+    // The recipient must not be this contract
+    assert_recipient(&env, recipient_addr.as_ref())?;
+
+    // The contract must not be paused, and the pair must be allowed
+    let config = CONFIG.load(deps.storage)?;
+    assert_pair_allowed(&config, &pair_addr)?;
+
+    let pair_info = query_pair(&deps.querier, &pair_addr)?;
+    let pool_info = query_pool(&deps.querier, &pair_addr)?;
+    let pool_assets = AssetList::from_legacy(&pool_info.assets);
+
+    // The pair must be registered with the factory
+    assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+    // The pair must be of XYK or stableswap type
+    assert_pair_type(&pair_info.pair_type)?;
+    // The pool must not be empty, as a single asset cannot be provided as the initial liquidity
+    if pool_info.total_share.is_zero() {
+        return Err(StdError::generic_err(
+            "cannot enter an empty pool via the router",
+        ));
+    }
+    // Must deposit a non-zero amount
+    if deposit.amount.is_zero() {
+        return Err(StdError::generic_err(
+            "deposit amount must be greater than zero",
+        ));
+    }
+    // The route must start from the deposited asset, and end in one of the pool's assets
+    let intermediate_info = assert_route(&route, &deposit.info, &pool_assets)?;
+
+    // Handle deposit. See `enter` for details
+    let mut deposits = AssetList::from(vec![deposit.clone()]);
+    let deposit_msgs = handle_deposits(
+        &deposits,
+        &mut received_assets,
+        &user_addr,
+        &env.contract.address,
+    )?;
+
+    // Deduct the protocol fee from the deposit before swapping it
+    let (protocol_fee, _, fee_msgs) = deduct_fees(&config, &mut deposits, None)?;
+    let offer_asset = Asset::new(deposit.info.clone(), deposits[0].amount);
+
+    // The router does not report the amount it returns in a way that is easy to parse, especially
+    // if the route includes native swaps. Instead, we record the contract's balance of the asset to
+    // be received, and compare it with the balance after the swap
+    let intermediate_balance =
+        intermediate_info.query_balance(&deps.querier, &env.contract.address)?;
+    let submsgs = build_route_swap_submsgs(&config.router, &offer_asset, route)?;
+
+    // Cache necessary data so that they can be accessed when handling reply
+    let cache = CacheData {
+        user_addr: user_addr.clone(),
+        recipient_addr: recipient_addr.unwrap_or(user_addr),
+        pair_addr,
+        liquidity_token_addr: pair_info.liquidity_token,
+        assets: AssetList::new(),
+        minimum_received,
+        auto_stake,
+        provide_slippage_tolerance,
+        intermediate_asset: Some(Asset::new(intermediate_info, intermediate_balance)),
+        source_pair_addr: None,
+        routes: vec![],
+        pending: vec![],
+        compounding: false,
+    };
+    start_zap(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_messages(deposit_msgs)
+        .add_messages(fee_msgs)
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/execute/enter_via_router")
+        .add_attribute("asset_deposited", deposit.to_string())
+        .add_attribute("protocol_fee", protocol_fee.to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn enter_many(
+    deps: DepsMut,
+    env: Env,
+    user_addr: Addr,
+    mut received_assets: AssetList,
+    targets: Vec<(Addr, u64, Option<Uint128>)>,
+    mut deposits: AssetList,
+    recipient_addr: Option<Addr>,
+    auto_stake: bool,
+    provide_slippage_tolerance: Option<Decimal>,
+) -> StdResult<Response> {
+    // Must specify at least one target, each with a non-zero weight
+    if targets.is_empty() {
+        return Err(StdError::generic_err("must specify at least one target"));
+    }
+    if targets.iter().any(|(_, weight, _)| *weight == 0) {
+        return Err(StdError::generic_err(
+            "target weights must be greater than zero",
+        ));
+    }
+    // The recipient must not be this contract
+    assert_recipient(&env, recipient_addr.as_ref())?;
+    // Must deposit exactly 1 or 2 non-zero assets
+    deposits.purge();
+    assert_deposit_number(&deposits)?;
+
+    // Deduct the protocol fee from the deposits, then split the rest among the targets, and
+    // validate each of them
+    let config = CONFIG.load(deps.storage)?;
+    let mut net_deposits = deposits.clone();
+    let (protocol_fee, _, fee_msgs) = deduct_fees(&config, &mut net_deposits, None)?;
+    let splits = split_deposits(&net_deposits, &targets)?;
+    let mut steps: Vec<PendingEnter> = vec![];
+    for ((pair_addr, _, minimum_received), assets) in targets.into_iter().zip(splits) {
+        // The contract must not be paused, and the pair must be allowed
+        assert_pair_allowed(&config, &pair_addr)?;
+
+        let pair_info = query_pair(&deps.querier, &pair_addr)?;
+        let pool_info = query_pool(&deps.querier, &pair_addr)?;
+
+        // The pair must be registered with the factory
+        assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+        // The pair must be of XYK or stableswap type
+        assert_pair_type(&pair_info.pair_type)?;
+        // Each deposited asset must be contained by the pool
+        assert_deposit_types(&AssetList::from_legacy(&pool_info.assets), &deposits)?;
+        // The pool must not be empty, as there is no initial price to provide the liquidity at
+        if pool_info.total_share.is_zero() {
+            return Err(StdError::generic_err(format!(
+                "cannot enter empty pool {} in a batch",
+                pair_addr
+            )));
+        }
+        // Each target must receive a non-zero amount of deposits
+        if assets.len() == 0 {
+            return Err(StdError::generic_err(format!(
+                "deposits are too small to be split; {} receives nothing",
+                pair_addr
+            )));
+        }
+
+        steps.push(PendingEnter {
+            pair_addr,
+            liquidity_token_addr: pair_info.liquidity_token,
+            assets,
+            minimum_received,
+        });
+    }
+
+    // Handle deposits. See `enter` for details
+    let deposit_msgs = handle_deposits(
+        &deposits,
+        &mut received_assets,
+        &user_addr,
+        &env.contract.address,
+    )?;
+
+    // Enter the first pair right away, and queue the rest to be entered in reply once the previous
+    // one is done
+    let first = steps.remove(0);
+    let mut cache = CacheData {
+        user_addr: user_addr.clone(),
+        recipient_addr: recipient_addr.unwrap_or(user_addr),
+        pair_addr: first.pair_addr,
+        liquidity_token_addr: first.liquidity_token_addr,
+        assets: first.assets,
+        minimum_received: first.minimum_received,
+        auto_stake,
+        provide_slippage_tolerance,
+        intermediate_asset: None,
+        source_pair_addr: None,
+        routes: vec![],
+        pending: steps,
+        compounding: false,
+    };
+    let (submsgs, attrs) = build_cached_enter_submsgs(&deps.querier, deps.storage, &mut cache)?;
+    start_zap(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_messages(deposit_msgs)
+        .add_messages(fee_msgs)
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/execute/enter_many")
+        .add_attribute("assets_deposited", deposits.to_string())
+        .add_attribute("protocol_fee", protocol_fee.to_string())
+        .add_attribute("pairs_pending", cache.pending.len().to_string())
+        .add_attributes(attrs))
+}
+
+/// Split each deposited asset among the targets in proportion to their weights. Amounts are rounded
+/// down, and the remainder goes to the last target, so that the splits always add up to the deposits
+fn split_deposits(
+    deposits: &AssetList,
+    targets: &[(Addr, u64, Option<Uint128>)],
+) -> StdResult<Vec<AssetList>> {
+    let total_weight: u128 = targets
+        .iter()
+        .map(|(_, weight, _)| u128::from(*weight))
+        .sum();
+
+    let mut remaining = deposits.clone();
+    let mut splits = vec![];
+    for (i, (_, weight, _)) in targets.iter().enumerate() {
+        let split = if i == targets.len() - 1 {
+            remaining.clone()
+        } else {
+            let mut split = AssetList::new();
+            for deposit in deposits {
+                let amount = deposit.amount.multiply_ratio(*weight, total_weight);
+                split.add(&Asset::new(deposit.info.clone(), amount))?;
+            }
+            remaining.deduct_many(&split)?;
+            split
+        };
+        splits.push(split);
+    }
+
+    Ok(splits)
+}
+
+fn exit(
+    deps: DepsMut,
+    user_addr: Addr,
+    shares: Asset,
+    pair_addr: Addr,
+    ask_asset_info: AssetInfo,
+    minimum_received: Option<Uint128>,
+) -> StdResult<Response> {
+    let config = CONFIG.load(deps.storage)?;
+    let pair_info = query_pair(&deps.querier, &pair_addr)?;
+    let pool_info = query_pool(&deps.querier, &pair_addr)?;
+    let pool_assets = AssetList::from_legacy(&pool_info.assets);
+
+    // The pair must be registered with the factory
+    assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+    // The token sent must be the pair's liquidity token
+    assert_liquidity_token(&pair_info.liquidity_token, &shares.info)?;
+    // The asset to receive must be contained by the pool
+    assert_asset_type(&pool_assets, &ask_asset_info)?;
+
+    // Burn the liquidity tokens. Once the underlying assets are withdrawn, we swap one of them into
+    // the other in reply
+    let submsgs = build_withdraw_liquidity_submsgs(&pair_addr, &shares, 3)?;
+
+    // Cache necessary data so that they can be accessed when handling reply
+    let cache = ExitCacheData {
+        user_addr,
+        pair_addr,
+        ask_asset_info,
+        assets: AssetList::new(),
+        minimum_received,
+    };
+    start_exit(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/execute/exit")
+        .add_attribute("shares_burned", shares.to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn migrate_liquidity(
+    deps: DepsMut,
+    env: Env,
+    user_addr: Addr,
+    mut received_assets: AssetList,
+    from_pair_addr: Addr,
+    to_pair_addr: Addr,
+    lp_amount: Uint128,
+    minimum_received: Option<Uint128>,
+    route: Option<Vec<SwapOperation>>,
+) -> StdResult<Response> {
+    if from_pair_addr == to_pair_addr {
+        return Err(StdError::generic_err(
+            "cannot migrate liquidity into the same pair",
+        ));
+    }
+    // The contract must not be paused, and the target pair must be allowed
+    let config = CONFIG.load(deps.storage)?;
+    assert_pair_allowed(&config, &to_pair_addr)?;
+
+    let from_pair_info = query_pair(&deps.querier, &from_pair_addr)?;
+    let from_pool_info = query_pool(&deps.querier, &from_pair_addr)?;
+    let to_pair_info = query_pair(&deps.querier, &to_pair_addr)?;
+    let to_pool_info = query_pool(&deps.querier, &to_pair_addr)?;
+
+    // Both pairs must be registered with the factory
+    assert_registered_pair(
+        &deps.querier,
+        &config.factory,
+        &from_pair_addr,
+        &from_pair_info,
+    )?;
+    assert_registered_pair(&deps.querier, &config.factory, &to_pair_addr, &to_pair_info)?;
+    // Any token sent must be the source pair's liquidity token
+    for asset in &received_assets {
+        assert_liquidity_token(&from_pair_info.liquidity_token, &asset.info)?;
+    }
+    // The target pair must be of XYK or stableswap type
+    assert_pair_type(&to_pair_info.pair_type)?;
+    // The target pool must not be empty
+    if to_pool_info.total_share.is_zero() {
+        return Err(StdError::generic_err(
+            "cannot migrate liquidity into an empty pool",
+        ));
+    }
+    // The two pairs must share at least one asset, or else a route must be provided to swap one of
+    // the source pool's assets into one of the target pool's
+    assert_migration_route(
+        &AssetList::from_legacy(&from_pool_info.assets),
+        &AssetList::from_legacy(&to_pool_info.assets),
+        route.as_deref(),
+    )?;
+
+    // Handle the liquidity tokens. See `enter` for details
+    let shares = Asset::cw20(from_pair_info.liquidity_token, lp_amount);
+    let deposit_msgs = handle_deposits(
+        &AssetList::from(vec![shares.clone()]),
+        &mut received_assets,
+        &user_addr,
+        &env.contract.address,
+    )?;
+
+    // Burn the liquidity tokens. Once the underlying assets are withdrawn, we provide them to the
+    // target pair in reply
+    let submsgs = build_withdraw_liquidity_submsgs(&from_pair_addr, &shares, 6)?;
+
+    // Cache necessary data so that they can be accessed when handling reply
+    let cache = CacheData {
+        user_addr: user_addr.clone(),
+        recipient_addr: user_addr,
+        pair_addr: to_pair_addr,
+        liquidity_token_addr: to_pair_info.liquidity_token,
+        assets: AssetList::new(),
+        minimum_received,
+        auto_stake: false,
+        provide_slippage_tolerance: None,
+        intermediate_asset: None,
+        source_pair_addr: Some(from_pair_addr),
+        routes: route.into_iter().collect(),
+        pending: vec![],
+        compounding: false,
+    };
+    start_zap(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_messages(deposit_msgs)
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/execute/migrate_liquidity")
+        .add_attribute("shares_burned", shares.to_string()))
+}
+
+fn stake(
+    deps: DepsMut,
+    env: Env,
+    user_addr: Addr,
+    pair_addr: Addr,
+    lp_token: Asset,
+) -> StdResult<Response> {
+    let config = CONFIG.load(deps.storage)?;
+    let pair_info = query_pair(&deps.querier, &pair_addr)?;
+
+    // The pair must be registered with the factory
+    assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+    // The token sent must be the pair's liquidity token
+    assert_liquidity_token(&pair_info.liquidity_token, &lp_token.info)?;
+
+    let generator_addr = query_generator(&deps.querier, &config.factory)?;
+    let lp_token_addr = pair_info.liquidity_token;
+
+    // The generator pays out the pending rewards when liquidity tokens are deposited. Record them
+    // so that they are compounded by the next `Compound`
+    record_pending_rewards(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &generator_addr,
+        &lp_token_addr,
+    )?;
+
+    // Issue shares in proportion to the liquidity tokens already staked, which include those minted
+    // by compounding
+    let total_shares = TOTAL_SHARES
+        .may_load(deps.storage, &lp_token_addr)?
+        .unwrap_or_default();
+    let deposit = query_generator_deposit(
+        &deps.querier,
+        &generator_addr,
+        &lp_token_addr,
+        &env.contract.address,
+    )?;
+    let shares = compute_vault_shares(lp_token.amount, total_shares, deposit);
+    if shares.is_zero() {
+        return Err(StdError::generic_err("stake too small to mint any shares"));
+    }
+
+    TOTAL_SHARES.save(deps.storage, &lp_token_addr, &(total_shares + shares))?;
+    SHARES.update(
+        deps.storage,
+        (&lp_token_addr, &user_addr),
+        |user_shares| -> StdResult<_> { Ok(user_shares.unwrap_or_default() + shares) },
+    )?;
+
+    let deposit_msg =
+        lp_token.send_msg(&generator_addr, to_binary(&GeneratorHookMsg::Deposit {})?)?;
+
+    Ok(Response::new()
+        .add_message(deposit_msg)
+        .add_attribute("action", "astrozap/execute/stake")
+        .add_attribute("asset_staked", lp_token.to_string())
+        .add_attribute("shares_issued", shares.to_string()))
+}
+
+fn unstake(
+    deps: DepsMut,
+    env: Env,
+    user_addr: Addr,
+    lp_token_addr: Addr,
+    shares: Uint128,
+) -> StdResult<Response> {
+    let user_shares = SHARES
+        .may_load(deps.storage, (&lp_token_addr, &user_addr))?
+        .unwrap_or_default();
+    if shares.is_zero() || shares > user_shares {
+        return Err(StdError::generic_err(format!(
+            "invalid shares: must be greater than zero and no more than {}",
+            user_shares
+        )));
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let generator_addr = query_generator(&deps.querier, &config.factory)?;
+
+    // The generator pays out the pending rewards when liquidity tokens are withdrawn. Record them
+    // so that they are compounded by the next `Compound`, for the benefit of the remaining stakers
+    record_pending_rewards(
+        deps.storage,
+        &deps.querier,
+        &env,
+        &generator_addr,
+        &lp_token_addr,
+    )?;
+
+    let total_shares = TOTAL_SHARES.load(deps.storage, &lp_token_addr)?;
+    let deposit = query_generator_deposit(
+        &deps.querier,
+        &generator_addr,
+        &lp_token_addr,
+        &env.contract.address,
+    )?;
+    let lp_token = Asset::cw20(
+        lp_token_addr.clone(),
+        compute_vault_lp_amount(shares, total_shares, deposit),
+    );
+
+    if shares == total_shares {
+        TOTAL_SHARES.remove(deps.storage, &lp_token_addr);
+    } else {
+        TOTAL_SHARES.save(deps.storage, &lp_token_addr, &(total_shares - shares))?;
+    }
+    if shares == user_shares {
+        SHARES.remove(deps.storage, (&lp_token_addr, &user_addr));
+    } else {
+        SHARES.save(
+            deps.storage,
+            (&lp_token_addr, &user_addr),
+            &(user_shares - shares),
+        )?;
+    }
+
+    let withdraw_msg = build_unstake_msg(&generator_addr, &lp_token_addr, lp_token.amount)?;
+
+    Ok(Response::new()
+        .add_message(withdraw_msg)
+        .add_message(lp_token.transfer_msg(&user_addr)?)
+        .add_attribute("action", "astrozap/execute/unstake")
+        .add_attribute("shares_burned", shares.to_string())
+        .add_attribute("asset_unstaked", lp_token.to_string()))
+}
+
+fn compound(
+    deps: DepsMut,
+    env: Env,
+    sender_addr: Addr,
+    pair_addr: Addr,
+    routes: Vec<Vec<SwapOperation>>,
+    minimum_received: Option<Uint128>,
+) -> StdResult<Response> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if sender_addr != config.owner && !config.keepers.contains(&sender_addr) {
+        return Err(StdError::generic_err(
+            "unauthorized: sender is neither owner nor keeper",
+        ));
+    }
+
+    // The contract must not be paused, and the pair must be allowed
+    assert_pair_allowed(&config, &pair_addr)?;
+
+    let pair_info = query_pair(&deps.querier, &pair_addr)?;
+    let pool_info = query_pool(&deps.querier, &pair_addr)?;
+    let pool_assets = AssetList::from_legacy(&pool_info.assets);
+
+    // The pair must be registered with the factory
+    assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+    // The pair must be of XYK or stableswap type
+    assert_pair_type(&pair_info.pair_type)?;
+
+    // The rewards to compound are those pending in the generator, along with those received when
+    // liquidity tokens were staked or unstaked, and those left over from the last `Compound`
+    let generator_addr = query_generator(&deps.querier, &config.factory)?;
+    let lp_token_addr = pair_info.liquidity_token.clone();
+    let mut rewards = REWARDS
+        .may_load(deps.storage, &lp_token_addr)?
+        .unwrap_or_default();
+    rewards.add_many(&query_pending_rewards(
+        &deps.querier,
+        &generator_addr,
+        &lp_token_addr,
+        &env.contract.address,
+    )?)?;
+    rewards.purge();
+    if rewards.len() == 0 {
+        return Err(StdError::generic_err("no rewards to compound"));
+    }
+    REWARDS.remove(deps.storage, &lp_token_addr);
+
+    // Each reward not in the pair must be swapped into one of the pair's assets through the router.
+    // The routes are run in the order the rewards are listed
+    let mut reward_routes = vec![];
+    for reward in &rewards {
+        if pool_assets.find(&reward.info).is_some() {
+            continue;
+        }
+        let route = routes
+            .iter()
+            .find(|route| {
+                route
+                    .first()
+                    .map(|operation| parse_swap_operation(operation).0)
+                    .as_ref()
+                    == Some(&reward.info)
+            })
+            .ok_or_else(|| {
+                StdError::generic_err(format!("no route provided for reward {}", reward.info))
+            })?;
+        assert_route(route, &reward.info, &pool_assets)?;
+        reward_routes.push(route.clone());
+    }
+
+    // Claim the pending rewards. Once they are received, we swap them and enter the pair in reply
+    let submsgs = build_claim_rewards_submsgs(&generator_addr, &lp_token_addr)?;
+
+    // Cache necessary data so that they can be accessed when handling reply. The liquidity tokens
+    // are staked on behalf of this contract, so they accrue to the vault shares
+    let cache = CacheData {
+        user_addr: sender_addr,
+        recipient_addr: env.contract.address,
+        pair_addr,
+        liquidity_token_addr: lp_token_addr,
+        assets: rewards.clone(),
+        minimum_received,
+        auto_stake: true,
+        provide_slippage_tolerance: None,
+        intermediate_asset: None,
+        source_pair_addr: None,
+        routes: reward_routes,
+        pending: vec![],
+        compounding: true,
+    };
+    start_zap(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/execute/compound")
+        .add_attribute("rewards_compounded", rewards.to_string()))
+}
+
+/// Compute the vault shares to issue for staking the given amount of liquidity tokens, given the
+/// total shares issued and the liquidity tokens staked so far
+///
+/// The shares are computed against `VIRTUAL_SHARES` more shares and one more liquidity token than
+/// there really are. Without this offset, the first staker could stake a tiny amount, then donate
+/// liquidity tokens to the vault's generator deposit, so that later stakes round down to zero or
+/// few shares, and the donation plus the rounding loss accrues to the first staker
+fn compute_vault_shares(lp_amount: Uint128, total_shares: Uint128, deposit: Uint128) -> Uint128 {
+    lp_amount.multiply_ratio(total_shares + VIRTUAL_SHARES, deposit + Uint128::new(1))
+}
+
+/// Compute the liquidity tokens the given vault shares are worth, with the same offset as in
+/// `compute_vault_shares`
+fn compute_vault_lp_amount(shares: Uint128, total_shares: Uint128, deposit: Uint128) -> Uint128 {
+    shares.multiply_ratio(deposit + Uint128::new(1), total_shares + VIRTUAL_SHARES)
+}
+
+/// Add the rewards pending in the generator for this contract's liquidity tokens to the rewards to
+/// be compounded. Called before depositing or withdrawing liquidity tokens, as either pays them out
+fn record_pending_rewards(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    generator_addr: &Addr,
+    lp_token_addr: &Addr,
+) -> StdResult<()> {
+    let pending = query_pending_rewards(
+        querier,
+        generator_addr,
+        lp_token_addr,
+        &env.contract.address,
+    )?;
+    if pending.len() == 0 {
+        return Ok(());
+    }
+
+    let mut rewards = REWARDS
+        .may_load(storage, lp_token_addr)?
+        .unwrap_or_default();
+    rewards.add_many(&pending)?;
+    REWARDS.save(storage, lp_token_addr, &rewards)
+}
+
+/// Assert that the recipient of a zap is not this contract. Only `Compound` enters a pair on behalf
+/// of this contract; liquidity tokens minted to it by any other zap would be mistaken for rewards,
+/// or staked into the vault without issuing any shares
+fn assert_recipient(env: &Env, recipient_addr: Option<&Addr>) -> StdResult<()> {
+    if recipient_addr == Some(&env.contract.address) {
+        return Err(StdError::generic_err("recipient cannot be this contract"));
+    }
+    Ok(())
+}
+
+/// Assert that the contract is not paused, and that the pair is allowed to be entered: it must be
+/// in the allowlist if the allowlist is not empty, and must not be in the denylist
+fn assert_pair_allowed(config: &Config, pair_addr: &Addr) -> StdResult<()> {
+    if config.paused {
+        return Err(StdError::generic_err("contract is paused"));
+    }
+    if !config.allowlist.is_empty() && !config.allowlist.contains(pair_addr) {
+        return Err(StdError::generic_err(format!(
+            "pair {} is not in the allowlist",
+            pair_addr
+        )));
+    }
+    if config.denylist.contains(pair_addr) {
+        return Err(StdError::generic_err(format!(
+            "pair {} is in the denylist",
+            pair_addr
+        )));
+    }
+    Ok(())
+}
+
+/// Look up the pair of the given assets from the Astroport factory. If `pair_type` is provided, the
+/// pair must be of this type
+fn resolve_pair(
+    querier: &QuerierWrapper,
+    factory_addr: &Addr,
+    asset_infos: &[AssetInfo; 2],
+    pair_type: Option<PairType>,
+) -> StdResult<Addr> {
+    let legacy_asset_infos: [LegacyAssetInfo; 2] =
+        [(&asset_infos[0]).into(), (&asset_infos[1]).into()];
+    let pair_info =
+        query_factory_pair(querier, factory_addr, &legacy_asset_infos).map_err(|_| {
+            StdError::generic_err(format!(
+                "no pair registered with the factory for assets {} and {}",
+                asset_infos[0], asset_infos[1]
+            ))
+        })?;
+
+    if let Some(pair_type) = pair_type {
+        if pair_info.pair_type != pair_type {
+            return Err(StdError::generic_err(format!(
+                "pair {} is of type {}; expected {}",
+                pair_info.contract_addr, pair_info.pair_type, pair_type
+            )));
+        }
+    }
+
+    Ok(pair_info.contract_addr)
+}
+
+/// Assert that the pair is the one registered with the Astroport factory for its assets. Otherwise,
+/// a contract posing as a pair could lie about its assets and liquidity token
+fn assert_registered_pair(
+    querier: &QuerierWrapper,
+    factory_addr: &Addr,
+    pair_addr: &Addr,
+    pair_info: &PairInfo,
+) -> StdResult<()> {
+    match query_factory_pair(querier, factory_addr, &pair_info.asset_infos) {
+        Ok(registered_pair_info) if registered_pair_info.contract_addr == *pair_addr => Ok(()),
+        _ => Err(StdError::generic_err(format!(
+            "pair {} is not registered with the factory",
+            pair_addr
+        ))),
+    }
+}
+
+/// Validate the deposits if specified; otherwise, deposit all native coins sent with the message
+fn deposits_or_funds(
+    api: &dyn Api,
+    deposits: Option<AssetListUnchecked>,
+    funds: &[Coin],
+) -> StdResult<AssetList> {
+    match deposits {
+        Some(deposits) => deposits.check(api, None),
+        None => Ok(AssetList::from(funds)),
+    }
+}
+
+/// Validate an optional address
+fn validate_addr(api: &dyn Api, addr: Option<String>) -> StdResult<Option<Addr>> {
+    addr.map(|addr| api.addr_validate(&addr)).transpose()
+}
+
+/// Validate a list of addresses
+fn validate_addrs(api: &dyn Api, addrs: Vec<String>) -> StdResult<Vec<Addr>> {
+    addrs.iter().map(|addr| api.addr_validate(addr)).collect()
+}
+
+/// Validate an optional referrer
+fn validate_referrer(api: &dyn Api, referrer: Option<Referrer>) -> StdResult<Option<(Addr, u64)>> {
+    referrer
+        .map(|referrer| Ok((api.addr_validate(&referrer.address)?, referrer.fee_bps)))
+        .transpose()
+}
+
+/// Assert that the protocol fee does not exceed the maximum
+fn assert_fee_bps(fee_bps: u64) -> StdResult<()> {
+    if fee_bps > MAX_FEE_BPS {
+        return Err(StdError::generic_err(format!(
+            "fee cannot exceed {} bps; received {}",
+            MAX_FEE_BPS, fee_bps
+        )));
+    }
+    Ok(())
+}
+
+/// Assert that a referral fee does not exceed the maximum
+fn assert_referral_fee_bps(fee_bps: u64) -> StdResult<()> {
+    if fee_bps > MAX_REFERRAL_FEE_BPS {
+        return Err(StdError::generic_err(format!(
+            "referral fee cannot exceed {} bps; received {}",
+            MAX_REFERRAL_FEE_BPS, fee_bps
+        )));
+    }
+    Ok(())
+}
+
+/// Compute the protocol fee, and the referral fee if `referral_fee_bps` is specified, to be charged
+/// on the deposits
+fn compute_fees(
+    config: &Config,
+    deposits: &AssetList,
+    referral_fee_bps: Option<u64>,
+) -> StdResult<(AssetList, AssetList)> {
+    let referral_fee_bps = referral_fee_bps.unwrap_or(0);
+    assert_referral_fee_bps(referral_fee_bps)?;
+
+    let mut protocol_fee = AssetList::new();
+    let mut referral_fee = AssetList::new();
+    for deposit in deposits {
+        protocol_fee.add(&Asset::new(
+            deposit.info.clone(),
+            deposit.amount.multiply_ratio(config.fee_bps, 10000u64),
+        ))?;
+        referral_fee.add(&Asset::new(
+            deposit.info.clone(),
+            deposit.amount.multiply_ratio(referral_fee_bps, 10000u64),
+        ))?;
+    }
+
+    Ok((protocol_fee, referral_fee))
+}
+
+/// Deduct the protocol fee, and the referral fee if a referrer is specified, from the assets.
+/// Returns the fees, along with the messages transferring them to the fee collector and the
+/// referrer
+fn deduct_fees(
+    config: &Config,
+    assets: &mut AssetList,
+    referrer: Option<&(Addr, u64)>,
+) -> StdResult<(AssetList, AssetList, Vec<CosmosMsg>)> {
+    let (protocol_fee, referral_fee) =
+        compute_fees(config, assets, referrer.map(|(_, fee_bps)| *fee_bps))?;
+    assets.deduct_many(&protocol_fee)?;
+    assets.deduct_many(&referral_fee)?;
+
+    let mut fee_msgs = protocol_fee.transfer_msgs(&config.fee_collector)?;
+    if let Some((referrer_addr, _)) = referrer {
+        fee_msgs.extend(referral_fee.transfer_msgs(referrer_addr)?);
+    }
+
+    Ok((protocol_fee, referral_fee, fee_msgs))
+}
+
+/// Assert that the deadline, if specified, has not passed
+fn assert_deadline(block: &BlockInfo, deadline: Option<Expiration>) -> StdResult<()> {
+    if let Some(deadline) = deadline {
+        if deadline.is_expired(block) {
+            return Err(StdError::generic_err(format!(
+                "deadline has passed; {}",
+                deadline
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Assert the given Astroport pair is of the XYK or stableswap type
 fn assert_pair_type(pair_type: &PairType) -> StdResult<()> {
-     match pair_type {
-         PairType::Xyk {} => Ok(()),
-         pt => Err(StdError::generic_err(format!("unsupported pair type: {}", pt))),
-     }
+    match pair_type {
+        PairType::Xyk {} | PairType::Stable {} => Ok(()),
+        pt => Err(StdError::generic_err(format!(
+            "unsupported pair type: {}",
+            pt
+        ))),
+    }
 }
 
 /// Assert each of the deposited asset must be contained by the Astroport pair
 fn assert_deposit_types(pair_assets: &AssetList, deposits: &AssetList) -> StdResult<()> {
     for deposit in deposits {
-        if pair_assets.find(&deposit.info).is_none() {
-            return Err(StdError::generic_err(
-                format!("pair does not contain asset {}", deposit.info)
-            ));
+        assert_asset_type(pair_assets, &deposit.info)?;
+    }
+    Ok(())
+}
+
+/// Assert the asset must be contained by the Astroport pair
+fn assert_asset_type(pair_assets: &AssetList, asset_info: &AssetInfo) -> StdResult<()> {
+    if pair_assets.find(asset_info).is_none() {
+        return Err(StdError::generic_err(format!(
+            "pair does not contain asset {}",
+            asset_info
+        )));
+    }
+    Ok(())
+}
+
+/// Assert the CW20 token sent to the contract is the liquidity token of the Astroport pair
+fn assert_liquidity_token(liquidity_token_addr: &Addr, sent_info: &AssetInfo) -> StdResult<()> {
+    if *sent_info != AssetInfo::cw20(liquidity_token_addr.clone()) {
+        return Err(StdError::generic_err(format!(
+            "invalid liquidity token: expected cw20:{}, received {}",
+            liquidity_token_addr, sent_info
+        )));
+    }
+    Ok(())
+}
+
+/// Assert that deposits must contain either exactly one or two assets
+fn assert_deposit_number(deposits: &AssetList) -> StdResult<()> {
+    if !(1..=2).contains(&deposits.len()) {
+        return Err(StdError::generic_err(format!(
+            "must deposit exactly 1 or 2 assets; received {}",
+            deposits.len()
+        )));
+    }
+    Ok(())
+}
+
+/// The share of the commission sent to the maker, in basis points. The pair only charges the maker
+/// fee if the factory has a fee address
+fn maker_fee_bps(fee_info: &FeeInfoResponse) -> u64 {
+    if fee_info.fee_address.is_some() {
+        fee_info.maker_fee_bps.into()
+    } else {
+        0
+    }
+}
+
+/// Compute the assets to provide to the pair, and deduct them from the available assets
+///
+/// For XYK pairs, only the amounts needed to mint liquidity tokens at the pool's current ratio are
+/// provided, and the excess is left in the available assets to be refunded, rather than donated to
+/// the pool. Stableswap pairs mint liquidity tokens in proportion to the increase of the invariant
+/// for deposits in any ratio, so nothing is donated, and all available assets are provided. See
+/// `compute_stable_provide`
+fn compute_assets_to_provide(
+    pair_info: &PairInfo,
+    pool_info: &PoolResponse,
+    available_assets: &mut AssetList,
+) -> StdResult<AssetList> {
+    let mut assets_to_provide = available_assets.clone();
+
+    if pair_info.pair_type == (PairType::Xyk {}) {
+        let pool_assets = AssetList::from_legacy(&pool_info.assets);
+        let find_amount = |info: &AssetInfo| {
+            available_assets
+                .find(info)
+                .map(|asset| asset.amount)
+                .unwrap_or_else(Uint128::zero)
+        };
+
+        let (assets_used, _) = compute_xyk_provide(
+            [
+                find_amount(&pool_assets[0].info),
+                find_amount(&pool_assets[1].info),
+            ],
+            [pool_assets[0].amount, pool_assets[1].amount],
+            pool_info.total_share,
+        )?;
+
+        assets_to_provide = AssetList::from(
+            available_assets
+                .to_vec()
+                .into_iter()
+                .map(|asset| {
+                    let amount = if asset.info == pool_assets[0].info {
+                        assets_used[0]
+                    } else {
+                        assets_used[1]
+                    };
+                    Asset::new(asset.info, amount)
+                })
+                .collect::<Vec<_>>(),
+        );
+        assets_to_provide.purge();
+    }
+
+    for asset in &assets_to_provide {
+        available_assets.deduct(asset)?;
+    }
+
+    Ok(assets_to_provide)
+}
+
+/// Assert that a route is valid for entering a pool with the given deposit, i.e. it is not empty,
+/// each operation starts from the asset the previous one ends in, the first one starts from the
+/// deposited asset, and the last one ends in one of the pool's assets. Returns the asset the route
+/// ends in
+fn assert_route(
+    route: &[SwapOperation],
+    deposit_info: &AssetInfo,
+    pool_assets: &AssetList,
+) -> StdResult<AssetInfo> {
+    if route.is_empty() {
+        return Err(StdError::generic_err("route must not be empty"));
+    }
+
+    let mut current_info = deposit_info.clone();
+    for operation in route {
+        let (offer_info, ask_info) = parse_swap_operation(operation);
+        if offer_info != current_info {
+            return Err(StdError::generic_err(format!(
+                "invalid route: expecting swap from {}, found {}",
+                current_info, offer_info
+            )));
+        }
+        current_info = ask_info;
+    }
+
+    // The route must not lead back to the deposited asset. Otherwise, the amount received from the
+    // router, measured as the increase in balance, would include the deposit itself
+    if current_info == *deposit_info {
+        return Err(StdError::generic_err(format!(
+            "invalid route: must not end in the deposited asset {}",
+            current_info
+        )));
+    }
+    if pool_assets.find(&current_info).is_none() {
+        return Err(StdError::generic_err(format!(
+            "invalid route: {} is not in the pool",
+            current_info
+        )));
+    }
+
+    Ok(current_info)
+}
+
+/// Assert that a route is provided for migrating liquidity if and only if the source and target
+/// pools share no asset, in which case it must start from one of the source pool's assets, and be
+/// valid for entering the target pool with it. See `assert_route`
+fn assert_migration_route(
+    source_pool_assets: &AssetList,
+    target_pool_assets: &AssetList,
+    route: Option<&[SwapOperation]>,
+) -> StdResult<()> {
+    let share_asset = source_pool_assets
+        .into_iter()
+        .any(|asset| target_pool_assets.find(&asset.info).is_some());
+
+    match route {
+        None if share_asset => Ok(()),
+        None => Err(StdError::generic_err(
+            "pairs do not share any asset; route must be provided",
+        )),
+        Some(_) if share_asset => Err(StdError::generic_err(
+            "route can only be provided if the pairs do not share any asset",
+        )),
+        Some(route) => {
+            let (start_info, _) = route
+                .first()
+                .map(parse_swap_operation)
+                .ok_or_else(|| StdError::generic_err("route must not be empty"))?;
+            if source_pool_assets.find(&start_info).is_none() {
+                return Err(StdError::generic_err(format!(
+                    "invalid route: {} is not in the source pool",
+                    start_info
+                )));
+            }
+            assert_route(route, &start_info, target_pool_assets)?;
+            Ok(())
         }
     }
+}
+
+/// Find the withdrawn asset to be swapped into the other one in the source pool when migrating
+/// liquidity: the one the route does not start from if a route is provided, or otherwise the one
+/// the target pool does not contain, if any
+fn find_migration_offer_asset(
+    withdrawn_assets: &AssetList,
+    target_pool_assets: &AssetList,
+    route: Option<&[SwapOperation]>,
+) -> StdResult<Option<AssetInfo>> {
+    match route.and_then(|route| route.first()) {
+        Some(operation) => {
+            let (start_info, _) = parse_swap_operation(operation);
+            Ok(withdrawn_assets
+                .into_iter()
+                .map(|asset| asset.info.clone())
+                .find(|info| *info != start_info))
+        }
+        None => find_unshared_asset(withdrawn_assets, target_pool_assets),
+    }
+}
+
+/// Find the asset of the source pool that is not contained by the target pool, if any. The two pools
+/// must share at least one asset
+fn find_unshared_asset(
+    source_pool_assets: &AssetList,
+    target_pool_assets: &AssetList,
+) -> StdResult<Option<AssetInfo>> {
+    let unshared_infos = source_pool_assets
+        .to_vec()
+        .into_iter()
+        .map(|asset| asset.info)
+        .filter(|info| target_pool_assets.find(info).is_none())
+        .collect::<Vec<_>>();
+
+    match unshared_infos.len() {
+        0 => Ok(None),
+        1 => Ok(Some(unshared_infos[0].clone())),
+        _ => Err(StdError::generic_err("pairs do not share any asset")),
+    }
+}
+
+/// Assert that the initial price is not specified, as the pool is not empty
+fn assert_no_initial_price(initial_price: Option<Decimal>) -> StdResult<()> {
+    if initial_price.is_some() {
+        return Err(StdError::generic_err(
+            "initial price can only be specified for empty pools",
+        ));
+    }
     Ok(())
 }
 
-/// Assert that deposits must contain either exactly one or two assets
-fn assert_deposit_number(deposits: &AssetList) -> StdResult<()> {
-    if !(1..=2).contains(&deposits.len()) {
-        return Err(StdError::generic_err(
-            format!("must deposit exactly 1 or 2 assets; received {}", deposits.len())
-        ));
-    }
-    Ok(())
+/// Compute the amounts of assets to provide as the initial liquidity of an empty pool, such that
+/// their ratio matches the initial price. Returns the assets to provide and the initial price
+///
+/// `initial_price` is the amount of the pair's second asset per unit of its first asset. Of the
+/// asset in excess of this ratio, only the matching amount is provided
+fn compute_initial_liquidity(
+    pool_assets: &AssetList,
+    deposits: &AssetList,
+    initial_price: Option<Decimal>,
+) -> StdResult<(AssetList, Decimal)> {
+    let initial_price = initial_price
+        .ok_or_else(|| StdError::generic_err("pool is empty; initial price must be specified"))?;
+    if initial_price.is_zero() {
+        return Err(StdError::generic_err(
+            "initial price must be greater than zero",
+        ));
+    }
+
+    let deposit_0 = deposits
+        .find(&pool_assets[0].info)
+        .map(|asset| asset.amount);
+    let deposit_1 = deposits
+        .find(&pool_assets[1].info)
+        .map(|asset| asset.amount);
+    let (deposit_0, deposit_1) = match (deposit_0, deposit_1) {
+        (Some(deposit_0), Some(deposit_1)) => (deposit_0, deposit_1),
+        _ => {
+            return Err(StdError::generic_err(
+                "pool is empty; must deposit both assets",
+            ))
+        }
+    };
+
+    let matching_1 =
+        deposit_0.multiply_ratio(initial_price.numerator(), initial_price.denominator());
+    let (amount_0, amount_1) = if matching_1 <= deposit_1 {
+        (deposit_0, matching_1)
+    } else {
+        let matching_0 =
+            deposit_1.multiply_ratio(initial_price.denominator(), initial_price.numerator());
+        (matching_0, deposit_1)
+    };
+
+    if amount_0.is_zero() || amount_1.is_zero() {
+        return Err(StdError::generic_err(
+            "deposits are too small for the initial price",
+        ));
+    }
+
+    let assets_to_provide = AssetList::from(vec![
+        Asset::new(pool_assets[0].info.clone(), amount_0),
+        Asset::new(pool_assets[1].info.clone(), amount_1),
+    ]);
+
+    Ok((assets_to_provide, initial_price))
+}
+
+/// The pool's spot price of the ask asset, in units of the offer asset. This is how Astroport
+/// expresses `belief_price`
+///
+/// For XYK pairs (`amp` is `None`), this is the ratio of the pool's depths. For stableswap pairs,
+/// the depths are not in the ratio of the price, so we use the marginal price under the StableSwap
+/// invariant instead
+fn compute_spot_price(
+    pool_assets: &AssetList,
+    offer_info: &AssetInfo,
+    amp: Option<u64>,
+) -> StdResult<Decimal> {
+    let (offer_pool, ask_pool) = if *offer_info == pool_assets[0].info {
+        (pool_assets[0].amount, pool_assets[1].amount)
+    } else {
+        (pool_assets[1].amount, pool_assets[0].amount)
+    };
+    match amp {
+        Some(amp) => compute_stable_spot_price(amp, offer_pool, ask_pool),
+        None => Ok(Decimal::from_ratio(offer_pool, ask_pool)),
+    }
+}
+
+/// Query the amplification coefficient if the pair is of stableswap type; return `None` otherwise
+fn query_amp(querier: &QuerierWrapper, pair_info: &PairInfo) -> StdResult<Option<u64>> {
+    match pair_info.pair_type {
+        PairType::Stable {} => Ok(Some(query_stable_amp(querier, &pair_info.contract_addr)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Compute the optimal swap for entering a non-empty pool with the available assets, and build the
+/// submsgs to execute it. Returns the submsgs, and attributes describing the solver's diagnostics
+///
+/// If no swap is needed (i.e. offer amount is calculated to be zero), we simply provide the
+/// liquidity with the given slippage tolerance, and deduct the assets to be provided from the list
+/// of available assets. Otherwise, the slippage tolerance is applied in reply, after the swap
+///
+/// If a swap is needed, we execute the swap, and deduct the offer asset from the list of available
+/// assets. If only `max_spread` is specified, the swap's spread is measured against the pool's spot
+/// price
+#[allow(clippy::too_many_arguments)]
+fn build_enter_submsgs(
+    querier: &QuerierWrapper,
+    storage: &dyn Storage,
+    pair_info: &PairInfo,
+    pool_info: &PoolResponse,
+    available_assets: &mut AssetList,
+    auto_stake_receiver: Option<&Addr>,
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
+    provide_slippage_tolerance: Option<Decimal>,
+) -> StdResult<(Vec<SubMsg>, Vec<Attribute>)> {
+    let pool_assets = AssetList::from_legacy(&pool_info.assets);
+
+    let config = CONFIG.load(storage)?;
+    let fee_info = query_fee_info(querier, &config.factory, &pair_info.pair_type)?;
+    let amp = query_amp(querier, pair_info)?;
+    let (offer_asset, solution) = compute_offer_asset(
+        &pool_assets,
+        pool_info.total_share,
+        available_assets,
+        &fee_info,
+        amp,
+    )?;
+
+    let submsgs = if offer_asset.amount.is_zero() {
+        let mut assets_to_provide =
+            compute_assets_to_provide(pair_info, pool_info, available_assets)?;
+        build_provide_liquidity_submsgs(
+            &pair_info.contract_addr,
+            &mut assets_to_provide,
+            auto_stake_receiver,
+            provide_slippage_tolerance,
+        )?
+    } else {
+        let belief_price = match (belief_price, max_spread) {
+            (None, Some(_)) => Some(compute_spot_price(&pool_assets, &offer_asset.info, amp)?),
+            _ => belief_price,
+        };
+        build_swap_submsgs(
+            &pair_info.contract_addr,
+            available_assets,
+            &offer_asset,
+            belief_price,
+            max_spread,
+            1,
+        )?
+    };
+
+    let attrs = vec![
+        attr("solver_iterations", solution.iterations.to_string()),
+        attr("solver_residual", solution.residual.to_string()),
+        attr("solver_converged", solution.converged.to_string()),
+    ];
+
+    Ok((submsgs, attrs))
 }
 
 /// Compute the maximal amount of asset to swap such that providing the two assets afterwards will
 /// return the greatest amount of liquidity tokens
 ///
-/// For details of the math involved, see `../../docs/astrozap.pdf`
-fn compute_offer_asset(pool_assets: &AssetList, user_assets: &AssetList) -> StdResult<Asset> {
+/// For XYK pairs (`amp` is `None`), we solve a quadratic equation; for details of the math involved,
+/// see `../../docs/astrozap.pdf`. For stableswap pairs, we solve the equation numerically under
+/// the StableSwap invariant
+///
+/// Returns the offer asset, along with the solution containing the solver's diagnostics
+fn compute_offer_asset(
+    pool_assets: &AssetList,
+    total_share: Uint128,
+    user_assets: &AssetList,
+    fee_info: &FeeInfoResponse,
+    amp: Option<u64>,
+) -> StdResult<(Asset, Solution)> {
     let a_pool = pool_assets[0].clone();
     let b_pool = pool_assets[1].clone();
 
@@ -165,89 +1820,337 @@ fn compute_offer_asset(pool_assets: &AssetList, user_assets: &AssetList) -> StdR
     let share_a = Decimal256::from_ratio(a_user.amount, a_pool.amount);
     let share_b = Decimal256::from_ratio(b_user.amount, b_pool.amount);
 
-    let q = if share_a > share_b {
-        Quadratic::from_asset_amounts(
-            &a_user.amount.u128().into(),
-            &a_pool.amount.u128().into(),
-            &b_user.amount.u128().into(),
-            &b_pool.amount.u128().into(),
-        )
+    let (offer_user, offer_pool, ask_user, ask_pool) = if share_a > share_b {
+        (&a_user, &a_pool, &b_user, &b_pool)
     } else {
-        Quadratic::from_asset_amounts(
-            &b_user.amount.u128().into(),
-            &b_pool.amount.u128().into(),
-            &a_user.amount.u128().into(),
-            &a_pool.amount.u128().into(),
-        )
+        (&b_user, &b_pool, &a_user, &a_pool)
     };
 
-    // Solve quadratic equation to find out the swap amount
-    //
-    // Here we use 0 as the initial value. It is possible to find a better guess, but in experience
-    // the equation usually converges in 4 - 5 iterations even starting with 0, so I'll go with this
-    let offer_amount = bigint_to_uint128(&q.solve())?;
+    let solution = match amp {
+        Some(amp) => StableSwap::from_asset_amounts(
+            offer_user.amount,
+            offer_pool.amount,
+            ask_user.amount,
+            ask_pool.amount,
+            amp,
+            fee_info.total_fee_bps.into(),
+            maker_fee_bps(fee_info),
+        )
+        .solve()?,
+        None => {
+            let quadratic = Quadratic::from_asset_amounts(
+                offer_user.amount,
+                offer_pool.amount,
+                ask_user.amount,
+                ask_pool.amount,
+                fee_info.total_fee_bps.into(),
+            );
+            let solution = quadratic.solve_exact()?;
 
-    let offer_asset_info = if share_a > share_b {
-        a_pool.info
-    } else {
-        b_pool.info
+            // The root is the real-valued optimum, but the pair rounds down both the swap and the
+            // liquidity tokens minted. We search the root's neighbourhood for the integer offer
+            // amount that actually mints the most shares
+            let outcome = XykPair {
+                offer_user: offer_user.amount,
+                offer_pool: offer_pool.amount,
+                ask_user: ask_user.amount,
+                ask_pool: ask_pool.amount,
+                total_share,
+                commission_rate_bps: fee_info.total_fee_bps.into(),
+                maker_fee_bps: maker_fee_bps(fee_info),
+            }
+            .refine(solution.root)?;
+
+            Solution {
+                root: outcome.offer_amount,
+                residual: quadratic.compute_value(outcome.offer_amount.into())?,
+                ..solution
+            }
+        }
     };
 
-    Ok(Asset::new(offer_asset_info, offer_amount))
+    Ok((Asset::new(offer_pool.info.clone(), solution.root), solution))
+}
+
+/// Start a new zap, caching its data under a new zap id. Fails if another zap is in progress, which
+/// can only happen if a contract called in the middle of a zap (e.g. a malicious CW20 token pulled
+/// as a deposit) calls back into this contract
+fn start_zap(storage: &mut dyn Storage, cache: &CacheData) -> StdResult<()> {
+    let zap_id = next_zap_id(storage)?;
+    CACHE.save(storage, zap_id.into(), cache)
+}
+
+/// Start a new `Exit`, the same way as `start_zap`
+fn start_exit(storage: &mut dyn Storage, cache: &ExitCacheData) -> StdResult<()> {
+    let zap_id = next_zap_id(storage)?;
+    EXIT_CACHE.save(storage, zap_id.into(), cache)
+}
+
+/// Assert that no zap, including an `Exit`, is in progress, and save a new zap id
+fn next_zap_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let zap_id = ZAP_ID.may_load(storage)?.unwrap_or(0);
+    if CACHE.has(storage, zap_id.into()) || EXIT_CACHE.has(storage, zap_id.into()) {
+        return Err(StdError::generic_err(format!(
+            "zap {} is already in progress",
+            zap_id
+        )));
+    }
+
+    let zap_id = zap_id + 1;
+    ZAP_ID.save(storage, &zap_id)?;
+    Ok(zap_id)
+}
+
+/// Load the cached data of the zap in progress
+fn load_cache(storage: &dyn Storage) -> StdResult<CacheData> {
+    let zap_id = ZAP_ID.load(storage)?;
+    CACHE.load(storage, zap_id.into())
+}
+
+/// Update the cached data of the zap in progress
+fn save_cache(storage: &mut dyn Storage, cache: &CacheData) -> StdResult<()> {
+    let zap_id = ZAP_ID.load(storage)?;
+    CACHE.save(storage, zap_id.into(), cache)
+}
+
+/// Clear the cached data of the zap in progress, once the zap is done
+fn end_zap(storage: &mut dyn Storage) -> StdResult<()> {
+    let zap_id = ZAP_ID.load(storage)?;
+    CACHE.remove(storage, zap_id.into());
+    Ok(())
+}
+
+/// Load the cached data of the `Exit` in progress
+fn load_exit_cache(storage: &dyn Storage) -> StdResult<ExitCacheData> {
+    let zap_id = ZAP_ID.load(storage)?;
+    EXIT_CACHE.load(storage, zap_id.into())
+}
+
+/// Update the cached data of the `Exit` in progress
+fn save_exit_cache(storage: &mut dyn Storage, cache: &ExitCacheData) -> StdResult<()> {
+    let zap_id = ZAP_ID.load(storage)?;
+    EXIT_CACHE.save(storage, zap_id.into(), cache)
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, reply: Reply) -> StdResult<Response> {
+pub fn reply(deps: DepsMut, env: Env, reply: Reply) -> StdResult<Response> {
     match reply.id {
         1 => after_swap(deps, unwrap_reply(reply)?),
         2 => after_provide_liquidity(deps, unwrap_reply(reply)?),
+        3 => after_withdraw_liquidity(deps, unwrap_reply(reply)?),
+        4 => after_exit_swap(deps, unwrap_reply(reply)?),
+        5 => after_route_swap(deps, env),
+        6 => after_migrate_withdraw(deps, env, unwrap_reply(reply)?),
+        7 => after_migrate_swap(deps, env, unwrap_reply(reply)?),
+        8 => after_claim_rewards(deps, env),
         id => Err(StdError::generic_err(format!("invalid reply id: {}", id))),
     }
 }
 
-fn after_swap(deps: DepsMut, res: SubMsgExecutionResponse) -> StdResult<Response> {
-    let event = res
-        .events
-        .iter()
-        .find(|event| event_contains_attr(event, "action", "swap"))
-        .ok_or_else(|| StdError::generic_err("cannot find `swap` event"))?;
+/// Build the submsgs for entering the cached pair with the cached assets, for when these assets only
+/// become available in a reply. The pool may have changed since the message was executed (e.g. if
+/// the router's route passes through the same pair), so we query it again
+fn build_cached_enter_submsgs(
+    querier: &QuerierWrapper,
+    storage: &dyn Storage,
+    cache: &mut CacheData,
+) -> StdResult<(Vec<SubMsg>, Vec<Attribute>)> {
+    let pair_info = query_pair(querier, &cache.pair_addr)?;
+    let pool_info = query_pool(querier, &cache.pair_addr)?;
+    let auto_stake_receiver = if cache.auto_stake {
+        Some(cache.recipient_addr.clone())
+    } else {
+        None
+    };
+    build_enter_submsgs(
+        querier,
+        storage,
+        &pair_info,
+        &pool_info,
+        &mut cache.assets,
+        auto_stake_receiver.as_ref(),
+        None,
+        None,
+        cache.provide_slippage_tolerance,
+    )
+}
 
-    let ask_asset_str = event
-        .attributes
-        .iter()
-        .cloned()
-        .find(|attr| attr.key == "ask_asset")
-        .ok_or_else(|| StdError::generic_err("cannot find `ask_asset` attribute"))?
-        .value;
+fn after_route_swap(deps: DepsMut, env: Env) -> StdResult<Response> {
+    let mut cache = load_cache(deps.storage)?;
+    let intermediate_asset = cache
+        .intermediate_asset
+        .take()
+        .ok_or_else(|| StdError::generic_err("intermediate asset not found in cache"))?;
 
-    let return_amount_str = event
-        .attributes
-        .iter()
-        .cloned()
-        .find(|attr| attr.key == "return_amount")
-        .ok_or_else(|| StdError::generic_err("cannot find `return_amount` attribute"))?
-        .value;
-
-    // If `ask_asset_str` can be validated as a Terra address, then we assume it is a CW20;
-    // otherwise we assume it is a native coin
-    let returned_info = if let Ok(contract_addr) = deps.api.addr_validate(&ask_asset_str) {
-        AssetInfo::cw20(contract_addr)
+    // The amount received from the router is the increase in the contract's balance
+    let balance = intermediate_asset
+        .info
+        .query_balance(&deps.querier, &env.contract.address)?;
+    let received_asset = Asset::new(
+        intermediate_asset.info,
+        balance.checked_sub(intermediate_asset.amount)?,
+    );
+    cache.assets.add(&received_asset)?;
+
+    // Swap through the next route if there is one. Otherwise, continue the same way as `Enter` with
+    // the assets received as the deposits
+    let (submsgs, attrs) =
+        build_cached_route_or_enter_submsgs(&deps.querier, deps.storage, &env, &mut cache)?;
+    save_cache(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/reply/after_route_swap")
+        .add_attribute("asset_received", received_asset.to_string())
+        .add_attributes(attrs))
+}
+
+fn after_migrate_withdraw(
+    deps: DepsMut,
+    env: Env,
+    res: SubMsgExecutionResponse,
+) -> StdResult<Response> {
+    let withdrawn_assets = parse_withdraw_liquidity_event(deps.api, &res)?;
+
+    let mut cache = load_cache(deps.storage)?;
+    cache.assets = withdrawn_assets.clone();
+
+    let source_pair_addr = cache
+        .source_pair_addr
+        .clone()
+        .ok_or_else(|| StdError::generic_err("source pair not found in cache"))?;
+    let target_pool_info = query_pool(&deps.querier, &cache.pair_addr)?;
+    let target_pool_assets = AssetList::from_legacy(&target_pool_info.assets);
+
+    // If the target pair does not contain one of the withdrawn assets, or if the route does not
+    // start from it, we swap it into the other one in the source pair, and continue once the swap
+    // is done. Otherwise, we swap through the router, or enter the target pair, right away
+    let offer_info = find_migration_offer_asset(
+        &withdrawn_assets,
+        &target_pool_assets,
+        cache.routes.first().map(|route| route.as_slice()),
+    )?;
+    let offer_asset = offer_info.and_then(|info| withdrawn_assets.find(&info).cloned());
+
+    let (submsgs, attrs) = if let Some(offer_asset) = offer_asset {
+        let submsgs = build_swap_submsgs(
+            &source_pair_addr,
+            &mut cache.assets,
+            &offer_asset,
+            None,
+            None,
+            7,
+        )?;
+        (submsgs, vec![])
     } else {
-        AssetInfo::native(ask_asset_str)
+        build_cached_route_or_enter_submsgs(&deps.querier, deps.storage, &env, &mut cache)?
     };
-    let returned_amount = Uint128::from_str(&return_amount_str)?;
-    let returned_asset = Asset::new(returned_info, returned_amount);
+    save_cache(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/reply/after_migrate_withdraw")
+        .add_attribute("assets_withdrawn", withdrawn_assets.to_string())
+        .add_attributes(attrs))
+}
+
+fn after_migrate_swap(
+    deps: DepsMut,
+    env: Env,
+    res: SubMsgExecutionResponse,
+) -> StdResult<Response> {
+    let returned_asset = parse_swap_event(deps.api, &res)?;
+
+    let mut cache = load_cache(deps.storage)?;
+    cache.assets.add(&returned_asset)?;
+
+    let (submsgs, attrs) =
+        build_cached_route_or_enter_submsgs(&deps.querier, deps.storage, &env, &mut cache)?;
+    save_cache(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/reply/after_migrate_swap")
+        .add_attribute("asset_returned", returned_asset.to_string())
+        .add_attributes(attrs))
+}
+
+fn after_claim_rewards(deps: DepsMut, env: Env) -> StdResult<Response> {
+    let mut cache = load_cache(deps.storage)?;
+
+    // The rewards have been received by now. Swap those not in the pair through the router, then
+    // enter the pair with them
+    let (submsgs, attrs) =
+        build_cached_route_or_enter_submsgs(&deps.querier, deps.storage, &env, &mut cache)?;
+    save_cache(deps.storage, &cache)?;
+
+    Ok(Response::new()
+        .add_submessages(submsgs)
+        .add_attribute("action", "astrozap/reply/after_claim_rewards")
+        .add_attributes(attrs))
+}
 
-    let mut cache = CACHE.load(deps.storage)?;
+/// Build the submsgs for swapping the cached asset the next cached route starts from through the
+/// router, if there are routes left in the cache. Otherwise, build the submsgs for entering the
+/// cached pair with the cached assets
+fn build_cached_route_or_enter_submsgs(
+    querier: &QuerierWrapper,
+    storage: &dyn Storage,
+    env: &Env,
+    cache: &mut CacheData,
+) -> StdResult<(Vec<SubMsg>, Vec<Attribute>)> {
+    if cache.routes.is_empty() {
+        return build_cached_enter_submsgs(querier, storage, cache);
+    }
+
+    let route = cache.routes.remove(0);
+    let (start_info, _) = parse_swap_operation(&route[0]);
+    let (_, end_info) = parse_swap_operation(&route[route.len() - 1]);
+
+    let offer_asset = cache
+        .assets
+        .find(&start_info)
+        .cloned()
+        .ok_or_else(|| StdError::generic_err(format!("{} not found in cache", start_info)))?;
+    cache.assets.deduct(&offer_asset)?;
+
+    // See `enter_via_router` for why we record the balance of the asset to be received
+    let end_balance = end_info.query_balance(querier, &env.contract.address)?;
+    cache.intermediate_asset = Some(Asset::new(end_info, end_balance));
+
+    let config = CONFIG.load(storage)?;
+    let submsgs = build_route_swap_submsgs(&config.router, &offer_asset, route)?;
+    Ok((submsgs, vec![attr("asset_routed", offer_asset.to_string())]))
+}
+
+fn after_swap(deps: DepsMut, res: SubMsgExecutionResponse) -> StdResult<Response> {
+    let returned_asset = parse_swap_event(deps.api, &res)?;
+
+    let mut cache = load_cache(deps.storage)?;
     cache.assets.add(&returned_asset)?;
 
     // Build messages to provide assets to the DEX pool, and deduct the assets to be provided from
     // the list of available assets
+    //
+    // The swap has already been executed at this point, so the pool is queried in its state after
+    // the swap
+    let pair_info = query_pair(&deps.querier, &cache.pair_addr)?;
+    let pool_info = query_pool(&deps.querier, &cache.pair_addr)?;
+    let mut assets_to_provide =
+        compute_assets_to_provide(&pair_info, &pool_info, &mut cache.assets)?;
+
+    let auto_stake_receiver = if cache.auto_stake {
+        Some(cache.recipient_addr.clone())
+    } else {
+        None
+    };
     let submsgs = build_provide_liquidity_submsgs(
         &cache.pair_addr,
-        &mut cache.assets,
+        &mut assets_to_provide,
+        auto_stake_receiver.as_ref(),
+        cache.provide_slippage_tolerance,
     )?;
-    CACHE.save(deps.storage, &cache)?;
+    save_cache(deps.storage, &cache)?;
 
     Ok(Response::new()
         .add_submessages(submsgs)
@@ -256,64 +2159,296 @@ fn after_swap(deps: DepsMut, res: SubMsgExecutionResponse) -> StdResult<Response
 }
 
 fn after_provide_liquidity(deps: DepsMut, res: SubMsgExecutionResponse) -> StdResult<Response> {
-    let event = res
-        .events
-        .iter()
-        .find(|event| event_contains_attr(event, "action", "provide_liquidity"))
-        .ok_or_else(|| StdError::generic_err("cannot find `provide_liquidity` event"))?;
-
-    let share_str = event
-        .attributes
-        .iter()
-        .cloned()
-        .find(|attr| attr.key == "share")
-        .ok_or_else(|| StdError::generic_err("cannot find `share` attribute"))?
-        .value;
-
+    let event = find_event(&res, "action", "provide_liquidity")?;
+    let share_str = read_attribute(event, "share")?;
     let share_amount = Uint128::from_str(&share_str)?;
 
-    let mut cache = CACHE.load(deps.storage)?;
-    CACHE.remove(deps.storage);
+    let mut cache = load_cache(deps.storage)?;
 
     if let Some(minimum_received) = cache.minimum_received {
         if share_amount < minimum_received {
-            return Err(StdError::generic_err(
-                format!("too little received! minimum: {}, received {}", minimum_received, share_amount)
-            ));
+            return Err(StdError::generic_err(format!(
+                "too little received! minimum: {}, received {}",
+                minimum_received, share_amount
+            )));
+        }
+    }
+
+    // If the liquidity tokens are auto-staked, the pair deposits them into the generator on behalf
+    // of the recipient, so the contract does not hold any of them. Otherwise, they are minted to the
+    // contract and need to be transferred to the recipient along with any leftover asset
+    let shares_minted = Asset::cw20(cache.liquidity_token_addr.clone(), share_amount);
+    if !cache.auto_stake {
+        cache.assets.add(&shares_minted)?;
+    }
+
+    // When compounding, the recipient is this contract itself. Instead of transferring the leftover
+    // assets, we keep them to be compounded by the next `Compound`
+    let transfer_msgs = if cache.compounding {
+        let mut rewards = REWARDS
+            .may_load(deps.storage, &cache.liquidity_token_addr)?
+            .unwrap_or_default();
+        rewards.add_many(&cache.assets)?;
+        rewards.purge();
+        REWARDS.save(deps.storage, &cache.liquidity_token_addr, &rewards)?;
+        vec![]
+    } else {
+        cache.assets.transfer_msgs(&cache.recipient_addr)?
+    };
+
+    let res = Response::new()
+        .add_messages(transfer_msgs)
+        .add_attribute("action", "astrozap/reply/after_providing_liquidity")
+        .add_attribute("shares_minted", shares_minted.to_string());
+
+    // If there are more pairs to be entered as part of `EnterMany`, move on to the next one.
+    // Otherwise, we are done and the cache is cleared
+    if cache.pending.is_empty() {
+        end_zap(deps.storage)?;
+        return Ok(res);
+    }
+
+    let next = cache.pending.remove(0);
+    cache.pair_addr = next.pair_addr;
+    cache.liquidity_token_addr = next.liquidity_token_addr;
+    cache.assets = next.assets;
+    cache.minimum_received = next.minimum_received;
+
+    let (submsgs, attrs) = build_cached_enter_submsgs(&deps.querier, deps.storage, &mut cache)?;
+    save_cache(deps.storage, &cache)?;
+
+    Ok(res
+        .add_submessages(submsgs)
+        .add_attribute("next_pair", cache.pair_addr.to_string())
+        .add_attribute("pairs_pending", cache.pending.len().to_string())
+        .add_attributes(attrs))
+}
+
+fn after_withdraw_liquidity(deps: DepsMut, res: SubMsgExecutionResponse) -> StdResult<Response> {
+    let withdrawn_assets = parse_withdraw_liquidity_event(deps.api, &res)?;
+
+    let mut cache = load_exit_cache(deps.storage)?;
+    cache.assets = withdrawn_assets.clone();
+
+    // Find the withdrawn asset that is not the one the user wants to receive. If there is none (i.e.
+    // the withdrawn amount is zero), we skip the swap and refund the user right away
+    let offer_asset = withdrawn_assets
+        .to_vec()
+        .into_iter()
+        .find(|asset| asset.info != cache.ask_asset_info);
+
+    let res = if let Some(offer_asset) = offer_asset {
+        let submsgs = build_swap_submsgs(
+            &cache.pair_addr,
+            &mut cache.assets,
+            &offer_asset,
+            None,
+            None,
+            4,
+        )?;
+        save_exit_cache(deps.storage, &cache)?;
+        Response::new().add_submessages(submsgs)
+    } else {
+        refund_exit(deps.storage, cache)?
+    };
+
+    Ok(res
+        .add_attribute("action", "astrozap/reply/after_withdraw_liquidity")
+        .add_attribute("assets_withdrawn", withdrawn_assets.to_string()))
+}
+
+fn after_exit_swap(deps: DepsMut, res: SubMsgExecutionResponse) -> StdResult<Response> {
+    let returned_asset = parse_swap_event(deps.api, &res)?;
+
+    let mut cache = load_exit_cache(deps.storage)?;
+    cache.assets.add(&returned_asset)?;
+
+    Ok(refund_exit(deps.storage, cache)?
+        .add_attribute("action", "astrozap/reply/after_exit_swap")
+        .add_attribute("asset_returned", returned_asset.to_string()))
+}
+
+/// Check the amount of ask asset available against the user's minimum received, and transfer it to
+/// the user. This is the last step of an `Exit`, so the cache is cleared
+fn refund_exit(storage: &mut dyn Storage, cache: ExitCacheData) -> StdResult<Response> {
+    let zap_id = ZAP_ID.load(storage)?;
+    EXIT_CACHE.remove(storage, zap_id.into());
+
+    let received_amount = cache
+        .assets
+        .find(&cache.ask_asset_info)
+        .map(|asset| asset.amount)
+        .unwrap_or_else(Uint128::zero);
+
+    if let Some(minimum_received) = cache.minimum_received {
+        if received_amount < minimum_received {
+            return Err(StdError::generic_err(format!(
+                "too little received! minimum: {}, received {}",
+                minimum_received, received_amount
+            )));
         }
     }
 
-    let shares_minted = Asset::cw20(cache.liquidity_token_addr, share_amount);
-    cache.assets.add(&shares_minted)?;
+    let asset_received = Asset::new(cache.ask_asset_info, received_amount);
 
     Ok(Response::new()
         .add_messages(cache.assets.transfer_msgs(&cache.user_addr)?)
-        .add_attribute("action", "astrozap/reply/after_providing_liquidity")
-        .add_attribute("shares_minted", shares_minted.to_string()))
+        .add_attribute("asset_received", asset_received.to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     let api = deps.api;
     match msg {
-        QueryMsg::SimulateEnter { pair, deposits } => to_binary(&query_simulate_enter(
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::SimulateEnter {
+            pair,
+            deposits,
+            initial_price,
+            referral_fee_bps,
+        } => to_binary(&query_simulate_enter(
             deps,
             api.addr_validate(&pair)?,
             deposits.check(api, None)?,
+            initial_price,
+            referral_fee_bps,
+        )?),
+        QueryMsg::SimulateEnterByAssets {
+            asset_infos,
+            pair_type,
+            deposits,
+            initial_price,
+            referral_fee_bps,
+        } => {
+            let config = CONFIG.load(deps.storage)?;
+            let pair_addr = resolve_pair(
+                &deps.querier,
+                &config.factory,
+                &[
+                    asset_infos[0].check(api, None)?,
+                    asset_infos[1].check(api, None)?,
+                ],
+                pair_type,
+            )?;
+            to_binary(&query_simulate_enter(
+                deps,
+                pair_addr,
+                deposits.check(api, None)?,
+                initial_price,
+                referral_fee_bps,
+            )?)
+        }
+        QueryMsg::SimulateExit {
+            pair,
+            lp_amount,
+            ask_asset,
+        } => to_binary(&query_simulate_exit(
+            deps,
+            api.addr_validate(&pair)?,
+            lp_amount,
+            ask_asset.check(api, None)?,
+        )?),
+        QueryMsg::SimulateMigrateLiquidity {
+            from_pair,
+            to_pair,
+            lp_amount,
+            route,
+        } => to_binary(&query_simulate_migrate_liquidity(
+            deps,
+            api.addr_validate(&from_pair)?,
+            api.addr_validate(&to_pair)?,
+            lp_amount,
+            route,
+        )?),
+        QueryMsg::StakeInfo { lp_token, user } => to_binary(&query_stake_info(
+            deps,
+            env,
+            api.addr_validate(&lp_token)?,
+            api.addr_validate(&user)?,
         )?),
     }
 }
 
+fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(ConfigResponse {
+        factory: config.factory.into(),
+        router: config.router.into(),
+        owner: config.owner.into(),
+        fee_bps: config.fee_bps,
+        fee_collector: config.fee_collector.into(),
+        paused: config.paused,
+        allowlist: config.allowlist.into_iter().map(String::from).collect(),
+        denylist: config.denylist.into_iter().map(String::from).collect(),
+        keepers: config.keepers.into_iter().map(String::from).collect(),
+    })
+}
+
+fn query_stake_info(
+    deps: Deps,
+    env: Env,
+    lp_token_addr: Addr,
+    user_addr: Addr,
+) -> StdResult<StakeInfoResponse> {
+    let shares = SHARES
+        .may_load(deps.storage, (&lp_token_addr, &user_addr))?
+        .unwrap_or_default();
+    let total_shares = TOTAL_SHARES
+        .may_load(deps.storage, &lp_token_addr)?
+        .unwrap_or_default();
+
+    let lp_amount = if total_shares.is_zero() {
+        Uint128::zero()
+    } else {
+        let config = CONFIG.load(deps.storage)?;
+        let generator_addr = query_generator(&deps.querier, &config.factory)?;
+        let deposit = query_generator_deposit(
+            &deps.querier,
+            &generator_addr,
+            &lp_token_addr,
+            &env.contract.address,
+        )?;
+        compute_vault_lp_amount(shares, total_shares, deposit)
+    };
+
+    Ok(StakeInfoResponse {
+        shares,
+        total_shares,
+        lp_amount,
+    })
+}
+
 fn query_simulate_enter(
     deps: Deps,
     pair_addr: Addr,
     mut deposits: AssetList,
+    initial_price: Option<Decimal>,
+    referral_fee_bps: Option<u64>,
+) -> StdResult<SimulateEnterResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let (protocol_fee, referral_fee) = compute_fees(&config, &deposits, referral_fee_bps)?;
+    deposits.deduct_many(&protocol_fee)?;
+    deposits.deduct_many(&referral_fee)?;
+
+    Ok(SimulateEnterResponse {
+        protocol_fee: protocol_fee.into(),
+        referral_fee: referral_fee.into(),
+        ..simulate_enter(deps, pair_addr, deposits, initial_price)?
+    })
+}
+
+/// Simulate entering a pair with the given deposits, without charging any fee
+fn simulate_enter(
+    deps: Deps,
+    pair_addr: Addr,
+    mut deposits: AssetList,
+    initial_price: Option<Decimal>,
 ) -> StdResult<SimulateEnterResponse> {
     let pair_info = query_pair(&deps.querier, &pair_addr)?;
     let pool_info = query_pool(&deps.querier, &pair_addr)?;
     let mut pool_assets = AssetList::from_legacy(&pool_info.assets);
 
-    // The pair must be of xyz type
+    // The pair must be of XYK or stableswap type
     assert_pair_type(&pair_info.pair_type)?;
     // Each deposited asset must be contained by the pool
     assert_deposit_types(&pool_assets, &deposits)?;
@@ -321,7 +2456,44 @@ fn query_simulate_enter(
     deposits.purge();
     assert_deposit_number(&deposits)?;
 
-    let offer_asset = compute_offer_asset(&pool_assets, &deposits)?;
+    // If the pool is empty, no swap is made, and the amount of shares minted is the geometric mean
+    // of the two amounts provided
+    // https://github.com/astroport-fi/astroport-core/blob/master/contracts/pair/src/contract.rs#L379
+    if pool_info.total_share.is_zero() {
+        let (assets_to_provide, _) =
+            compute_initial_liquidity(&pool_assets, &deposits, initial_price)?;
+        let mint_shares = isqrt(
+            Uint512::from(assets_to_provide[0].amount)
+                .checked_mul(Uint512::from(assets_to_provide[1].amount))?,
+        );
+        let mint_shares =
+            Uint128::try_from(mint_shares).map_err(|err| StdError::generic_err(err.to_string()))?;
+
+        deposits.deduct_many(&assets_to_provide)?;
+
+        return Ok(SimulateEnterResponse {
+            offer_asset: Asset::new(pool_assets[0].info.clone(), Uint128::zero()).into(),
+            return_asset: Asset::new(pool_assets[1].info.clone(), Uint128::zero()).into(),
+            mint_shares,
+            refund_assets: deposits.into(),
+            solver: None,
+            protocol_fee: AssetList::new().into(),
+            referral_fee: AssetList::new().into(),
+        });
+    }
+
+    assert_no_initial_price(initial_price)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let fee_info = query_fee_info(&deps.querier, &config.factory, &pair_info.pair_type)?;
+    let amp = query_amp(&deps.querier, &pair_info)?;
+    let (offer_asset, solution) = compute_offer_asset(
+        &pool_assets,
+        pool_info.total_share,
+        &deposits,
+        &fee_info,
+        amp,
+    )?;
 
     let simulation = query_simulation(&deps.querier, &pair_addr, &offer_asset)?;
     let return_info = if offer_asset.info == pool_assets[0].info {
@@ -329,36 +2501,335 @@ fn query_simulate_enter(
     } else {
         pool_assets[0].info.clone()
     };
-    let return_asset = Asset::new(return_info, simulation.return_amount);
+    let return_asset = Asset::new(return_info.clone(), simulation.return_amount);
+    let maker_fee_asset = Asset::new(
+        return_info,
+        simulation
+            .commission_amount
+            .multiply_ratio(maker_fee_bps(&fee_info), 10000u64),
+    );
 
     pool_assets.add(&offer_asset)?;
     pool_assets.deduct(&return_asset)?;
+    pool_assets.deduct(&maker_fee_asset)?;
 
     deposits.add(&return_asset)?;
     deposits.deduct(&offer_asset)?;
 
-    // https://github.com/astroport-fi/astroport-core/blob/master/contracts/pair/src/contract.rs#L386
-    let mint_shares = std::cmp::min(
-        deposits
-            .find(&pool_assets[0].info)
-            .map(|asset| asset.amount)
-            .unwrap_or_else(Uint128::zero)
-            .multiply_ratio(pool_info.total_share, pool_assets[0].amount),
+    // Of the assets available after the swap, those in excess of the pool's ratio are refunded
+    let pool_info_after_swap = PoolResponse {
+        assets: pool_assets.try_into_legacy()?,
+        total_share: pool_info.total_share,
+    };
+    let mut refund_assets = deposits;
+    let deposits =
+        compute_assets_to_provide(&pair_info, &pool_info_after_swap, &mut refund_assets)?;
+
+    let find_amount = |info: &AssetInfo| {
         deposits
-            .find(&pool_assets[1].info)
+            .find(info)
             .map(|asset| asset.amount)
             .unwrap_or_else(Uint128::zero)
-            .multiply_ratio(pool_info.total_share, pool_assets[1].amount),
-    );
+    };
+    let deposit_amounts = [
+        find_amount(&pool_assets[0].info),
+        find_amount(&pool_assets[1].info),
+    ];
+    let pool_amounts = [pool_assets[0].amount, pool_assets[1].amount];
+    let mint_shares = match amp {
+        Some(amp) => {
+            compute_stable_provide(amp, deposit_amounts, pool_amounts, pool_info.total_share)?
+        }
+        None => compute_xyk_provide(deposit_amounts, pool_amounts, pool_info.total_share)?.1,
+    };
 
     Ok(SimulateEnterResponse {
         offer_asset: offer_asset.into(),
         return_asset: return_asset.into(),
         mint_shares,
+        refund_assets: refund_assets.into(),
+        solver: Some(SolverInfo {
+            iterations: solution.iterations,
+            residual: solution.residual.to_string(),
+            converged: solution.converged,
+        }),
+        protocol_fee: AssetList::new().into(),
+        referral_fee: AssetList::new().into(),
+    })
+}
+
+fn query_simulate_exit(
+    deps: Deps,
+    pair_addr: Addr,
+    lp_amount: Uint128,
+    ask_asset_info: AssetInfo,
+) -> StdResult<SimulateExitResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let pair_info = query_pair(&deps.querier, &pair_addr)?;
+    let pool_info = query_pool(&deps.querier, &pair_addr)?;
+    let pool_assets = AssetList::from_legacy(&pool_info.assets);
+
+    // The pair must be registered with the factory
+    assert_registered_pair(&deps.querier, &config.factory, &pair_addr, &pair_info)?;
+
+    // The asset to receive must be contained by the pool
+    assert_asset_type(&pool_assets, &ask_asset_info)?;
+
+    let withdrawn_assets =
+        compute_withdrawn_assets(&pool_assets, pool_info.total_share, lp_amount)?;
+
+    let offer_info = if ask_asset_info == pool_assets[0].info {
+        pool_assets[1].info.clone()
+    } else {
+        pool_assets[0].info.clone()
+    };
+    let offer_amount = withdrawn_assets
+        .find(&offer_info)
+        .map(|asset| asset.amount)
+        .unwrap_or_else(Uint128::zero);
+    let offer_asset = Asset::new(offer_info, offer_amount);
+
+    let return_amount = if offer_amount.is_zero() {
+        Uint128::zero()
+    } else {
+        compute_swap_after_withdrawal(
+            deps,
+            &pair_info,
+            &pool_assets,
+            &withdrawn_assets,
+            &offer_asset,
+        )?
+    };
+    let return_asset = Asset::new(ask_asset_info.clone(), return_amount);
+
+    let receive_amount = withdrawn_assets
+        .find(&ask_asset_info)
+        .map(|asset| asset.amount)
+        .unwrap_or_else(Uint128::zero)
+        .checked_add(return_amount)?;
+
+    Ok(SimulateExitResponse {
+        withdrawn_assets: withdrawn_assets.into(),
+        offer_asset: offer_asset.into(),
+        return_asset: return_asset.into(),
+        receive_amount,
     })
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::new()) // do nothing
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> StdResult<Response> {
+    let api = deps.api;
+    let stored = STORED_CONFIG.may_load(deps.storage)?.unwrap_or_default();
+    let fee_bps = stored_or_provided("fee_bps", stored.fee_bps, msg.fee_bps)?;
+    assert_fee_bps(fee_bps)?;
+    let config = Config {
+        factory: stored_or_provided("factory", stored.factory, validate_addr(api, msg.factory)?)?,
+        router: stored_or_provided("router", stored.router, validate_addr(api, msg.router)?)?,
+        owner: stored_or_provided("owner", stored.owner, validate_addr(api, msg.owner)?)?,
+        fee_bps,
+        fee_collector: stored_or_provided(
+            "fee_collector",
+            stored.fee_collector,
+            validate_addr(api, msg.fee_collector)?,
+        )?,
+        paused: stored.paused.unwrap_or(false),
+        allowlist: match stored.allowlist {
+            Some(allowlist) => allowlist,
+            None => validate_addrs(api, msg.allowlist.unwrap_or_default())?,
+        },
+        denylist: match stored.denylist {
+            Some(denylist) => denylist,
+            None => validate_addrs(api, msg.denylist.unwrap_or_default())?,
+        },
+        keepers: match stored.keepers {
+            Some(keepers) => keepers,
+            None => validate_addrs(api, msg.keepers.unwrap_or_default())?,
+        },
+    };
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new())
+}
+
+/// Keep a config field as stored, or take the value provided in the migrate message if the field is
+/// not stored, i.e. the contract was deployed before the field was introduced
+fn stored_or_provided<T>(name: &str, stored: Option<T>, provided: Option<T>) -> StdResult<T> {
+    stored.or(provided).ok_or_else(|| {
+        StdError::generic_err(format!(
+            "{} is not in the stored config and must be provided",
+            name
+        ))
+    })
+}
+
+fn query_simulate_migrate_liquidity(
+    deps: Deps,
+    from_pair_addr: Addr,
+    to_pair_addr: Addr,
+    lp_amount: Uint128,
+    route: Option<Vec<SwapOperation>>,
+) -> StdResult<SimulateMigrateLiquidityResponse> {
+    let from_pool_info = query_pool(&deps.querier, &from_pair_addr)?;
+    let from_pool_assets = AssetList::from_legacy(&from_pool_info.assets);
+    let to_pool_info = query_pool(&deps.querier, &to_pair_addr)?;
+    let to_pool_assets = AssetList::from_legacy(&to_pool_info.assets);
+
+    assert_migration_route(&from_pool_assets, &to_pool_assets, route.as_deref())?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let from_pair_info = query_pair(&deps.querier, &from_pair_addr)?;
+    let withdrawn_assets =
+        compute_withdrawn_assets(&from_pool_assets, from_pool_info.total_share, lp_amount)?;
+
+    // If the target pool does not contain one of the withdrawn assets, or if the route does not
+    // start from it, it is swapped into the other one in the source pool
+    let mut deposits = withdrawn_assets.clone();
+    let mut offer_asset = None;
+    let mut return_asset = None;
+    if let Some(offer_info) =
+        find_migration_offer_asset(&from_pool_assets, &to_pool_assets, route.as_deref())?
+    {
+        let offer_amount = withdrawn_assets
+            .find(&offer_info)
+            .map(|asset| asset.amount)
+            .unwrap_or_else(Uint128::zero);
+
+        if !offer_amount.is_zero() {
+            let offer = Asset::new(offer_info.clone(), offer_amount);
+            let return_info = if offer_info == from_pool_assets[0].info {
+                from_pool_assets[1].info.clone()
+            } else {
+                from_pool_assets[0].info.clone()
+            };
+            let return_amount = compute_swap_after_withdrawal(
+                deps,
+                &from_pair_info,
+                &from_pool_assets,
+                &withdrawn_assets,
+                &offer,
+            )?;
+            let returned = Asset::new(return_info, return_amount);
+
+            deposits.deduct(&offer)?;
+            deposits.add(&returned)?;
+
+            offer_asset = Some(offer.into());
+            return_asset = Some(returned.into());
+        }
+    }
+
+    // If a route is provided, the asset it starts from is then swapped through the router
+    let mut routed_asset = None;
+    if let Some(route) = route {
+        let (start_info, _) = parse_swap_operation(&route[0]);
+        let (_, end_info) = parse_swap_operation(&route[route.len() - 1]);
+        let offer = deposits
+            .find(&start_info)
+            .cloned()
+            .unwrap_or_else(|| Asset::new(start_info, 0u128));
+
+        let routed_amount =
+            query_route_simulation(&deps.querier, &config.router, offer.amount, route)?;
+        let routed = Asset::new(end_info, routed_amount);
+
+        deposits.deduct(&offer)?;
+        deposits.add(&routed)?;
+
+        routed_asset = Some(routed.into());
+    }
+
+    let enter = simulate_enter(deps, to_pair_addr, deposits, None)?;
+
+    Ok(SimulateMigrateLiquidityResponse {
+        withdrawn_assets: withdrawn_assets.into(),
+        offer_asset,
+        return_asset,
+        routed_asset,
+        enter,
+    })
+}
+
+/// Compute the amount of ask asset returned, after commission, by swapping in a pair right after
+/// the given assets are withdrawn from it
+///
+/// The pair's simulation query would price the swap against the pool depths before the withdrawal
+/// and overstate the return amount, so we compute it locally against the depths after it
+fn compute_swap_after_withdrawal(
+    deps: Deps,
+    pair_info: &PairInfo,
+    pool_assets: &AssetList,
+    withdrawn_assets: &AssetList,
+    offer_asset: &Asset,
+) -> StdResult<Uint128> {
+    let depth_after_withdrawal = |pool_asset: &Asset| -> StdResult<Uint128> {
+        let withdrawn_amount = withdrawn_assets
+            .find(&pool_asset.info)
+            .map(|asset| asset.amount)
+            .unwrap_or_else(Uint128::zero);
+        Ok(pool_asset.amount.checked_sub(withdrawn_amount)?)
+    };
+    let (offer_pool, ask_pool) = if offer_asset.info == pool_assets[0].info {
+        (
+            depth_after_withdrawal(&pool_assets[0])?,
+            depth_after_withdrawal(&pool_assets[1])?,
+        )
+    } else {
+        (
+            depth_after_withdrawal(&pool_assets[1])?,
+            depth_after_withdrawal(&pool_assets[0])?,
+        )
+    };
+
+    let config = CONFIG.load(deps.storage)?;
+    let fee_info = query_fee_info(&deps.querier, &config.factory, &pair_info.pair_type)?;
+    match query_amp(&deps.querier, pair_info)? {
+        Some(amp) => {
+            let (return_amount, _) = StableSwap::from_asset_amounts(
+                Uint128::zero(),
+                offer_pool,
+                Uint128::zero(),
+                ask_pool,
+                amp,
+                fee_info.total_fee_bps.into(),
+                maker_fee_bps(&fee_info),
+            )
+            .compute_swap(offer_asset.amount)?;
+            Uint128::try_from(return_amount).map_err(|err| StdError::generic_err(err.to_string()))
+        }
+        None => {
+            let (return_amount, _) = XykPair {
+                offer_user: Uint128::zero(),
+                offer_pool,
+                ask_user: Uint128::zero(),
+                ask_pool,
+                total_share: Uint128::zero(),
+                commission_rate_bps: fee_info.total_fee_bps.into(),
+                maker_fee_bps: maker_fee_bps(&fee_info),
+            }
+            .compute_swap(offer_asset.amount)?;
+            Ok(return_amount)
+        }
+    }
+}
+
+/// Compute the assets withdrawn from a pool by burning the given amount of liquidity tokens
+///
+/// https://github.com/astroport-fi/astroport-core/blob/v1.0.0/contracts/pair/src/contract.rs#L1043
+fn compute_withdrawn_assets(
+    pool_assets: &AssetList,
+    total_share: Uint128,
+    lp_amount: Uint128,
+) -> StdResult<AssetList> {
+    let share_ratio = if total_share.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(lp_amount, total_share)
+    };
+    let mut withdrawn_assets = AssetList::new();
+    for pool_asset in pool_assets {
+        withdrawn_assets.add(&Asset::new(
+            pool_asset.info.clone(),
+            pool_asset.amount * share_ratio,
+        ))?;
+    }
+    Ok(withdrawn_assets)
 }
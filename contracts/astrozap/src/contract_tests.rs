@@ -1,23 +1,57 @@
+use std::str::FromStr;
+
 use cosmwasm_std::testing::{mock_env, mock_info, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, Coin, ContractResult, CosmosMsg, Event, OwnedDeps, Reply,
-    ReplyOn, StdError, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg, Decimal
+    from_binary, to_binary, Addr, Attribute, BankMsg, Coin, ContractResult, CosmosMsg, Decimal,
+    Event, OwnedDeps, Reply, ReplyOn, StdError, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
 };
+use cw20::{Cw20ReceiveMsg, Expiration};
 
 use cw_asset::{Asset, AssetInfo, AssetList};
 
 use astroport::asset::PairInfo;
 use astroport::factory::PairType;
 use astroport::pair::PoolResponse;
+use astroport::router::SwapOperation;
 
-use crate::contract::{execute, query, reply};
-use crate::msg::{ExecuteMsg, QueryMsg, SimulateEnterResponse};
-use crate::state::{CacheData, CACHE};
-use crate::test_utils::{mock_dependencies, CustomMockApi, CustomMockQuerier};
+use crate::contract::{execute, instantiate, migrate, query, reply};
+use crate::msg::{
+    ConfigResponse, Cw20HookMsg, EnterTarget, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg,
+    Referrer, SimulateEnterResponse, SimulateExitResponse, SimulateMigrateLiquidityResponse,
+    SolverInfo, StakeInfoResponse,
+};
+use crate::state::{
+    CacheData, ExitCacheData, PendingEnter, StoredConfig, CACHE, CONFIG, EXIT_CACHE, REWARDS,
+    SHARES, STORED_CONFIG, TOTAL_SHARES, ZAP_ID,
+};
+use crate::test_utils::{
+    compute_stable_provide, mock_dependencies, CustomMockApi, CustomMockQuerier, FACTORY_ADDR,
+    GENERATOR_ADDR, ROUTER_ADDR,
+};
 
 fn setup_test() -> OwnedDeps<MockStorage, CustomMockApi, CustomMockQuerier> {
     let mut deps = mock_dependencies();
 
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("deployer", &[]),
+        InstantiateMsg {
+            factory: String::from(FACTORY_ADDR),
+            router: String::from(ROUTER_ADDR),
+            owner: String::from("deployer"),
+            fee_bps: 0,
+            fee_collector: String::from("fee_collector"),
+            allowlist: None,
+            denylist: None,
+            keepers: None,
+        },
+    )
+    .unwrap();
+
+    deps.querier.set_fee_info(PairType::Xyk {}, 30, 0);
+    deps.querier.set_fee_info(PairType::Stable {}, 5, 0);
+
     deps.querier.set_pair(
         "luna_ust_pair",
         PairInfo {
@@ -86,6 +120,30 @@ fn setup_test() -> OwnedDeps<MockStorage, CustomMockApi, CustomMockQuerier> {
             total_share: Uint128::new(2948589474051u128),
         },
     );
+    deps.querier.set_amp("bluna_luna_pair", 10);
+
+    deps.querier.set_pair(
+        "astro_luna_pair",
+        PairInfo {
+            asset_infos: [
+                AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+                AssetInfo::native("uluna").into(),
+            ],
+            contract_addr: Addr::unchecked("astro_luna_pair"),
+            liquidity_token: Addr::unchecked("astro_luna_lp_token"),
+            pair_type: PairType::Xyk {},
+        },
+    );
+    deps.querier.set_pool(
+        "astro_luna_pair",
+        PoolResponse {
+            assets: [
+                Asset::cw20(Addr::unchecked("astro_token"), 0u128).into(),
+                Asset::native("uluna", 0u128).into(),
+            ],
+            total_share: Uint128::zero(),
+        },
+    );
 
     deps
 }
@@ -94,19 +152,44 @@ fn setup_test() -> OwnedDeps<MockStorage, CustomMockApi, CustomMockQuerier> {
 fn should_reject_wrong_pair_type() {
     let mut deps = setup_test();
 
+    deps.querier.set_pair(
+        "bluna_luna_pair",
+        PairInfo {
+            asset_infos: [
+                AssetInfo::cw20(Addr::unchecked("bluna_token")).into(),
+                AssetInfo::native("uluna").into(),
+            ],
+            contract_addr: Addr::unchecked("bluna_luna_pair"),
+            liquidity_token: Addr::unchecked("bluna_luna_lp_token"),
+            pair_type: PairType::Custom(String::from("concentrated")),
+        },
+    );
+
     let msg = ExecuteMsg::Enter {
         pair: String::from("bluna_luna_pair"),
-        deposits: AssetList::from(vec![
-            Asset::cw20(Addr::unchecked("bluna_token"), 12345u128),
-            Asset::native("uluna", 12345u128),
-        ])
-        .into(),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::cw20(Addr::unchecked("bluna_token"), 12345u128),
+                Asset::native("uluna", 12345u128),
+            ])
+            .into(),
+        ),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
     assert_eq!(
         err,
-        Err(StdError::generic_err("unsupported pair type: stable"))
+        Err(StdError::generic_err(
+            "unsupported pair type: custom-concentrated"
+        ))
     );
 }
 
@@ -116,12 +199,22 @@ fn should_reject_wrong_deposit_type() {
 
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![
-            Asset::cw20(Addr::unchecked("astro_token"), 12345u128),
-            Asset::native("uusd", 12345u128),
-        ])
-        .into(),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::cw20(Addr::unchecked("astro_token"), 12345u128),
+                Asset::native("uusd", 12345u128),
+            ])
+            .into(),
+        ),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
     assert_eq!(
@@ -139,8 +232,16 @@ fn should_reject_wrong_deposit_number() {
     // Deposit no asset
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![Asset::native("uluna", 0u128)]).into(),
+        deposits: Some(AssetList::from(vec![Asset::native("uluna", 0u128)]).into()),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
     assert_eq!(
@@ -159,13 +260,23 @@ fn should_reject_wrong_deposit_number() {
     // input will be considered legal
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![
-            Asset::native("uluna", 12345u128),
-            Asset::native("uusd", 12345u128),
-            Asset::native("uluna", 12345u128),
-        ])
-        .into(),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::native("uluna", 12345u128),
+                Asset::native("uusd", 12345u128),
+                Asset::native("uluna", 12345u128),
+            ])
+            .into(),
+        ),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
     assert_eq!(
@@ -183,8 +294,16 @@ fn should_reject_missing_deposit() {
     // User claims to deposit 12345 uluna, but doesn't actually send any with the message
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![Asset::native("uluna", 12345u128)]).into(),
+        deposits: Some(AssetList::from(vec![Asset::native("uluna", 12345u128)]).into()),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let err = execute(
         deps.as_mut(),
@@ -219,10 +338,18 @@ fn should_reject_extra_deposit() {
 
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![Asset::native("uluna", 12345u128)]).into(),
+        deposits: Some(AssetList::from(vec![Asset::native("uluna", 12345u128)]).into()),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
-    // User claims to deposit 12345 uluna, but also deposit more 
+    // User claims to deposit 12345 uluna, but also deposit more
     let actual_deposits = &[
         Coin::new(12345, "uluna"),
         Coin::new(69420, "uusd"),
@@ -242,6 +369,266 @@ fn should_reject_extra_deposit() {
     );
 }
 
+#[test]
+fn should_reject_unregistered_pair() {
+    let mut deps = setup_test();
+
+    // A contract posing as the LUNA-UST pair, with its own liquidity token
+    deps.querier.set_unregistered_pair(
+        "fake_pair",
+        PairInfo {
+            asset_infos: [
+                AssetInfo::native("uusd").into(),
+                AssetInfo::native("uluna").into(),
+            ],
+            contract_addr: Addr::unchecked("fake_pair"),
+            liquidity_token: Addr::unchecked("fake_lp_token"),
+            pair_type: PairType::Xyk {},
+        },
+    );
+    deps.querier.set_pool(
+        "fake_pair",
+        PoolResponse {
+            assets: [
+                Asset::native("uusd", 118070429547232u128).into(),
+                Asset::native("uluna", 1451993415113u128).into(),
+            ],
+            total_share: Uint128::new(12966110801826u128),
+        },
+    );
+
+    // The pair is verified before the deposits are handled, so the missing deposit is not reported
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("fake_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 12345u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair fake_pair is not registered with the factory"
+        ))
+    );
+
+    // Same if the pair claims assets that no registered pair has
+    deps.querier.set_unregistered_pair(
+        "fake_pair",
+        PairInfo {
+            asset_infos: [
+                AssetInfo::native("uusd").into(),
+                AssetInfo::native("ukrw").into(),
+            ],
+            contract_addr: Addr::unchecked("fake_pair"),
+            liquidity_token: Addr::unchecked("fake_lp_token"),
+            pair_type: PairType::Xyk {},
+        },
+    );
+
+    let msg = ExecuteMsg::EnterMany {
+        targets: vec![
+            EnterTarget {
+                pair: String::from("luna_ust_pair"),
+                weight: 1,
+                minimum_received: None,
+            },
+            EnterTarget {
+                pair: String::from("fake_pair"),
+                weight: 1,
+                minimum_received: None,
+            },
+        ],
+        deposits: AssetList::from(vec![Asset::native("uusd", 12345u128)]).into(),
+        recipient: None,
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair fake_pair is not registered with the factory"
+        ))
+    );
+
+    // Liquidity tokens of an unregistered pair can neither be exited nor migrated
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("fake_pair"),
+            ask_asset: AssetInfo::native("uusd").into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("fake_lp_token", &[]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair fake_pair is not registered with the factory"
+        ))
+    );
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::MigrateLiquidity {
+            from_pair: String::from("fake_pair"),
+            to_pair: String::from("luna_ust_pair"),
+            minimum_received: None,
+            route: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("fake_lp_token", &[]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair fake_pair is not registered with the factory"
+        ))
+    );
+
+    // Nor can exiting be simulated
+    let msg = QueryMsg::SimulateExit {
+        pair: String::from("fake_pair"),
+        lp_amount: Uint128::new(12345),
+        ask_asset: AssetInfo::native("uusd").into(),
+    };
+    let err = query(deps.as_ref(), mock_env(), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair fake_pair is not registered with the factory"
+        ))
+    );
+}
+
+#[test]
+fn should_reject_expired_deadline() {
+    let mut deps = setup_test();
+
+    // `mock_env` is at block height 12345, so a deadline at this height has already passed
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 12345u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: Some(Expiration::AtHeight(12345)),
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "deadline has passed; expiration height: 12345"
+        ))
+    );
+
+    // The deadline is checked before the deposits, so the missing deposit is only reported once the
+    // deadline is in the future
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 12345u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: Some(Expiration::AtHeight(12346)),
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "invalid deposit: expected native:uusd:12345, received none"
+        ))
+    );
+
+    // The deadline also applies to CW20 tokens sent with the `Enter` hook
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::Enter {
+            pair: String::from("astro_ust_pair"),
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            deadline: Some(Expiration::AtHeight(12345)),
+            max_spread: None,
+            belief_price: None,
+            provide_slippage_tolerance: None,
+            referrer: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_token", &[]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "deadline has passed; expiration height: 12345"
+        ))
+    );
+
+    // With `EnterByAssets`, the deadline is checked before looking up the pair, so the missing
+    // pair is not reported
+    let msg = ExecuteMsg::EnterByAssets {
+        asset_infos: [
+            AssetInfo::native("uusd").into(),
+            AssetInfo::native("ukrw").into(),
+        ],
+        pair_type: None,
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 12345u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: Some(Expiration::AtHeight(12345)),
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "deadline has passed; expiration height: 12345"
+        ))
+    );
+}
+
 #[test]
 fn should_enter_native_native_pool() {
     let mut deps = setup_test();
@@ -259,11 +646,21 @@ fn should_enter_native_native_pool() {
     // b = 171807034937323920678165568
     // c = -17143748622214425401611721600000000000
     //
-    // In 4 interations, should find solution: offer_amount = 50064546170
+    // Should find solution: offer_amount = 50064546169
+    //
+    // Accounting for the pair's rounding, offering 50064546167 mints one more liquidity token
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let res = execute(
         deps.as_mut(),
@@ -280,13 +677,13 @@ fn should_enter_native_native_pool() {
             msg: CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: String::from("luna_ust_pair"),
                 msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
-                    offer_asset: Asset::native("uusd", 50064546170u128).into(),
+                    offer_asset: Asset::native("uusd", 50064546167u128).into(),
                     belief_price: None,
                     max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
                     to: None,
                 })
                 .unwrap(),
-                funds: vec![Coin::new(50064546170, "uusd")]
+                funds: vec![Coin::new(50064546167, "uusd")]
             }),
             gas_limit: None,
             reply_on: ReplyOn::Success,
@@ -294,8 +691,24 @@ fn should_enter_native_native_pool() {
     );
 
     // Using `scripts/cfmm.ts` to estimate:
-    // offering 50064546170 uusd, should receive 613571013 uluna after commission
-    // uusd available: 100000000000 - 50064546170 = 49935453830
+    // offering 50064546167 uusd, should receive 613571013 uluna after commission
+    // uusd available: 100000000000 - 50064546167 = 49935453833
+    //
+    // The pool should have:
+    // uusd: 118070429547232 + 50064546167 = 118120494093399
+    // uluna: 1451993415113 - 613571013 = 1451379844100
+    // uLP: 12966110801826
+    deps.querier.set_pool(
+        "luna_ust_pair",
+        PoolResponse {
+            assets: [
+                Asset::native("uusd", 118120494093399u128).into(),
+                Asset::native("uluna", 1451379844100u128).into(),
+            ],
+            total_share: Uint128::new(12966110801826u128),
+        },
+    );
+
     let _reply = Reply {
         id: 1,
         result: ContractResult::Ok(SubMsgExecutionResponse {
@@ -316,7 +729,7 @@ fn should_enter_native_native_pool() {
                 contract_addr: String::from("luna_ust_pair"),
                 msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
                     assets: [
-                        Asset::native("uusd", 49935453830u128).into(),
+                        Asset::native("uusd", 49935453833u128).into(),
                         Asset::native("uluna", 613571013u128).into(),
                     ],
                     slippage_tolerance: None,
@@ -325,7 +738,7 @@ fn should_enter_native_native_pool() {
                 })
                 .unwrap(),
                 funds: vec![
-                    Coin::new(49935453830, "uusd"),
+                    Coin::new(49935453833, "uusd"),
                     Coin::new(613571013, "uluna")
                 ]
             }),
@@ -334,26 +747,22 @@ fn should_enter_native_native_pool() {
         }
     );
 
-    // The pool should have:
-    // uusd: 118070429547232 + 50064546170 = 118120494093402
-    // uluna: 1451993415113 - 613571013 = 1451379844100
-    // uLP: 12966110801826
-    //
     // Amount of liquidity tokens to be minted:
     // min(
-    //     12966110801826 * 49935453830 / 118120494093402,
+    //     12966110801826 * 49935453833 / 118120494093399,
     //     12966110801826 * 613571013 / 1451379844100,
     // )
-    // = min(5481424982, 5481424984)
-    // = 5481424982
+    // = min(5481424983, 5481424984)
+    // = 5481424983
     //
-    // Should generate a message to refund
+    // Both assets are in the pool's ratio, so there is nothing to refund other than the liquidity
+    // tokens
     let _reply = Reply {
         id: 2,
         result: ContractResult::Ok(SubMsgExecutionResponse {
             events: vec![Event::new("wasm")
                 .add_attribute("action", "provide_liquidity")
-                .add_attribute("share", "5481424982")],
+                .add_attribute("share", "5481424983")],
             data: None,
         }),
     };
@@ -367,7 +776,7 @@ fn should_enter_native_native_pool() {
                 contract_addr: String::from("luna_ust_lp_token"),
                 msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
                     recipient: String::from("alice"),
-                    amount: Uint128::new(5481424982)
+                    amount: Uint128::new(5481424983)
                 })
                 .unwrap(),
                 funds: vec![]
@@ -395,15 +804,28 @@ fn should_enter_cw20_native_pool() {
     // b = 6262754336088952322758000272
     // c = -2117537481900892096900110663650000000000
     //
-    // In 4 interations, should find solution: offer_amount = 336933122413
+    // Should find solution: offer_amount = 336933122412
+    //
+    // Accounting for the pair's rounding, offering 336933122413 mints the same amount of liquidity
+    // tokens with a smaller refund
     let msg = ExecuteMsg::Enter {
         pair: String::from("astro_ust_pair"),
-        deposits: AssetList::from(vec![
-            Asset::native("uusd", 100000000000u128),
-            Asset::cw20(Addr::unchecked("astro_token"), 750000000000u128), // ~$1M
-        ])
-        .into(),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::native("uusd", 100000000000u128),
+                Asset::cw20(Addr::unchecked("astro_token"), 750000000000u128), // ~$1M
+            ])
+            .into(),
+        ),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
     let res = execute(
         deps.as_mut(),
@@ -459,6 +881,22 @@ fn should_enter_cw20_native_pool() {
     // offering 336933122413 uASTRO, should receive 452253642498 uusd after commission
     // uASTRO available: 750000000000 - 336933122413 = 413066877587
     // uusd available: 100000000000 + 452253642498 = 552253642498
+    //
+    // The pool should have:
+    // uASTRO: 48059201882191 + 336933122413 = 48396135004604
+    // uusd: 65155920988539 - 452253642498 = 64703667346041
+    // uLP: 55851193190261
+    deps.querier.set_pool(
+        "astro_ust_pair",
+        PoolResponse {
+            assets: [
+                Asset::cw20(Addr::unchecked("astro_token"), 48396135004604u128).into(),
+                Asset::native("uusd", 64703667346041u128).into(),
+            ],
+            total_share: Uint128::new(55851193190261u128),
+        },
+    );
+
     let _reply = Reply {
         id: 1,
         result: ContractResult::Ok(SubMsgExecutionResponse {
@@ -497,7 +935,7 @@ fn should_enter_cw20_native_pool() {
                 contract_addr: String::from("astro_ust_pair"),
                 msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
                     assets: [
-                        Asset::native("uusd", 552253642498u128).into(),
+                        Asset::native("uusd", 552253642497u128).into(),
                         Asset::cw20(Addr::unchecked("astro_token"), 413066877587u128).into(),
                     ],
                     slippage_tolerance: None,
@@ -505,18 +943,13 @@ fn should_enter_cw20_native_pool() {
                     receiver: None
                 })
                 .unwrap(),
-                funds: vec![Coin::new(552253642498, "uusd"),]
+                funds: vec![Coin::new(552253642497, "uusd"),]
             }),
             gas_limit: None,
             reply_on: ReplyOn::Success
         }
     );
 
-    // The pool should have:
-    // uASTRO: 48059201882191 + 336933122413 = 48396135004604
-    // uusd: 65155920988539 - 452253642498 = 64702289434651
-    // uLP: 55851193190261
-    //
     // Amount of liquidity tokens to be minted:
     // min(
     //     55851193190261 * 413066877587 / 48396135004604,
@@ -525,7 +958,12 @@ fn should_enter_cw20_native_pool() {
     // = min(476696702710, 476696702711)
     // = 476696702710
     //
-    // Should generate a message to refund
+    // Minting this amount requires only:
+    // uASTRO: ceil(476696702710 * 48396135004604 / 55851193190261) = 413066877587
+    // uusd: ceil(476696702710 * 64703667346041 / 55851193190261) = 552253642497
+    //
+    // Only these amounts were provided, so the excess 1 uusd should be refunded along with the
+    // liquidity tokens
     let _reply = Reply {
         id: 2,
         result: ContractResult::Ok(SubMsgExecutionResponse {
@@ -536,9 +974,16 @@ fn should_enter_cw20_native_pool() {
         }),
     };
     let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
-    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages.len(), 2);
     assert_eq!(
         res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("alice"),
+            amount: vec![Coin::new(1, "uusd")]
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
         SubMsg {
             id: 0,
             msg: CosmosMsg::Wasm(WasmMsg::Execute {
@@ -557,92 +1002,3863 @@ fn should_enter_cw20_native_pool() {
 }
 
 #[test]
-fn should_enter_with_equal_value_assets() {
+fn should_enter_with_funds_only() {
     let mut deps = setup_test();
+    let mut deps_with_deposits = setup_test();
 
-    // We provide LUNA + UST that's exactly the pool depth
-    // No swap should be needed
+    // Omitting `deposits` should be the same as specifying the native coins sent
     let msg = ExecuteMsg::Enter {
         pair: String::from("luna_ust_pair"),
-        deposits: AssetList::from(vec![
-            Asset::native("uusd", 118070429547232u128),
-            Asset::native("uluna", 1451993415113u128),
-        ])
-        .into(),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::native("uusd", 69420000000u128),
+                Asset::native("uluna", 88888888u128),
+            ])
+            .into(),
+        ),
         minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
     };
-    let res = execute(
+    let info = mock_info(
+        "alice",
+        &[Coin::new(69420000000, "uusd"), Coin::new(88888888, "uluna")],
+    );
+    let expected = execute(deps_with_deposits.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: None,
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap();
+    assert_eq!(res, expected);
+    assert_eq!(
+        CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap(),
+        CACHE
+            .load(deps_with_deposits.as_ref().storage, 1u64.into())
+            .unwrap()
+    );
+
+    // The coins sent are validated the same way as specified deposits
+    let err = execute(
         deps.as_mut(),
         mock_env(),
-        mock_info(
-            "alice",
-            &[
-                Coin::new(118070429547232, "uusd"),
-                Coin::new(1451993415113, "uluna"),
-            ],
-        ),
-        msg,
-    )
-    .unwrap();
+        mock_info("alice", &[]),
+        msg.clone(),
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "must deposit exactly 1 or 2 assets; received 0"
+        ))
+    );
+
+    let info = mock_info(
+        "alice",
+        &[Coin::new(12345, "uusd"), Coin::new(12345, "ukrw")],
+    );
+    let err = execute(deps.as_mut(), mock_env(), info, msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair does not contain asset native:ukrw"
+        ))
+    );
+}
+
+#[test]
+fn should_enter_via_cw20_hook() {
+    let mut deps = setup_test();
+
+    // Compute offer amount
+    //
+    // Using `scripts/math.ts`, create `Equation` instance using the following parameters:
+    // offer_user = 750000000000
+    // offer_pool = 48059201882191
+    // ask_user = 0
+    // ask_pool = 65155920988539
+    //
+    // Should calculate:
+    // a = 65155920988539
+    // b = 6253142495712514122758000272
+    // c = -2348506170456211192604406711750000000000
+    //
+    // Should find solution: offer_amount = 374113795678
+    //
+    // Accounting for the pair's rounding, offering 374113795679 mints one more liquidity token
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(750000000000),
+        msg: to_binary(&Cw20HookMsg::Enter {
+            pair: String::from("astro_ust_pair"),
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            deadline: None,
+            max_spread: None,
+            belief_price: None,
+            provide_slippage_tolerance: None,
+            referrer: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_token", &[]),
+        msg,
+    )
+    .unwrap();
+
+    // The token has already been received, so there should be no `TransferFrom` message
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("astro_ust_pair"),
+                    amount: Uint128::new(374113795679),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::Swap {
+                        belief_price: None,
+                        max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.user_addr, Addr::unchecked("alice"));
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![Asset::cw20(
+            Addr::unchecked("astro_token"),
+            375886204321u128
+        )])
+    );
+}
+
+#[test]
+fn should_reject_reentrant_enter() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: None,
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let info = mock_info("alice", &[Coin::new(100000000000, "uusd")]);
+    execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(ZAP_ID.load(deps.as_ref().storage).unwrap(), 1);
+    assert!(CACHE.has(deps.as_ref().storage, 1u64.into()));
+
+    // Suppose a contract called in the middle of the zap calls back into AstroZap. It should not
+    // be able to start another zap, which would otherwise overwrite the cache of the first one
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone());
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("zap 1 is already in progress"))
+    );
+
+    // Once the first zap is done, its cache is cleared, and the next zap is cached under a new id
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uluna")
+                .add_attribute("return_amount", "613571013")],
+            data: None,
+        }),
+    };
+    reply(deps.as_mut(), mock_env(), _reply).unwrap();
+
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "5481424983")],
+            data: None,
+        }),
+    };
+    reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert!(!CACHE.has(deps.as_ref().storage, 1u64.into()));
+
+    execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(ZAP_ID.load(deps.as_ref().storage).unwrap(), 2);
+    assert!(CACHE.has(deps.as_ref().storage, 2u64.into()));
+
+    // `Exit` is under the same guard: it cannot start while a zap is in progress, and no zap can
+    // start while it is in progress
+    let exit_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(5481424982),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("luna_ust_pair"),
+            ask_asset: AssetInfo::native("uusd").into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    let exit_info = mock_info("luna_ust_lp_token", &[]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        exit_info.clone(),
+        exit_msg.clone(),
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("zap 2 is already in progress"))
+    );
+
+    CACHE.remove(deps.as_mut().storage, 2u64.into());
+    execute(deps.as_mut(), mock_env(), exit_info, exit_msg).unwrap();
+    assert_eq!(ZAP_ID.load(deps.as_ref().storage).unwrap(), 3);
+    assert!(EXIT_CACHE.has(deps.as_ref().storage, 3u64.into()));
+
+    let err = execute(deps.as_mut(), mock_env(), info, msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("zap 3 is already in progress"))
+    );
+}
+
+#[test]
+fn should_enter_stable_pool() {
+    let mut deps = setup_test();
+
+    // Under the StableSwap invariant with amp = 10 and 0.05% commission, offering 49395594273 uluna
+    // returns 49331679227 ubLUNA, after which the user's assets are in the same ratio as the pool's
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("bluna_luna_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uluna", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uluna")]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.attributes[4..],
+        [
+            Attribute::new("solver_iterations", "37"),
+            Attribute::new("solver_residual", "3289016919243"),
+            Attribute::new("solver_converged", "true"),
+        ]
+    );
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("bluna_luna_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uluna", 49395594273u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(49395594273, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "bluna_token")
+                .add_attribute("return_amount", "49331679227")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("bluna_luna_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uluna", 50604405727u128).into(),
+                        Asset::cw20(Addr::unchecked("bluna_token"), 49331679227u128).into(),
+                    ],
+                    slippage_tolerance: None,
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![Coin::new(50604405727, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+}
+
+#[test]
+fn should_enter_empty_pool() {
+    let mut deps = setup_test();
+
+    // The user specifies an initial price of 0.02 uluna per uASTRO
+    // uluna to be provided: 1000000000 * 0.02 = 20000000 (less than the 25000000 deposited)
+    // uluna to be refunded: 25000000 - 20000000 = 5000000
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("astro_luna_pair"),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::cw20(Addr::unchecked("astro_token"), 1000000000u128),
+                Asset::native("uluna", 25000000u128),
+            ])
+            .into(),
+        ),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: Some(Decimal::from_ratio(1u128, 50u128)),
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(25000000, "uluna")]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::TransferFrom {
+                    owner: String::from("alice"),
+                    recipient: String::from(MOCK_CONTRACT_ADDR),
+                    amount: Uint128::new(1000000000),
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: String::from("astro_luna_pair"),
+                    amount: Uint128::new(1000000000),
+                    expires: None,
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_luna_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::cw20(Addr::unchecked("astro_token"), 1000000000u128).into(),
+                        Asset::native("uluna", 20000000u128).into(),
+                    ],
+                    slippage_tolerance: None,
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![Coin::new(20000000, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+    assert_eq!(res.attributes[4], Attribute::new("initial_price", "0.02"));
+
+    // Amount of liquidity tokens to be minted: isqrt(1000000000 * 20000000) = 141421356
+    //
+    // Should transfer the liquidity tokens along with the excess uluna to the user
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "141421356")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("alice"),
+            amount: vec![Coin::new(5000000, "uluna")]
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: String::from("astro_luna_lp_token"),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: String::from("alice"),
+                amount: Uint128::new(141421356)
+            })
+            .unwrap(),
+            funds: vec![]
+        }))
+    );
+}
+
+#[test]
+fn should_reject_invalid_empty_pool_entry() {
+    let mut deps = setup_test();
+
+    // Initial price must be specified for an empty pool
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("astro_luna_pair"),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::cw20(Addr::unchecked("astro_token"), 1000000000u128),
+                Asset::native("uluna", 25000000u128),
+            ])
+            .into(),
+        ),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(25000000, "uluna")]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err("pool is empty; initial price must be specified")
+    );
+
+    // Both assets must be deposited into an empty pool
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("astro_luna_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uluna", 25000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: Some(Decimal::from_ratio(1u128, 50u128)),
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(25000000, "uluna")]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err("pool is empty; must deposit both assets")
+    );
+
+    // Initial price must not be specified for a non-empty pool
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: Some(Decimal::from_ratio(1u128, 50u128)),
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err("initial price can only be specified for empty pools")
+    );
+}
+
+#[test]
+fn should_enter_with_equal_value_assets() {
+    let mut deps = setup_test();
+
+    // We provide LUNA + UST that's exactly the pool depth
+    // No swap should be needed
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::native("uusd", 118070429547232u128),
+                Asset::native("uluna", 1451993415113u128),
+            ])
+            .into(),
+        ),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "alice",
+            &[
+                Coin::new(118070429547232, "uusd"),
+                Coin::new(1451993415113, "uluna"),
+            ],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uusd", 118070429547232u128).into(),
+                        Asset::native("uluna", 1451993415113u128).into(),
+                    ],
+                    slippage_tolerance: None,
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![
+                    Coin::new(118070429547232, "uusd"),
+                    Coin::new(1451993415113, "uluna")
+                ]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+}
+
+#[test]
+fn should_enter_with_recipient() {
+    let mut deps = setup_test();
+
+    // Recipient must be a valid address
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: Some(String::from("charlie")),
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("[mock]: invalid address: charlie"))
+    );
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: Some(String::from("bob")),
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        msg,
+    )
+    .unwrap();
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.user_addr, Addr::unchecked("alice"));
+    assert_eq!(cache.recipient_addr, Addr::unchecked("bob"));
+
+    // Suppose after providing liquidity, there is some uusd left over. Both the liquidity tokens
+    // and the leftover should be sent to the recipient
+    CACHE
+        .save(
+            deps.as_mut().storage,
+            1u64.into(),
+            &CacheData {
+                assets: AssetList::from(vec![Asset::native("uusd", 420u128)]),
+                ..cache
+            },
+        )
+        .unwrap();
+
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "5481424982")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("bob"),
+            amount: vec![Coin::new(420, "uusd")]
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: String::from("luna_ust_lp_token"),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: String::from("bob"),
+                amount: Uint128::new(5481424982)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+}
+
+#[test]
+fn should_reject_contract_as_recipient() {
+    let mut deps = setup_test();
+
+    // Liquidity tokens minted to the contract itself would be mistaken for rewards to compound, or
+    // staked into the vault without issuing any shares
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: None,
+        minimum_received: None,
+        recipient: Some(String::from(MOCK_CONTRACT_ADDR)),
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let info = mock_info("alice", &[Coin::new(100000000000, "uusd")]);
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("recipient cannot be this contract"))
+    );
+
+    let msg = ExecuteMsg::EnterViaRouter {
+        pair: String::from("luna_ust_pair"),
+        deposit: Asset::native("uusd", 100000000000u128).into(),
+        route: vec![SwapOperation::NativeSwap {
+            offer_denom: String::from("uusd"),
+            ask_denom: String::from("uluna"),
+        }],
+        minimum_received: None,
+        recipient: Some(String::from(MOCK_CONTRACT_ADDR)),
+        auto_stake: Some(true),
+        provide_slippage_tolerance: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("recipient cannot be this contract"))
+    );
+
+    let msg = ExecuteMsg::EnterMany {
+        targets: vec![EnterTarget {
+            pair: String::from("luna_ust_pair"),
+            weight: 1,
+            minimum_received: None,
+        }],
+        deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        recipient: Some(String::from(MOCK_CONTRACT_ADDR)),
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info, msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("recipient cannot be this contract"))
+    );
+}
+
+#[test]
+fn should_enter_with_auto_stake() {
+    let mut deps = setup_test();
+
+    // We provide LUNA + UST that's exactly the pool depth, so no swap is needed
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::native("uusd", 118070429547232u128),
+                Asset::native("uluna", 1451993415113u128),
+            ])
+            .into(),
+        ),
+        minimum_received: Some(Uint128::new(12966110801826)),
+        recipient: Some(String::from("bob")),
+        auto_stake: Some(true),
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "alice",
+            &[
+                Coin::new(118070429547232, "uusd"),
+                Coin::new(1451993415113, "uluna"),
+            ],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uusd", 118070429547232u128).into(),
+                        Asset::native("uluna", 1451993415113u128).into(),
+                    ],
+                    slippage_tolerance: None,
+                    auto_stake: Some(true),
+                    receiver: Some(String::from("bob"))
+                })
+                .unwrap(),
+                funds: vec![
+                    Coin::new(118070429547232, "uusd"),
+                    Coin::new(1451993415113, "uluna")
+                ]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // The amount of shares minted is read from the `share` attribute and checked against minimum
+    // received. The liquidity tokens are already staked on behalf of the recipient, so there should
+    // be no message to transfer them
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "12966110801826")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 0);
+}
+
+#[test]
+fn should_enter_with_max_spread() {
+    let mut deps = setup_test();
+
+    // If only `max_spread` is specified, the belief price should be the pool's spot price of uluna
+    // in uusd
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: Some(Decimal::percent(2)),
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 50064546167u128).into(),
+                    belief_price: Some(Decimal::from_ratio(118070429547232u128, 1451993415113u128)),
+                    max_spread: Some(Decimal::percent(2)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(50064546167, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // If `belief_price` is specified, it should be passed to the pair as is, along with the default
+    // max spread if `max_spread` is not specified. The first zap is still in progress, so we start
+    // from a fresh state
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("astro_ust_pair"),
+        deposits: Some(
+            AssetList::from(vec![Asset::cw20(
+                Addr::unchecked("astro_token"),
+                750000000000u128,
+            )])
+            .into(),
+        ),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: Some(Decimal::from_ratio(3u128, 4u128)),
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("astro_ust_pair"),
+                    amount: Uint128::new(374113795679),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::Swap {
+                        belief_price: Some(Decimal::from_ratio(3u128, 4u128)),
+                        max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+}
+
+#[test]
+fn should_enter_stable_pool_with_max_spread() {
+    let mut deps = setup_test();
+
+    // For stableswap pools, the belief price should be the marginal price under the invariant. The
+    // ratio of the pool's depths, 2961459937027 / 2937863752918 = 1.008..., overstates the price of
+    // uluna by most of the 1% max spread
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(100000000000),
+        msg: to_binary(&Cw20HookMsg::Enter {
+            pair: String::from("bluna_luna_pair"),
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            deadline: None,
+            max_spread: Some(Decimal::percent(1)),
+            belief_price: None,
+            provide_slippage_tolerance: None,
+            referrer: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("bluna_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("bluna_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("bluna_luna_pair"),
+                    amount: Uint128::new(49038335019),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::Swap {
+                        belief_price: Some(Decimal::from_str("1.000727522757377209").unwrap()),
+                        max_spread: Some(Decimal::percent(1)),
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+}
+
+#[test]
+fn should_enter_with_provide_slippage_tolerance() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: Some(Decimal::percent(1)),
+        referrer: None,
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        msg,
+    )
+    .unwrap();
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.provide_slippage_tolerance, Some(Decimal::percent(1)));
+
+    // Same as in `should_enter_native_native_pool`, the swap should return 613571013 uluna, which
+    // should be provided along with 49935453833 uusd, with the slippage tolerance forwarded to the
+    // pair
+    deps.querier.set_pool(
+        "luna_ust_pair",
+        PoolResponse {
+            assets: [
+                Asset::native("uusd", 118120494093399u128).into(),
+                Asset::native("uluna", 1451379844100u128).into(),
+            ],
+            total_share: Uint128::new(12966110801826u128),
+        },
+    );
+
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uluna")
+                .add_attribute("return_amount", "613571013")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uusd", 49935453833u128).into(),
+                        Asset::native("uluna", 613571013u128).into(),
+                    ],
+                    slippage_tolerance: Some(Decimal::percent(1)),
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![
+                    Coin::new(49935453833, "uusd"),
+                    Coin::new(613571013, "uluna")
+                ]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // If no swap is needed, the liquidity is provided right away, with the slippage tolerance
+    // forwarded all the same. The first zap is still in progress, so we start from a fresh state
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(
+            AssetList::from(vec![
+                Asset::native("uusd", 118070429547232u128),
+                Asset::native("uluna", 1451993415113u128),
+            ])
+            .into(),
+        ),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: Some(Decimal::percent(1)),
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "alice",
+            &[
+                Coin::new(118070429547232, "uusd"),
+                Coin::new(1451993415113, "uluna"),
+            ],
+        ),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uusd", 118070429547232u128).into(),
+                        Asset::native("uluna", 1451993415113u128).into(),
+                    ],
+                    slippage_tolerance: Some(Decimal::percent(1)),
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![
+                    Coin::new(118070429547232, "uusd"),
+                    Coin::new(1451993415113, "uluna")
+                ]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // The slippage tolerance should also be kept for the pairs entered via the router
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(70000000000),
+        msg: to_binary(&Cw20HookMsg::EnterViaRouter {
+            pair: String::from("luna_ust_pair"),
+            route: vec![SwapOperation::AstroSwap {
+                offer_asset_info: AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+                ask_asset_info: AssetInfo::native("uusd").into(),
+            }],
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            provide_slippage_tolerance: Some(Decimal::percent(1)),
+        })
+        .unwrap(),
+    });
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_token", &[]),
+        msg,
+    )
+    .unwrap();
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.provide_slippage_tolerance, Some(Decimal::percent(1)));
+
+    // The same goes for the CW20 hook
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(750000000000),
+        msg: to_binary(&Cw20HookMsg::Enter {
+            pair: String::from("astro_ust_pair"),
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            deadline: None,
+            max_spread: None,
+            belief_price: None,
+            provide_slippage_tolerance: Some(Decimal::percent(1)),
+            referrer: None,
+        })
+        .unwrap(),
+    });
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_token", &[]),
+        msg,
+    )
+    .unwrap();
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.provide_slippage_tolerance, Some(Decimal::percent(1)));
+}
+
+#[test]
+fn should_update_config() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        fee_bps: Some(30),
+        fee_collector: Some(String::from("bob")),
+        paused: None,
+        allowlist: None,
+        denylist: None,
+        keepers: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[]),
+        msg.clone(),
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err("unauthorized: sender is not owner"))
+    );
+
+    let msg_excessive = ExecuteMsg::UpdateConfig {
+        owner: None,
+        fee_bps: Some(101),
+        fee_collector: None,
+        paused: None,
+        allowlist: None,
+        denylist: None,
+        keepers: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("deployer", &[]),
+        msg_excessive,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "fee cannot exceed 100 bps; received 101"
+        ))
+    );
+
+    execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.owner, Addr::unchecked("deployer"));
+    assert_eq!(config.fee_bps, 30);
+    assert_eq!(config.fee_collector, Addr::unchecked("bob"));
+}
+
+#[test]
+fn should_keep_stored_config_on_migration() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        fee_bps: Some(30),
+        fee_collector: Some(String::from("bob")),
+        paused: Some(true),
+        allowlist: Some(vec![String::from("luna_ust_pair")]),
+        denylist: Some(vec![String::from("astro_ust_pair")]),
+        keepers: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+
+    // Fields already stored are kept, even if provided in the migrate message. In particular, a
+    // paused contract stays paused
+    let msg = MigrateMsg {
+        factory: None,
+        router: None,
+        owner: Some(String::from("alice")),
+        fee_bps: Some(0),
+        fee_collector: None,
+        allowlist: Some(vec![]),
+        denylist: None,
+        keepers: None,
+    };
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.owner, Addr::unchecked("deployer"));
+    assert_eq!(config.fee_bps, 30);
+    assert_eq!(config.fee_collector, Addr::unchecked("bob"));
+    assert!(config.paused);
+    assert_eq!(config.allowlist, vec![Addr::unchecked("luna_ust_pair")]);
+    assert_eq!(config.denylist, vec![Addr::unchecked("astro_ust_pair")]);
+
+    // Suppose the contract was deployed before the router, the owner, and the fee were introduced.
+    // The missing fields must be provided
+    STORED_CONFIG
+        .save(
+            deps.as_mut().storage,
+            &StoredConfig {
+                factory: Some(Addr::unchecked(FACTORY_ADDR)),
+                ..StoredConfig::default()
+            },
+        )
+        .unwrap();
+
+    let msg = MigrateMsg {
+        factory: None,
+        router: None,
+        owner: Some(String::from("deployer")),
+        fee_bps: Some(0),
+        fee_collector: Some(String::from("fee_collector")),
+        allowlist: None,
+        denylist: None,
+        keepers: None,
+    };
+    let err = migrate(deps.as_mut(), mock_env(), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "router is not in the stored config and must be provided"
+        ))
+    );
+
+    let msg = MigrateMsg {
+        factory: None,
+        router: Some(String::from(ROUTER_ADDR)),
+        owner: Some(String::from("deployer")),
+        fee_bps: Some(0),
+        fee_collector: Some(String::from("fee_collector")),
+        allowlist: None,
+        denylist: None,
+        keepers: None,
+    };
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.factory, Addr::unchecked(FACTORY_ADDR));
+    assert_eq!(config.router, Addr::unchecked(ROUTER_ADDR));
+    assert_eq!(config.owner, Addr::unchecked("deployer"));
+    assert_eq!(config.fee_bps, 0);
+    assert_eq!(config.fee_collector, Addr::unchecked("fee_collector"));
+    assert!(!config.paused);
+    assert_eq!(config.allowlist, Vec::<Addr>::new());
+    assert_eq!(config.denylist, Vec::<Addr>::new());
+    assert_eq!(config.keepers, Vec::<Addr>::new());
+}
+
+#[test]
+fn should_pause_and_restrict_pairs() {
+    let mut deps = setup_test();
+
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        ConfigResponse {
+            factory: String::from(FACTORY_ADDR),
+            router: String::from(ROUTER_ADDR),
+            owner: String::from("deployer"),
+            fee_bps: 0,
+            fee_collector: String::from("fee_collector"),
+            paused: false,
+            allowlist: vec![],
+            denylist: vec![],
+            keepers: vec![],
+        }
+    );
+
+    let update_config =
+        |paused: bool, allowlist: &[&str], denylist: &[&str]| ExecuteMsg::UpdateConfig {
+            owner: None,
+            fee_bps: None,
+            fee_collector: None,
+            paused: Some(paused),
+            allowlist: Some(allowlist.iter().map(|pair| String::from(*pair)).collect()),
+            denylist: Some(denylist.iter().map(|pair| String::from(*pair)).collect()),
+            keepers: None,
+        };
+    let enter = |pair: &str| ExecuteMsg::Enter {
+        pair: String::from(pair),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 12345u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let alice = mock_info("alice", &[Coin::new(12345, "uusd")]);
+
+    // While paused, entering any pair should fail, while exiting should still be allowed
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("deployer", &[]),
+        update_config(true, &[], &[]),
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        alice.clone(),
+        enter("luna_ust_pair"),
+    );
+    assert_eq!(err, Err(StdError::generic_err("contract is paused")));
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::MigrateLiquidity {
+            from_pair: String::from("luna_ust_pair"),
+            to_pair: String::from("astro_ust_pair"),
+            minimum_received: None,
+            route: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_ust_lp_token", &[]),
+        msg,
+    );
+    assert_eq!(err, Err(StdError::generic_err("contract is paused")));
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("luna_ust_pair"),
+            ask_asset: AssetInfo::native("uusd").into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_ust_lp_token", &[]),
+        msg,
+    )
+    .unwrap();
+
+    // Suppose the exit is done, so that its cache is cleared
+    EXIT_CACHE.remove(deps.as_mut().storage, 1u64.into());
+
+    // With an allowlist, only the pairs in it can be entered
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("deployer", &[]),
+        update_config(false, &["astro_ust_pair"], &[]),
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        alice.clone(),
+        enter("luna_ust_pair"),
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair luna_ust_pair is not in the allowlist"
+        ))
+    );
+
+    // Pairs in the denylist cannot be entered
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("deployer", &[]),
+        update_config(false, &[], &["luna_ust_pair"]),
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        alice.clone(),
+        enter("luna_ust_pair"),
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair luna_ust_pair is in the denylist"
+        ))
+    );
+
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert!(!res.paused);
+    assert!(res.allowlist.is_empty());
+    assert_eq!(res.denylist, vec![String::from("luna_ust_pair")]);
+
+    // Other pairs can still be entered
+    execute(deps.as_mut(), mock_env(), alice, enter("astro_ust_pair")).unwrap();
+}
+
+#[test]
+fn should_enter_with_fees() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        fee_bps: Some(30),
+        fee_collector: None,
+        paused: None,
+        allowlist: None,
+        denylist: None,
+        keepers: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+
+    let enter_msg = |referral_fee_bps: u64| ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: Some(Referrer {
+            address: String::from("referrer"),
+            fee_bps: referral_fee_bps,
+        }),
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        enter_msg(51),
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "referral fee cannot exceed 50 bps; received 51"
+        ))
+    );
+
+    // The protocol fee is 100000000000 * 0.003 = 300000000 uusd, and the referral fee is
+    // 100000000000 * 0.002 = 200000000 uusd, leaving 99500000000 uusd to enter the pool with
+    //
+    // Solving the same way as in `should_enter_native_native_pool`, with:
+    // offer_user = 99500000000
+    // offer_pool = 118070429547232
+    // ask_user = 0
+    // ask_pool = 1451993415113
+    //
+    // Should find solution: offer_amount = 49814275993
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uusd")]),
+        enter_msg(20),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("fee_collector"),
+            amount: vec![Coin::new(300000000, "uusd")]
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("referrer"),
+            amount: vec![Coin::new(200000000, "uusd")]
+        }))
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 49814275993u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(49814275993, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+    assert_eq!(
+        res.attributes[2],
+        Attribute::new("protocol_fee", "native:uusd:300000000")
+    );
+    assert_eq!(
+        res.attributes[3],
+        Attribute::new("referral_fee", "native:uusd:200000000")
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![Asset::native("uusd", 49685724007u128)])
+    );
+}
+
+#[test]
+fn should_charge_fees_on_enter_paths_only() {
+    let setup_test_with_fee = || {
+        let mut deps = setup_test();
+        let msg = ExecuteMsg::UpdateConfig {
+            owner: None,
+            fee_bps: Some(30),
+            fee_collector: None,
+            paused: None,
+            allowlist: None,
+            denylist: None,
+            keepers: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+        deps
+    };
+    let fee_transfer = |token: &str, amount: u128| {
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: String::from(token),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: String::from("fee_collector"),
+                amount: Uint128::new(amount),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    };
+
+    // EnterViaRouter: the fee is 70000000000 * 0.003 = 210000000 ASTRO, and the remaining
+    // 69790000000 ASTRO is sent to the router
+    let mut deps = setup_test_with_fee();
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(70000000000),
+        msg: to_binary(&Cw20HookMsg::EnterViaRouter {
+            pair: String::from("luna_ust_pair"),
+            route: vec![SwapOperation::AstroSwap {
+                offer_asset_info: AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+                ask_asset_info: AssetInfo::native("uusd").into(),
+            }],
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            provide_slippage_tolerance: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0], fee_transfer("astro_token", 210000000));
+    assert_eq!(
+        res.attributes[2],
+        Attribute::new("protocol_fee", "cw20:astro_token:210000000")
+    );
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.assets, AssetList::new());
+
+    // EnterMany: the fee is 200000000000 * 0.003 = 600000000 uusd, deducted before splitting
+    let mut deps = setup_test_with_fee();
+    let msg = ExecuteMsg::EnterMany {
+        targets: vec![EnterTarget {
+            pair: String::from("luna_ust_pair"),
+            weight: 1,
+            minimum_received: None,
+        }],
+        deposits: AssetList::from(vec![Asset::native("uusd", 200000000000u128)]).into(),
+        recipient: None,
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(200000000000, "uusd")]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("fee_collector"),
+            amount: vec![Coin::new(600000000, "uusd")]
+        }))
+    );
+    assert_eq!(
+        res.attributes[2],
+        Attribute::new("protocol_fee", "native:uusd:600000000")
+    );
+
+    // MigrateLiquidity and Compound are not charged, so they should behave the same as without a
+    // fee
+    let migrate = |deps: &mut OwnedDeps<MockStorage, CustomMockApi, CustomMockQuerier>| {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("alice"),
+            amount: Uint128::new(5481424982),
+            msg: to_binary(&Cw20HookMsg::MigrateLiquidity {
+                from_pair: String::from("luna_ust_pair"),
+                to_pair: String::from("astro_ust_pair"),
+                minimum_received: None,
+                route: None,
+            })
+            .unwrap(),
+        });
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("luna_ust_lp_token", &[]),
+            msg,
+        )
+        .unwrap()
+    };
+    assert_eq!(
+        migrate(&mut setup_test_with_fee()),
+        migrate(&mut setup_test())
+    );
+
+    let compound = |deps: &mut OwnedDeps<MockStorage, CustomMockApi, CustomMockQuerier>| {
+        deps.querier
+            .set_reward_info("astro_ust_lp_token", "astro_token", None);
+        deps.querier.set_pending_rewards(
+            "astro_ust_lp_token",
+            MOCK_CONTRACT_ADDR,
+            1000000000,
+            None,
+        );
+        let msg = ExecuteMsg::Compound {
+            pair: String::from("astro_ust_pair"),
+            routes: vec![],
+            minimum_received: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+        let _reply = Reply {
+            id: 8,
+            result: ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), _reply).unwrap()
+    };
+    assert_eq!(
+        compound(&mut setup_test_with_fee()),
+        compound(&mut setup_test())
+    );
+}
+
+#[test]
+fn should_enter_by_assets() {
+    let mut deps = setup_test();
+    let mut deps_by_addr = setup_test();
+
+    // Entering by the pair's assets, specified in either order, should be the same as entering by
+    // the pair's address
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("luna_ust_pair"),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let info = mock_info("alice", &[Coin::new(100000000000, "uusd")]);
+    let expected = execute(deps_by_addr.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+    let msg = ExecuteMsg::EnterByAssets {
+        asset_infos: [
+            AssetInfo::native("uluna").into(),
+            AssetInfo::native("uusd").into(),
+        ],
+        pair_type: Some(PairType::Xyk {}),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    assert_eq!(res, expected);
+    assert_eq!(
+        CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap(),
+        CACHE
+            .load(deps_by_addr.as_ref().storage, 1u64.into())
+            .unwrap()
+    );
+
+    // The pair found must be of the specified type
+    let msg = ExecuteMsg::EnterByAssets {
+        asset_infos: [
+            AssetInfo::native("uusd").into(),
+            AssetInfo::native("uluna").into(),
+        ],
+        pair_type: Some(PairType::Stable {}),
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair luna_ust_pair is of type xyk; expected stable"
+        ))
+    );
+
+    // There must be a pair registered for the assets
+    let msg = ExecuteMsg::EnterByAssets {
+        asset_infos: [
+            AssetInfo::native("uusd").into(),
+            AssetInfo::native("ukrw").into(),
+        ],
+        pair_type: None,
+        deposits: Some(AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info, msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "no pair registered with the factory for assets native:uusd and native:ukrw"
+        ))
+    );
+}
+
+#[test]
+fn should_enter_many() {
+    let mut deps = setup_test();
+
+    // With weights 1 and 3, luna_ust_pair should receive 200000000001 * 1 / 4 = 50000000000 uusd,
+    // and astro_ust_pair the remaining 150000000001 uusd
+    let msg = ExecuteMsg::EnterMany {
+        targets: vec![
+            EnterTarget {
+                pair: String::from("luna_ust_pair"),
+                weight: 1,
+                minimum_received: None,
+            },
+            EnterTarget {
+                pair: String::from("astro_ust_pair"),
+                weight: 3,
+                minimum_received: None,
+            },
+        ],
+        deposits: AssetList::from(vec![Asset::native("uusd", 200000000001u128)]).into(),
+        recipient: None,
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(200000000001, "uusd")]),
+        msg,
+    )
+    .unwrap();
+
+    // Solving the same way as in `should_enter_native_native_pool`, with:
+    // offer_user = 50000000000
+    // offer_pool = 118070429547232
+    // ask_user = 0
+    // ask_pool = 1451993415113
+    //
+    // Should find solution: offer_amount = 25034914144
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 25034914144u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(25034914144, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.pair_addr, Addr::unchecked("luna_ust_pair"));
+    assert_eq!(
+        cache.pending,
+        vec![PendingEnter {
+            pair_addr: Addr::unchecked("astro_ust_pair"),
+            liquidity_token_addr: Addr::unchecked("astro_ust_lp_token"),
+            assets: AssetList::from(vec![Asset::native("uusd", 150000000001u128)]),
+            minimum_received: None,
+        }]
+    );
+
+    // Offering 25034914144 uusd should return 306882903 uluna after commission
+    //
+    // The pool should have:
+    // uusd: 118070429547232 + 25034914144 = 118095464461376
+    // uluna: 1451993415113 - 306882903 = 1451686532210
+    deps.querier.set_pool(
+        "luna_ust_pair",
+        PoolResponse {
+            assets: [
+                Asset::native("uusd", 118095464461376u128).into(),
+                Asset::native("uluna", 1451686532210u128).into(),
+            ],
+            total_share: Uint128::new(12966110801826u128),
+        },
+    );
+
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uluna")
+                .add_attribute("return_amount", "306882903")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uusd", 24965085856u128).into(),
+                        Asset::native("uluna", 306882903u128).into(),
+                    ],
+                    slippage_tolerance: None,
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![
+                    Coin::new(24965085856, "uusd"),
+                    Coin::new(306882903, "uluna")
+                ]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // Once luna_ust_pair mints 2741003398 uLP, they should be transferred to the user, and we should
+    // move on to astro_ust_pair
+    //
+    // Solving the same way as in `should_enter_cw20_native_pool`, with:
+    // offer_user = 150000000001
+    // offer_pool = 65155920988539
+    // ask_user = 0
+    // ask_pool = 48059201882191
+    //
+    // Should find solution: offer_amount = 75069617803
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "2741003398")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_lp_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                    recipient: String::from("alice"),
+                    amount: Uint128::new(2741003398)
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 75069617803u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(75069617803, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.pair_addr, Addr::unchecked("astro_ust_pair"));
+    assert_eq!(
+        cache.liquidity_token_addr,
+        Addr::unchecked("astro_ust_lp_token")
+    );
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![Asset::native("uusd", 74930382198u128)])
+    );
+    assert!(cache.pending.is_empty());
+
+    // Offering 75069617803 uusd should return 55141927572 uASTRO after commission, which should be
+    // provided along with the remaining uusd
+    deps.querier.set_pool(
+        "astro_ust_pair",
+        PoolResponse {
+            assets: [
+                Asset::cw20(Addr::unchecked("astro_token"), 48004059954619u128).into(),
+                Asset::native("uusd", 65230990606342u128).into(),
+            ],
+            total_share: Uint128::new(55851193190261u128),
+        },
+    );
+
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "astro_token")
+                .add_attribute("return_amount", "55141927572")],
+            data: None,
+        }),
+    };
+    reply(deps.as_mut(), mock_env(), _reply).unwrap();
+
+    // Once astro_ust_pair mints 64155874578 uLP, they should be transferred to the user, and the
+    // cache should be cleared
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "64155874578")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_lp_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                    recipient: String::from("alice"),
+                    amount: Uint128::new(64155874578)
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+    assert!(CACHE
+        .may_load(deps.as_ref().storage, 1u64.into())
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn should_reject_invalid_enter_many() {
+    let mut deps = setup_test();
+
+    let mut enter_many = |targets: &[(&str, u64)], deposit: Asset| {
+        let msg = ExecuteMsg::EnterMany {
+            targets: targets
+                .iter()
+                .map(|(pair, weight)| EnterTarget {
+                    pair: String::from(*pair),
+                    weight: *weight,
+                    minimum_received: None,
+                })
+                .collect(),
+            deposits: AssetList::from(vec![deposit]).into(),
+            recipient: None,
+            auto_stake: None,
+            provide_slippage_tolerance: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg)
+    };
+
+    assert_eq!(
+        enter_many(&[], Asset::native("uusd", 12345u128)),
+        Err(StdError::generic_err("must specify at least one target"))
+    );
+    assert_eq!(
+        enter_many(
+            &[("luna_ust_pair", 1), ("astro_ust_pair", 0)],
+            Asset::native("uusd", 12345u128)
+        ),
+        Err(StdError::generic_err(
+            "target weights must be greater than zero"
+        ))
+    );
+    assert_eq!(
+        enter_many(
+            &[("luna_ust_pair", 1), ("bluna_luna_pair", 1)],
+            Asset::native("uusd", 12345u128)
+        ),
+        Err(StdError::generic_err(
+            "pair does not contain asset native:uusd"
+        ))
+    );
+    assert_eq!(
+        enter_many(
+            &[("luna_ust_pair", 1), ("astro_luna_pair", 1)],
+            Asset::native("uluna", 12345u128)
+        ),
+        Err(StdError::generic_err(
+            "cannot enter empty pool astro_luna_pair in a batch"
+        ))
+    );
+    assert_eq!(
+        enter_many(
+            &[("luna_ust_pair", 1), ("astro_ust_pair", 1)],
+            Asset::native("uusd", 1u128)
+        ),
+        Err(StdError::generic_err(
+            "deposits are too small to be split; luna_ust_pair receives nothing"
+        ))
+    );
+}
+
+#[test]
+fn should_enter_via_router() {
+    let mut deps = setup_test();
+
+    let route = vec![SwapOperation::AstroSwap {
+        offer_asset_info: AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+        ask_asset_info: AssetInfo::native("uusd").into(),
+    }];
+
+    // ASTRO is not in the LUNA-UST pool, so it should first be sent to the router to be swapped
+    // into UST
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(70000000000),
+        msg: to_binary(&Cw20HookMsg::EnterViaRouter {
+            pair: String::from("luna_ust_pair"),
+            route: route.clone(),
+            minimum_received: None,
+            recipient: None,
+            auto_stake: None,
+            provide_slippage_tolerance: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 5,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from(ROUTER_ADDR),
+                    amount: Uint128::new(70000000000),
+                    msg: to_binary(&astroport::router::Cw20HookMsg::ExecuteSwapOperations {
+                        operations: route,
+                        minimum_receive: None,
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // The contract holds no UST before the swap
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.assets, AssetList::new());
+    assert_eq!(cache.intermediate_asset, Some(Asset::native("uusd", 0u128)));
+
+    // Suppose the router returns 100000000000 uusd. From here on, the zap should proceed the same
+    // way as in `should_enter_native_native_pool`
+    deps.querier
+        .set_native_balance(MOCK_CONTRACT_ADDR, &[Coin::new(100000000000, "uusd")]);
+
+    let _reply = Reply {
+        id: 5,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 50064546167u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(50064546167, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+    assert_eq!(
+        res.attributes[1],
+        Attribute::new("asset_received", "native:uusd:100000000000")
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![Asset::native("uusd", 49935453833u128)])
+    );
+    assert_eq!(cache.intermediate_asset, None);
+}
+
+#[test]
+fn should_enter_via_router_with_native_deposit() {
+    let mut deps = setup_test();
+
+    let route = vec![SwapOperation::AstroSwap {
+        offer_asset_info: AssetInfo::native("uluna").into(),
+        ask_asset_info: AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+    }];
+
+    // The contract already holds some ASTRO, which should not be counted as received from the router
+    deps.querier
+        .set_cw20_balance("astro_token", MOCK_CONTRACT_ADDR, 12345);
+
+    let msg = ExecuteMsg::EnterViaRouter {
+        pair: String::from("astro_ust_pair"),
+        deposit: Asset::native("uluna", 10000000000u128).into(),
+        route: route.clone(),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(10000000000, "uluna")]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 5,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from(ROUTER_ADDR),
+                msg: to_binary(&astroport::router::ExecuteMsg::ExecuteSwapOperations {
+                    operations: route,
+                    minimum_receive: None,
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(10000000000, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(
+        cache.intermediate_asset,
+        Some(Asset::cw20(Addr::unchecked("astro_token"), 12345u128))
+    );
+
+    // Suppose the router returns 750000000000 uASTRO. From here on, the zap should proceed the same
+    // way as in `should_enter_via_cw20_hook`
+    deps.querier
+        .set_cw20_balance("astro_token", MOCK_CONTRACT_ADDR, 750000012345);
+
+    let _reply = Reply {
+        id: 5,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("astro_ust_pair"),
+                    amount: Uint128::new(374113795679),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::Swap {
+                        belief_price: None,
+                        max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+}
+
+#[test]
+fn should_reject_invalid_route() {
+    let mut deps = setup_test();
+
+    let mut msg = ExecuteMsg::EnterViaRouter {
+        pair: String::from("astro_ust_pair"),
+        deposit: Asset::native("uluna", 100000000u128).into(),
+        route: vec![],
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+
+    let mut assert_error = |route: Vec<SwapOperation>, expected: &str| {
+        if let ExecuteMsg::EnterViaRouter { route: r, .. } = &mut msg {
+            *r = route;
+        }
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[Coin::new(100000000, "uluna")]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, StdError::generic_err(expected));
+    };
+
+    assert_error(vec![], "route must not be empty");
+
+    // The route must start from the deposited asset
+    assert_error(
+        vec![SwapOperation::NativeSwap {
+            offer_denom: String::from("ukrw"),
+            ask_denom: String::from("uusd"),
+        }],
+        "invalid route: expecting swap from native:uluna, found native:ukrw",
+    );
+
+    // The route must end in one of the pool's assets
+    assert_error(
+        vec![SwapOperation::NativeSwap {
+            offer_denom: String::from("uluna"),
+            ask_denom: String::from("ukrw"),
+        }],
+        "invalid route: native:ukrw is not in the pool",
+    );
+
+    // The route must not end in the deposited asset, even if the pool contains it
+    let msg = ExecuteMsg::EnterViaRouter {
+        pair: String::from("luna_ust_pair"),
+        deposit: Asset::native("uluna", 100000000u128).into(),
+        route: vec![
+            SwapOperation::NativeSwap {
+                offer_denom: String::from("uluna"),
+                ask_denom: String::from("uusd"),
+            },
+            SwapOperation::NativeSwap {
+                offer_denom: String::from("uusd"),
+                ask_denom: String::from("uluna"),
+            },
+        ],
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        provide_slippage_tolerance: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000, "uluna")]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "invalid route: must not end in the deposited asset native:uluna"
+        ))
+    );
+}
+
+#[test]
+fn should_reject_excessive_slippage() {
+    let mut deps = setup_test();
+
+    ZAP_ID.save(deps.as_mut().storage, &1).unwrap();
+    CACHE
+        .save(
+            deps.as_mut().storage,
+            1u64.into(),
+            &CacheData {
+                user_addr: Addr::unchecked("alice"),
+                recipient_addr: Addr::unchecked("alice"),
+                pair_addr: Addr::unchecked("luna_ust_pair"),
+                liquidity_token_addr: Addr::unchecked("luna_ust_lp_token"),
+                assets: AssetList::default(),
+                minimum_received: Some(Uint128::new(20000)),
+                auto_stake: false,
+                provide_slippage_tolerance: None,
+                intermediate_asset: None,
+                source_pair_addr: None,
+                routes: vec![],
+                pending: vec![],
+                compounding: false,
+            },
+        )
+        .unwrap();
+
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "12345")],
+            data: None,
+        }),
+    };
+    let err = reply(deps.as_mut(), mock_env(), _reply);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "too little received! minimum: 20000, received 12345"
+        ))
+    );
+}
+
+#[test]
+fn should_reject_invalid_liquidity_token() {
+    let mut deps = setup_test();
+
+    // User sends the liquidity token of the ASTRO-UST pair, but wants to exit the LUNA-UST pair
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("luna_ust_pair"),
+            ask_asset: AssetInfo::native("uusd").into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_ust_lp_token", &[]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "invalid liquidity token: expected cw20:luna_ust_lp_token, received cw20:astro_ust_lp_token"
+        ))
+    );
+
+    // User wants to receive an asset that is not in the pair
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(12345),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("luna_ust_pair"),
+            ask_asset: AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_ust_lp_token", &[]),
+        msg,
+    );
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "pair does not contain asset cw20:astro_token"
+        ))
+    );
+}
+
+#[test]
+fn should_exit_native_native_pool() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(5481424982),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("luna_ust_pair"),
+            ask_asset: AssetInfo::native("uusd").into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_ust_lp_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 3,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_lp_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("luna_ust_pair"),
+                    amount: Uint128::new(5481424982),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::WithdrawLiquidity {}).unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // Burning 5481424982 uLP should return:
+    // uusd: 118070429547232 * 5481424982 / 12966110801826 = 49914289029
+    // uluna: 1451993415113 * 5481424982 / 12966110801826 = 613830399
+    //
+    // The uluna should be swapped for uusd
+    let _reply = Reply {
+        id: 3,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "withdraw_liquidity")
+                .add_attribute("withdrawn_share", "5481424982")
+                .add_attribute("refund_assets", "49914289029uusd, 613830399uluna")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 4,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uluna", 613830399u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(613830399, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = EXIT_CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![Asset::native("uusd", 49914289029u128)])
+    );
+
+    // Suppose the swap returns 49761021837 uusd, the user should receive a total of
+    // 49914289029 + 49761021837 = 99675310866 uusd
+    let _reply = Reply {
+        id: 4,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uusd")
+                .add_attribute("return_amount", "49761021837")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("alice"),
+            amount: vec![Coin::new(99675310866, "uusd")]
+        }))
+    );
+
+    // The cache should have been cleared
+    assert!(!EXIT_CACHE.has(deps.as_ref().storage, 1u64.into()));
+}
+
+#[test]
+fn should_exit_cw20_native_pool() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(476696702710),
+        msg: to_binary(&Cw20HookMsg::Exit {
+            pair: String::from("astro_ust_pair"),
+            ask_asset: AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+            minimum_received: None,
+        })
+        .unwrap(),
+    });
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_ust_lp_token", &[]),
+        msg,
+    )
+    .unwrap();
+
+    // The uusd should be swapped for ASTRO
+    let _reply = Reply {
+        id: 3,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "withdraw_liquidity")
+                .add_attribute("withdrawn_share", "476696702710")
+                .add_attribute("refund_assets", "410191112552astro_token, 556113682145uusd")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 4,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 556113682145u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(556113682145, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let _reply = Reply {
+        id: 4,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "astro_token")
+                .add_attribute("return_amount", "403012345678")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: String::from("astro_token"),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: String::from("alice"),
+                amount: Uint128::new(813203458230)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+}
+
+#[test]
+fn should_reject_excessive_exit_slippage() {
+    let mut deps = setup_test();
+
+    ZAP_ID.save(deps.as_mut().storage, &1).unwrap();
+    EXIT_CACHE
+        .save(
+            deps.as_mut().storage,
+            1u64.into(),
+            &ExitCacheData {
+                user_addr: Addr::unchecked("alice"),
+                pair_addr: Addr::unchecked("luna_ust_pair"),
+                ask_asset_info: AssetInfo::native("uusd"),
+                assets: AssetList::from(vec![Asset::native("uusd", 10000u128)]),
+                minimum_received: Some(Uint128::new(30000)),
+            },
+        )
+        .unwrap();
+
+    let _reply = Reply {
+        id: 4,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uusd")
+                .add_attribute("return_amount", "12345")],
+            data: None,
+        }),
+    };
+    let err = reply(deps.as_mut(), mock_env(), _reply);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "too little received! minimum: 30000, received 22345"
+        ))
+    );
+}
+
+#[test]
+fn should_migrate_liquidity() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(5481424982),
+        msg: to_binary(&Cw20HookMsg::MigrateLiquidity {
+            from_pair: String::from("luna_ust_pair"),
+            to_pair: String::from("astro_ust_pair"),
+            minimum_received: None,
+            route: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("luna_ust_lp_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 6,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_lp_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("luna_ust_pair"),
+                    amount: Uint128::new(5481424982),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::WithdrawLiquidity {}).unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // Same as in `should_exit_native_native_pool`, burning 5481424982 uLP should return
+    // 49914289029 uusd + 613830399 uluna
+    //
+    // astro_ust_pair does not contain uluna, so it should be swapped for uusd in luna_ust_pair
+    let _reply = Reply {
+        id: 6,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "withdraw_liquidity")
+                .add_attribute("withdrawn_share", "5481424982")
+                .add_attribute("refund_assets", "49914289029uusd, 613830399uluna")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 7,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("luna_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uluna", 613830399u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(613830399, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.pair_addr, Addr::unchecked("astro_ust_pair"));
+    assert_eq!(
+        cache.liquidity_token_addr,
+        Addr::unchecked("astro_ust_lp_token")
+    );
+    assert_eq!(
+        cache.source_pair_addr,
+        Some(Addr::unchecked("luna_ust_pair"))
+    );
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![Asset::native("uusd", 49914289029u128)])
+    );
+
+    // Suppose the swap returns 49761021837 uusd, we should enter astro_ust_pair with a total of
+    // 49914289029 + 49761021837 = 99675310866 uusd
+    //
+    // Solving the same way as in `should_enter_cw20_native_pool`, with:
+    // offer_user = 99675310866
+    // offer_pool = 65155920988539
+    // ask_user = 0
+    // ask_pool = 48059201882191
+    //
+    // Should find solution: offer_amount = 49893507069
+    let _reply = Reply {
+        id: 7,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uusd")
+                .add_attribute("return_amount", "49761021837")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 49893507069u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(49893507069, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // Offering 49893507069 uusd should return 36663120013 uASTRO after commission
+    //
+    // The pool should have:
+    // uASTRO: 48059201882191 - 36663120013 = 48022538762178
+    // uusd: 65155920988539 + 49893507069 = 65205814495608
+    deps.querier.set_pool(
+        "astro_ust_pair",
+        PoolResponse {
+            assets: [
+                Asset::cw20(Addr::unchecked("astro_token"), 48022538762178u128).into(),
+                Asset::native("uusd", 65205814495608u128).into(),
+            ],
+            total_share: Uint128::new(55851193190261u128),
+        },
+    );
+
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "astro_token")
+                .add_attribute("return_amount", "36663120013")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 2,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                    assets: [
+                        Asset::native("uusd", 49781803795u128).into(),
+                        Asset::cw20(Addr::unchecked("astro_token"), 36663120013u128).into(),
+                    ],
+                    slippage_tolerance: None,
+                    auto_stake: None,
+                    receiver: None
+                })
+                .unwrap(),
+                funds: vec![Coin::new(49781803795, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success
+        }
+    );
+
+    // Minting 42639957228 uLP requires only 49781803795 uusd out of the 49781803797 available, so
+    // the excess 2 uusd should be refunded along with the liquidity tokens
+    let _reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "provide_liquidity")
+                .add_attribute("share", "42639957228")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: String::from("alice"),
+            amount: vec![Coin::new(2, "uusd")]
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_lp_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                    recipient: String::from("alice"),
+                    amount: Uint128::new(42639957228)
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+}
+
+#[test]
+fn should_migrate_liquidity_via_router() {
+    let mut deps = setup_test();
+
+    let route = vec![SwapOperation::AstroSwap {
+        offer_asset_info: AssetInfo::native("uusd").into(),
+        ask_asset_info: AssetInfo::native("uluna").into(),
+    }];
+
+    // The sender has approved allowance, so the liquidity tokens should be drawn from their wallet
+    let msg = ExecuteMsg::MigrateLiquidity {
+        from_pair: String::from("astro_ust_pair"),
+        to_pair: String::from("bluna_luna_pair"),
+        amount: Uint128::new(476696702710),
+        minimum_received: None,
+        route: Some(route.clone()),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: String::from("astro_ust_lp_token"),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::TransferFrom {
+                owner: String::from("alice"),
+                recipient: String::from(MOCK_CONTRACT_ADDR),
+                amount: Uint128::new(476696702710)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+    assert_eq!(res.messages[1].id, 6);
+
+    // bluna_luna_pair shares no asset with astro_ust_pair. The route starts from uusd, so the ASTRO
+    // withdrawn should first be swapped for uusd in astro_ust_pair
+    let _reply = Reply {
+        id: 6,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "withdraw_liquidity")
+                .add_attribute("withdrawn_share", "476696702710")
+                .add_attribute("refund_assets", "410191112552astro_token, 556113682145uusd")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 7,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from("astro_ust_pair"),
+                    amount: Uint128::new(410191112552),
+                    msg: to_binary(&astroport::pair::Cw20HookMsg::Swap {
+                        belief_price: None,
+                        max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // Suppose the swap returns 551234567890 uusd. The total of 556113682145 + 551234567890 =
+    // 1107348250035 uusd should be sent to the router to be swapped into uluna
+    let _reply = Reply {
+        id: 7,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "uusd")
+                .add_attribute("return_amount", "551234567890")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 5,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from(ROUTER_ADDR),
+                msg: to_binary(&astroport::router::ExecuteMsg::ExecuteSwapOperations {
+                    operations: route,
+                    minimum_receive: None,
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(1107348250035, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // The contract holds no uluna before the swap
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(cache.assets, AssetList::new());
+    assert_eq!(
+        cache.intermediate_asset,
+        Some(Asset::native("uluna", 0u128))
+    );
+    assert!(cache.routes.is_empty());
+
+    // Suppose the router returns 100000000000 uluna. From here on, the zap should proceed the same
+    // way as entering bluna_luna_pair in `should_query_simulate_stable`
+    deps.querier
+        .set_native_balance(MOCK_CONTRACT_ADDR, &[Coin::new(100000000000, "uluna")]);
+
+    let _reply = Reply {
+        id: 5,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("bluna_luna_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uluna", 49395594273u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(49395594273, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+}
+
+#[test]
+fn should_reject_invalid_liquidity_migration() {
+    let mut deps = setup_test();
+
+    let mut migrate = |lp_token: &str, from_pair: &str, to_pair: &str, route: Option<Vec<_>>| {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("alice"),
+            amount: Uint128::new(12345),
+            msg: to_binary(&Cw20HookMsg::MigrateLiquidity {
+                from_pair: String::from(from_pair),
+                to_pair: String::from(to_pair),
+                minimum_received: None,
+                route,
+            })
+            .unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info(lp_token, &[]), msg)
+    };
+    let swap = |offer_info: AssetInfo, ask_info: AssetInfo| SwapOperation::AstroSwap {
+        offer_asset_info: offer_info.into(),
+        ask_asset_info: ask_info.into(),
+    };
+
+    assert_eq!(
+        migrate("luna_ust_lp_token", "luna_ust_pair", "luna_ust_pair", None),
+        Err(StdError::generic_err(
+            "cannot migrate liquidity into the same pair"
+        ))
+    );
+    assert_eq!(
+        migrate("astro_ust_lp_token", "luna_ust_pair", "astro_ust_pair", None),
+        Err(StdError::generic_err(
+            "invalid liquidity token: expected cw20:luna_ust_lp_token, received cw20:astro_ust_lp_token"
+        ))
+    );
+    assert_eq!(
+        migrate(
+            "luna_ust_lp_token",
+            "luna_ust_pair",
+            "astro_luna_pair",
+            None
+        ),
+        Err(StdError::generic_err(
+            "cannot migrate liquidity into an empty pool"
+        ))
+    );
+    assert_eq!(
+        migrate(
+            "astro_ust_lp_token",
+            "astro_ust_pair",
+            "bluna_luna_pair",
+            None
+        ),
+        Err(StdError::generic_err(
+            "pairs do not share any asset; route must be provided"
+        ))
+    );
+    assert_eq!(
+        migrate(
+            "luna_ust_lp_token",
+            "luna_ust_pair",
+            "astro_ust_pair",
+            Some(vec![swap(
+                AssetInfo::native("uluna"),
+                AssetInfo::native("uusd")
+            )])
+        ),
+        Err(StdError::generic_err(
+            "route can only be provided if the pairs do not share any asset"
+        ))
+    );
+    assert_eq!(
+        migrate(
+            "astro_ust_lp_token",
+            "astro_ust_pair",
+            "bluna_luna_pair",
+            Some(vec![])
+        ),
+        Err(StdError::generic_err("route must not be empty"))
+    );
+    assert_eq!(
+        migrate(
+            "astro_ust_lp_token",
+            "astro_ust_pair",
+            "bluna_luna_pair",
+            Some(vec![swap(
+                AssetInfo::native("uluna"),
+                AssetInfo::native("uusd")
+            )])
+        ),
+        Err(StdError::generic_err(
+            "invalid route: native:uluna is not in the source pool"
+        ))
+    );
+    assert_eq!(
+        migrate(
+            "astro_ust_lp_token",
+            "astro_ust_pair",
+            "bluna_luna_pair",
+            Some(vec![swap(
+                AssetInfo::native("uusd"),
+                AssetInfo::native("ukrw")
+            )])
+        ),
+        Err(StdError::generic_err(
+            "invalid route: native:ukrw is not in the pool"
+        ))
+    );
+}
+
+#[test]
+fn should_stake_and_unstake() {
+    let mut deps = setup_test();
+    deps.querier
+        .set_reward_info("astro_ust_lp_token", "astro_token", Some("proxy_token"));
+
+    // Nothing is staked yet, so shares should be issued against the virtual 1000000 shares backed
+    // by one liquidity token, i.e. 1000000 shares per liquidity token
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("alice"),
+        amount: Uint128::new(1000),
+        msg: to_binary(&Cw20HookMsg::Stake {
+            pair: String::from("astro_ust_pair"),
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_ust_lp_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: String::from("astro_ust_lp_token"),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                contract: String::from(GENERATOR_ADDR),
+                amount: Uint128::new(1000),
+                msg: to_binary(&astroport::generator::Cw20HookMsg::Deposit {}).unwrap(),
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+    assert_eq!(
+        res.attributes[2],
+        Attribute::new("shares_issued", "1000000000")
+    );
+
+    // Suppose compounding has grown the stake to 1200 liquidity tokens, and some rewards are
+    // pending. Bob's 500 liquidity tokens should be worth 500 * 1001000000 / 1201 = 416736053
+    // shares, and the pending rewards, which the generator pays out upon deposit, should be
+    // recorded
+    deps.querier
+        .set_generator_deposit("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 1200);
+    deps.querier
+        .set_pending_rewards("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 50, Some(20));
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: String::from("bob"),
+        amount: Uint128::new(500),
+        msg: to_binary(&Cw20HookMsg::Stake {
+            pair: String::from("astro_ust_pair"),
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("astro_ust_lp_token", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes[2],
+        Attribute::new("shares_issued", "416736053")
+    );
+
+    let lp_token_addr = Addr::unchecked("astro_ust_lp_token");
+    let rewards = REWARDS.load(deps.as_ref().storage, &lp_token_addr).unwrap();
+    assert_eq!(
+        rewards,
+        AssetList::from(vec![
+            Asset::cw20(Addr::unchecked("astro_token"), 50u128),
+            Asset::cw20(Addr::unchecked("proxy_token"), 20u128),
+        ])
+    );
+
+    // Bob's shares are worth 416736053 * 1701 / 1417736053 = 499 liquidity tokens
+    deps.querier
+        .set_generator_deposit("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 1700);
+    deps.querier
+        .set_pending_rewards("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 0, None);
+
+    let res: StakeInfoResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::StakeInfo {
+                lp_token: String::from("astro_ust_lp_token"),
+                user: String::from("bob"),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        StakeInfoResponse {
+            shares: Uint128::new(416736053),
+            total_shares: Uint128::new(1416736053),
+            lp_amount: Uint128::new(499),
+        }
+    );
+
+    // Alice's 1000000000 shares are worth 1000000000 * 1701 / 1417736053 = 1199 liquidity tokens,
+    // which should be withdrawn from the generator and transferred to her
+    let msg = ExecuteMsg::Unstake {
+        lp_token: String::from("astro_ust_lp_token"),
+        shares: Uint128::new(1000000000),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: String::from(GENERATOR_ADDR),
+            msg: to_binary(&astroport::generator::ExecuteMsg::Withdraw {
+                lp_token: Addr::unchecked("astro_ust_lp_token"),
+                amount: Uint128::new(1199),
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: String::from("astro_ust_lp_token"),
+            msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                recipient: String::from("alice"),
+                amount: Uint128::new(1199),
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    let total_shares = TOTAL_SHARES
+        .load(deps.as_ref().storage, &lp_token_addr)
+        .unwrap();
+    assert_eq!(total_shares, Uint128::new(416736053));
+    let alice_addr = Addr::unchecked("alice");
+    let alice_shares = SHARES
+        .may_load(deps.as_ref().storage, (&lp_token_addr, &alice_addr))
+        .unwrap();
+    assert_eq!(alice_shares, None);
+
+    // Bob cannot unstake more than he holds
+    let msg = ExecuteMsg::Unstake {
+        lp_token: String::from("astro_ust_lp_token"),
+        shares: Uint128::new(416736054),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), msg);
+    assert_eq!(
+        err,
+        Err(StdError::generic_err(
+            "invalid shares: must be greater than zero and no more than 416736053"
+        ))
+    );
+}
+
+#[test]
+fn should_reject_invalid_stake() {
+    let mut deps = setup_test();
+
+    // A contract posing as the ASTRO-UST pair, with its own liquidity token
+    deps.querier.set_unregistered_pair(
+        "fake_pair",
+        PairInfo {
+            asset_infos: [
+                AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+                AssetInfo::native("uusd").into(),
+            ],
+            contract_addr: Addr::unchecked("fake_pair"),
+            liquidity_token: Addr::unchecked("fake_lp_token"),
+            pair_type: PairType::Xyk {},
+        },
+    );
+
+    let mut stake = |token: &str, pair: &str| {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from("alice"),
+            amount: Uint128::new(1000),
+            msg: to_binary(&Cw20HookMsg::Stake {
+                pair: String::from(pair),
+            })
+            .unwrap(),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info(token, &[]), msg)
+    };
+
+    assert_eq!(
+        stake("fake_lp_token", "fake_pair"),
+        Err(StdError::generic_err(
+            "pair fake_pair is not registered with the factory"
+        ))
+    );
+    assert_eq!(
+        stake("luna_ust_lp_token", "astro_ust_pair"),
+        Err(StdError::generic_err(
+            "invalid liquidity token: expected cw20:astro_ust_lp_token, received cw20:luna_ust_lp_token"
+        ))
+    );
+}
+
+#[test]
+fn should_resist_vault_share_inflation() {
+    let mut deps = setup_test();
+    deps.querier
+        .set_reward_info("astro_ust_lp_token", "astro_token", None);
+
+    let stake = |sender: &str, amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: String::from(sender),
+            amount: Uint128::new(amount),
+            msg: to_binary(&Cw20HookMsg::Stake {
+                pair: String::from("astro_ust_pair"),
+            })
+            .unwrap(),
+        })
+    };
+    let lp_token = mock_info("astro_ust_lp_token", &[]);
+
+    // Suppose Alice stakes 1 liquidity token, then donates 1000000000 to the vault's generator
+    // deposit, hoping that later stakes round down to few shares
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        lp_token.clone(),
+        stake("alice", 1),
+    )
+    .unwrap();
+    deps.querier
+        .set_generator_deposit("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 1000000001);
+
+    // Thanks to the virtual shares, Bob's 1000000 liquidity tokens are still worth
+    // 1000000 * 2000000 / 1000000002 = 1999 shares
+    let res = execute(deps.as_mut(), mock_env(), lp_token, stake("bob", 1000000)).unwrap();
+    assert_eq!(res.attributes[2], Attribute::new("shares_issued", "1999"));
+    deps.querier
+        .set_generator_deposit("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 1001000001);
+
+    // Bob's shares are worth 1999 * 1001000002 / 2001999 = 999500 liquidity tokens, so he loses
+    // only 0.05% to rounding, while half of Alice's donation accrues to the virtual shares
+    let stake_info = |user: &str| -> StakeInfoResponse {
+        let msg = QueryMsg::StakeInfo {
+            lp_token: String::from("astro_ust_lp_token"),
+            user: String::from(user),
+        };
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+    };
+    assert_eq!(stake_info("bob").lp_amount, Uint128::new(999500));
+    assert_eq!(stake_info("alice").lp_amount, Uint128::new(500000250));
+}
+
+#[test]
+fn should_compound() {
+    let mut deps = setup_test();
+
+    deps.querier.set_pair(
+        "proxy_ust_pair",
+        PairInfo {
+            asset_infos: [
+                AssetInfo::cw20(Addr::unchecked("proxy_token")).into(),
+                AssetInfo::native("uusd").into(),
+            ],
+            contract_addr: Addr::unchecked("proxy_ust_pair"),
+            liquidity_token: Addr::unchecked("proxy_ust_lp_token"),
+            pair_type: PairType::Xyk {},
+        },
+    );
+    deps.querier
+        .set_reward_info("astro_ust_lp_token", "astro_token", Some("proxy_token"));
+    deps.querier.set_pending_rewards(
+        "astro_ust_lp_token",
+        MOCK_CONTRACT_ADDR,
+        1000000000,
+        Some(2000000000),
+    );
+
+    // 500000000 ASTRO was received when someone staked or unstaked earlier
+    let lp_token_addr = Addr::unchecked("astro_ust_lp_token");
+    REWARDS
+        .save(
+            deps.as_mut().storage,
+            &lp_token_addr,
+            &AssetList::from(vec![Asset::cw20(
+                Addr::unchecked("astro_token"),
+                500000000u128,
+            )]),
+        )
+        .unwrap();
+
+    let route = vec![SwapOperation::AstroSwap {
+        offer_asset_info: AssetInfo::cw20(Addr::unchecked("proxy_token")).into(),
+        ask_asset_info: AssetInfo::native("uusd").into(),
+    }];
+
+    // The pending rewards should be claimed first
+    let msg = ExecuteMsg::Compound {
+        pair: String::from("astro_ust_pair"),
+        routes: vec![route.clone()],
+        minimum_received: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 8,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from(GENERATOR_ADDR),
+                msg: to_binary(&astroport::generator::ExecuteMsg::Withdraw {
+                    lp_token: lp_token_addr.clone(),
+                    amount: Uint128::zero(),
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    let cache = CACHE.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    assert_eq!(
+        cache.assets,
+        AssetList::from(vec![
+            Asset::cw20(Addr::unchecked("astro_token"), 1500000000u128),
+            Asset::cw20(Addr::unchecked("proxy_token"), 2000000000u128),
+        ])
+    );
+    assert_eq!(cache.recipient_addr, Addr::unchecked(MOCK_CONTRACT_ADDR));
+    assert!(cache.auto_stake);
+    assert_eq!(cache.routes, vec![route.clone()]);
+    assert_eq!(
+        REWARDS
+            .may_load(deps.as_ref().storage, &lp_token_addr)
+            .unwrap(),
+        None
+    );
+
+    // Once the rewards are received, the proxy reward should be swapped into UST through the router
+    let _reply = Reply {
+        id: 8,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 5,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("proxy_token"),
+                msg: to_binary(&cw20::Cw20ExecuteMsg::Send {
+                    contract: String::from(ROUTER_ADDR),
+                    amount: Uint128::new(2000000000),
+                    msg: to_binary(&astroport::router::Cw20HookMsg::ExecuteSwapOperations {
+                        operations: route,
+                        minimum_receive: None,
+                        to: None,
+                    })
+                    .unwrap()
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // Suppose the router returns 3000000000 uusd. The ASTRO and UST should then be zapped into the
+    // pair. The user's share of UST is bigger, so UST should be offered
+    deps.querier
+        .set_native_balance(MOCK_CONTRACT_ADDR, &[Coin::new(3000000000, "uusd")]);
+
+    let _reply = Reply {
+        id: 5,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
     assert_eq!(res.messages.len(), 1);
     assert_eq!(
-        res.messages[0],
+        res.messages[0],
+        SubMsg {
+            id: 1,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: String::from("astro_ust_pair"),
+                msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                    offer_asset: Asset::native("uusd", 483901822u128).into(),
+                    belief_price: None,
+                    max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                    to: None,
+                })
+                .unwrap(),
+                funds: vec![Coin::new(483901822, "uusd")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Success,
+        }
+    );
+
+    // The liquidity tokens should be staked on behalf of the contract itself
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "astro_token")
+                .add_attribute("return_amount", "355000000")],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[1],
         SubMsg {
             id: 2,
             msg: CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: String::from("luna_ust_pair"),
+                contract_addr: String::from("astro_ust_pair"),
                 msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
                     assets: [
-                        Asset::native("uusd", 118070429547232u128).into(),
-                        Asset::native("uluna", 1451993415113u128).into(),
+                        Asset::cw20(Addr::unchecked("astro_token"), 1855000000u128).into(),
+                        Asset::native("uusd", 2514903050u128).into(),
                     ],
                     slippage_tolerance: None,
-                    auto_stake: None,
-                    receiver: None
+                    auto_stake: Some(true),
+                    receiver: Some(String::from(MOCK_CONTRACT_ADDR))
                 })
                 .unwrap(),
-                funds: vec![
-                    Coin::new(118070429547232, "uusd"),
-                    Coin::new(1451993415113, "uluna")
-                ]
+                funds: vec![Coin::new(2514903050, "uusd")]
             }),
             gas_limit: None,
             reply_on: ReplyOn::Success
         }
     );
-}
-
-#[test]
-fn should_reject_excessive_slippage() {
-    let mut deps = setup_test();
-
-    CACHE
-        .save(
-            deps.as_mut().storage,
-            &CacheData {
-                user_addr: Addr::unchecked("alice"),
-                pair_addr: Addr::unchecked("luna_ust_pair"),
-                liquidity_token_addr: Addr::unchecked("luna_ust_lp_token"),
-                assets: AssetList::default(),
-                minimum_received: Some(Uint128::new(20000)),
-            },
-        )
-        .unwrap();
 
+    // The leftover UST should be kept to be compounded next time, rather than transferred
     let _reply = Reply {
         id: 2,
         result: ContractResult::Ok(SubMsgExecutionResponse {
             events: vec![Event::new("wasm")
                 .add_attribute("action", "provide_liquidity")
-                .add_attribute("share", "12345")],
+                .add_attribute("share", "2145000000")],
             data: None,
         }),
     };
-    let err = reply(deps.as_mut(), mock_env(), _reply);
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+    assert_eq!(res.messages.len(), 0);
+
+    let rewards = REWARDS.load(deps.as_ref().storage, &lp_token_addr).unwrap();
     assert_eq!(
-        err,
+        rewards,
+        AssetList::from(vec![Asset::native("uusd", 1195128u128)])
+    );
+    assert!(!CACHE.has(deps.as_ref().storage, 1u64.into()));
+}
+
+#[test]
+fn should_reject_invalid_compound() {
+    let mut deps = setup_test();
+    deps.querier
+        .set_reward_info("astro_ust_lp_token", "astro_token", Some("proxy_token"));
+
+    // Bob is a keeper, e.g. a bot, so he can compound along with the owner
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        fee_bps: None,
+        fee_collector: None,
+        paused: None,
+        allowlist: None,
+        denylist: None,
+        keepers: Some(vec![String::from("bob")]),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+
+    let mut compound = |sender: &str, routes: Vec<Vec<SwapOperation>>| {
+        let msg = ExecuteMsg::Compound {
+            pair: String::from("astro_ust_pair"),
+            routes,
+            minimum_received: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), msg)
+    };
+
+    assert_eq!(
+        compound("alice", vec![]),
         Err(StdError::generic_err(
-            "too little received! minimum: 20000, received 12345"
+            "unauthorized: sender is neither owner nor keeper"
+        ))
+    );
+    assert_eq!(
+        compound("deployer", vec![]),
+        Err(StdError::generic_err("no rewards to compound"))
+    );
+    assert_eq!(
+        compound("bob", vec![]),
+        Err(StdError::generic_err("no rewards to compound"))
+    );
+
+    deps.querier
+        .set_pending_rewards("astro_ust_lp_token", MOCK_CONTRACT_ADDR, 12345, Some(12345));
+
+    let mut compound = |routes: Vec<Vec<SwapOperation>>| {
+        let msg = ExecuteMsg::Compound {
+            pair: String::from("astro_ust_pair"),
+            routes,
+            minimum_received: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg)
+    };
+    let swap = |offer_info: AssetInfo, ask_info: AssetInfo| SwapOperation::AstroSwap {
+        offer_asset_info: offer_info.into(),
+        ask_asset_info: ask_info.into(),
+    };
+
+    // The ASTRO reward is in the pair, so no route is needed for it, but the proxy reward needs one
+    assert_eq!(
+        compound(vec![vec![swap(
+            AssetInfo::native("uluna"),
+            AssetInfo::native("uusd")
+        )]]),
+        Err(StdError::generic_err(
+            "no route provided for reward cw20:proxy_token"
+        ))
+    );
+    assert_eq!(
+        compound(vec![vec![swap(
+            AssetInfo::cw20(Addr::unchecked("proxy_token")),
+            AssetInfo::native("uluna")
+        )]]),
+        Err(StdError::generic_err(
+            "invalid route: native:uluna is not in the pool"
         ))
     );
 }
@@ -654,15 +4870,25 @@ fn should_query_simulate() {
     let msg = QueryMsg::SimulateEnter {
         pair: String::from("luna_ust_pair"),
         deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
     };
     let res: SimulateEnterResponse =
         from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
     assert_eq!(
         res,
         SimulateEnterResponse {
-            offer_asset: Asset::native("uusd", 50064546170u128).into(),
+            offer_asset: Asset::native("uusd", 50064546167u128).into(),
             return_asset: Asset::native("uluna", 613571013u128).into(),
-            mint_shares: Uint128::new(5481424982)
+            mint_shares: Uint128::new(5481424983),
+            refund_assets: AssetList::new().into(),
+            solver: Some(SolverInfo {
+                iterations: 0,
+                residual: String::from("-969034901855892585777480016"),
+                converged: true,
+            }),
+            protocol_fee: AssetList::new().into(),
+            referral_fee: AssetList::new().into(),
         }
     );
 
@@ -673,6 +4899,8 @@ fn should_query_simulate() {
             Asset::cw20(Addr::unchecked("astro_token"), 750000000000u128), // ~$1M
         ])
         .into(),
+        initial_price: None,
+        referral_fee_bps: None,
     };
     let res: SimulateEnterResponse =
         from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
@@ -681,7 +4909,418 @@ fn should_query_simulate() {
         SimulateEnterResponse {
             offer_asset: Asset::cw20(Addr::unchecked("astro_token"), 336933122413u128).into(),
             return_asset: Asset::native("uusd", 452253642498u128).into(),
-            mint_shares: Uint128::new(476696702710)
+            mint_shares: Uint128::new(476696702710),
+            refund_assets: AssetList::from(vec![Asset::native("uusd", 1u128)]).into(),
+            solver: Some(SolverInfo {
+                iterations: 0,
+                residual: String::from("2873836675545801050147513027"),
+                converged: true,
+            }),
+            protocol_fee: AssetList::new().into(),
+            referral_fee: AssetList::new().into(),
+        }
+    );
+}
+
+#[test]
+fn should_query_simulate_stable() {
+    let deps = setup_test();
+
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("bluna_luna_pair"),
+        deposits: AssetList::from(vec![Asset::native("uluna", 100000000000u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let res: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        SimulateEnterResponse {
+            offer_asset: Asset::native("uluna", 49395594273u128).into(),
+            return_asset: Asset::cw20(Addr::unchecked("bluna_token"), 49331679227u128).into(),
+            mint_shares: Uint128::new(49949335066),
+            refund_assets: AssetList::new().into(),
+            solver: Some(SolverInfo {
+                iterations: 37,
+                residual: String::from("3289016919243"),
+                converged: true,
+            }),
+            protocol_fee: AssetList::new().into(),
+            referral_fee: AssetList::new().into(),
+        }
+    );
+}
+
+#[test]
+fn should_simulate_stable_enter_as_executed() {
+    let mut deps = setup_test();
+
+    let deposits = AssetList::from(vec![
+        Asset::native("uluna", 100000000000u128),
+        Asset::cw20(Addr::unchecked("bluna_token"), 20000000000u128),
+    ]);
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("bluna_luna_pair"),
+        deposits: deposits.clone().into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let simulated: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(simulated.refund_assets, AssetList::new().into());
+
+    let msg = ExecuteMsg::Enter {
+        pair: String::from("bluna_luna_pair"),
+        deposits: Some(deposits.into()),
+        minimum_received: None,
+        recipient: None,
+        auto_stake: None,
+        initial_price: None,
+        deadline: None,
+        max_spread: None,
+        belief_price: None,
+        provide_slippage_tolerance: None,
+        referrer: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("alice", &[Coin::new(100000000000, "uluna")]),
+        msg,
+    )
+    .unwrap();
+    let offer_amount = simulated.offer_asset.amount;
+    assert_eq!(
+        simulated.offer_asset,
+        Asset::native("uluna", offer_amount).into()
+    );
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: String::from("bluna_luna_pair"),
+            msg: to_binary(&astroport::pair::ExecuteMsg::Swap {
+                offer_asset: Asset::native("uluna", offer_amount).into(),
+                belief_price: None,
+                max_spread: Some(Decimal::from_ratio(1u128, 2u128)),
+                to: None,
+            })
+            .unwrap(),
+            funds: vec![Coin::new(offer_amount.u128(), "uluna")]
+        })
+    );
+
+    let return_amount = simulated.return_asset.amount;
+    let _reply = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("action", "swap")
+                .add_attribute("ask_asset", "bluna_token")
+                .add_attribute("return_amount", return_amount.to_string())],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), _reply).unwrap();
+
+    // All the assets after the swap are provided, with nothing left to refund
+    let provided_luna = Uint128::new(100000000000) - offer_amount;
+    let provided_bluna = Uint128::new(20000000000) + return_amount;
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: String::from("bluna_luna_pair"),
+            msg: to_binary(&astroport::pair::ExecuteMsg::ProvideLiquidity {
+                assets: [
+                    Asset::native("uluna", provided_luna).into(),
+                    Asset::cw20(Addr::unchecked("bluna_token"), provided_bluna).into(),
+                ],
+                slippage_tolerance: None,
+                auto_stake: None,
+                receiver: None
+            })
+            .unwrap(),
+            funds: vec![Coin::new(provided_luna.u128(), "uluna")]
+        })
+    );
+
+    // The pair mints shares according to the increase of the invariant, starting from the pool's
+    // depths after the swap. There is no maker fee, so the whole commission stays in the pool
+    let minted = compute_stable_provide(
+        [
+            Uint128::new(2961459937027) - return_amount,
+            Uint128::new(2937863752918) + offer_amount,
+        ],
+        [provided_bluna, provided_luna],
+        Uint128::new(2948589474051),
+        10,
+    )
+    .unwrap();
+    assert_eq!(simulated.mint_shares, minted);
+}
+
+#[test]
+fn should_query_simulate_with_factory_commission_rate() {
+    let mut deps = setup_test();
+    deps.querier.set_fee_info(PairType::Xyk {}, 20, 0);
+
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("luna_ust_pair"),
+        deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let res: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        SimulateEnterResponse {
+            offer_asset: Asset::native("uusd", 50039478225u128).into(),
+            return_asset: Asset::native("uluna", 613879030u128).into(),
+            mint_shares: Uint128::new(5484177860),
+            refund_assets: AssetList::new().into(),
+            solver: Some(SolverInfo {
+                iterations: 0,
+                residual: String::from("-2794042512245595476393465825"),
+                converged: true,
+            }),
+            protocol_fee: AssetList::new().into(),
+            referral_fee: AssetList::new().into(),
+        }
+    );
+}
+
+#[test]
+fn should_query_simulate_with_fees() {
+    let mut deps = setup_test();
+
+    let msg = ExecuteMsg::UpdateConfig {
+        owner: None,
+        fee_bps: Some(30),
+        fee_collector: None,
+        paused: None,
+        allowlist: None,
+        denylist: None,
+        keepers: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("deployer", &[]), msg).unwrap();
+
+    // Same as in `should_enter_with_fees`, 99500000000 uusd should be left after the fees, of which
+    // 49814275993 uusd should be offered for swap
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("luna_ust_pair"),
+        deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: Some(20),
+    };
+    let res: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res.offer_asset,
+        Asset::native("uusd", 49814275993u128).into()
+    );
+    assert_eq!(res.mint_shares, Uint128::new(5454023643));
+    assert_eq!(
+        res.protocol_fee,
+        AssetList::from(vec![Asset::native("uusd", 300000000u128)]).into()
+    );
+    assert_eq!(
+        res.referral_fee,
+        AssetList::from(vec![Asset::native("uusd", 200000000u128)]).into()
+    );
+}
+
+#[test]
+fn should_query_simulate_by_assets() {
+    let deps = setup_test();
+
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("astro_ust_pair"),
+        deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let expected: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+
+    let msg = QueryMsg::SimulateEnterByAssets {
+        asset_infos: [
+            AssetInfo::native("uusd").into(),
+            AssetInfo::cw20(Addr::unchecked("astro_token")).into(),
+        ],
+        pair_type: None,
+        deposits: AssetList::from(vec![Asset::native("uusd", 100000000000u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let res: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn should_query_simulate_empty_pool() {
+    let deps = setup_test();
+
+    // uASTRO in excess of the initial price is not provided
+    // uASTRO to be provided: 20000000 / 0.02 = 1000000000
+    // Amount of liquidity tokens to be minted: isqrt(1000000000 * 20000000) = 141421356
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("astro_luna_pair"),
+        deposits: AssetList::from(vec![
+            Asset::cw20(Addr::unchecked("astro_token"), 1200000000u128),
+            Asset::native("uluna", 20000000u128),
+        ])
+        .into(),
+        initial_price: Some(Decimal::from_ratio(1u128, 50u128)),
+        referral_fee_bps: None,
+    };
+    let res: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        SimulateEnterResponse {
+            offer_asset: Asset::cw20(Addr::unchecked("astro_token"), 0u128).into(),
+            return_asset: Asset::native("uluna", 0u128).into(),
+            mint_shares: Uint128::new(141421356),
+            refund_assets: AssetList::from(vec![Asset::cw20(
+                Addr::unchecked("astro_token"),
+                200000000u128
+            )])
+            .into(),
+            solver: None,
+            protocol_fee: AssetList::new().into(),
+            referral_fee: AssetList::new().into(),
+        }
+    );
+}
+
+#[test]
+fn should_query_simulate_exit() {
+    let deps = setup_test();
+
+    let msg = QueryMsg::SimulateExit {
+        pair: String::from("luna_ust_pair"),
+        lp_amount: Uint128::new(5481424982),
+        ask_asset: AssetInfo::native("uusd").into(),
+    };
+    // Burning 5481424982 uLP should return 49914289029 uusd + 613830399 uluna
+    //
+    // The swap is made after the withdrawal, in a pool of 118020515258203 uusd + 1451379584714
+    // uluna. Using `scripts/cfmm.ts` to estimate:
+    // offering 613830399 uluna, should receive 49743508117 uusd after commission
+    // uusd received: 49914289029 + 49743508117 = 99657797146
+    let res: SimulateExitResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        SimulateExitResponse {
+            withdrawn_assets: AssetList::from(vec![
+                Asset::native("uusd", 49914289029u128),
+                Asset::native("uluna", 613830399u128),
+            ])
+            .into(),
+            offer_asset: Asset::native("uluna", 613830399u128).into(),
+            return_asset: Asset::native("uusd", 49743508117u128).into(),
+            receive_amount: Uint128::new(99657797146)
         }
     );
 }
+
+#[test]
+fn should_query_simulate_migrate_liquidity() {
+    let deps = setup_test();
+
+    let msg = QueryMsg::SimulateMigrateLiquidity {
+        from_pair: String::from("luna_ust_pair"),
+        to_pair: String::from("astro_ust_pair"),
+        lp_amount: Uint128::new(5481424982),
+        route: None,
+    };
+    // Same as in `should_query_simulate_exit`, burning 5481424982 uLP should return
+    // 49914289029 uusd + 613830399 uluna, and the uluna should be swapped for 49743508117 uusd
+    //
+    // Entering astro_ust_pair should then be simulated with 49914289029 + 49743508117 = 99657797146
+    // uusd deposited
+    let res: SimulateMigrateLiquidityResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res.withdrawn_assets,
+        AssetList::from(vec![
+            Asset::native("uusd", 49914289029u128),
+            Asset::native("uluna", 613830399u128),
+        ])
+        .into()
+    );
+    assert_eq!(
+        res.offer_asset,
+        Some(Asset::native("uluna", 613830399u128).into())
+    );
+    assert_eq!(
+        res.return_asset,
+        Some(Asset::native("uusd", 49743508117u128).into())
+    );
+
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("astro_ust_pair"),
+        deposits: AssetList::from(vec![Asset::native("uusd", 99657797146u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let expected: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(res.enter, expected);
+}
+
+#[test]
+fn should_query_simulate_migrate_liquidity_via_router() {
+    let deps = setup_test();
+
+    let msg = QueryMsg::SimulateMigrateLiquidity {
+        from_pair: String::from("astro_ust_pair"),
+        to_pair: String::from("bluna_luna_pair"),
+        lp_amount: Uint128::new(476696702710),
+        route: Some(vec![SwapOperation::AstroSwap {
+            offer_asset_info: AssetInfo::native("uusd").into(),
+            ask_asset_info: AssetInfo::native("uluna").into(),
+        }]),
+    };
+    // Burning 476696702710 uLP should return 410191112552 uASTRO + 556113682145 uusd. The route
+    // starts from uusd, so the ASTRO should be swapped for 549713082916 uusd in astro_ust_pair
+    // after the withdrawal
+    //
+    // The total of 556113682145 + 549713082916 = 1105826765061 uusd should then be swapped for
+    // 13432509722 uluna in luna_ust_pair by the router, with which bluna_luna_pair is entered
+    let res: SimulateMigrateLiquidityResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(
+        res.withdrawn_assets,
+        AssetList::from(vec![
+            Asset::cw20(Addr::unchecked("astro_token"), 410191112552u128),
+            Asset::native("uusd", 556113682145u128),
+        ])
+        .into()
+    );
+    assert_eq!(
+        res.offer_asset,
+        Some(Asset::cw20(Addr::unchecked("astro_token"), 410191112552u128).into())
+    );
+    assert_eq!(
+        res.return_asset,
+        Some(Asset::native("uusd", 549713082916u128).into())
+    );
+    assert_eq!(
+        res.routed_asset,
+        Some(Asset::native("uluna", 13432509722u128).into())
+    );
+
+    let msg = QueryMsg::SimulateEnter {
+        pair: String::from("bluna_luna_pair"),
+        deposits: AssetList::from(vec![Asset::native("uluna", 13432509722u128)]).into(),
+        initial_price: None,
+        referral_fee_bps: None,
+    };
+    let expected: SimulateEnterResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(res.enter, expected);
+}
@@ -1,37 +1,22 @@
 use std::str::FromStr;
 
 use cosmwasm_std::{
-    to_binary, Addr, Coin, CosmosMsg, Decimal, Event, QuerierWrapper, QueryRequest, Reply,
-    StdError, StdResult, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg, WasmQuery,
+    from_binary, to_binary, Addr, Api, Coin, CosmosMsg, Decimal, Event, QuerierWrapper,
+    QueryRequest, Reply, StdError, StdResult, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
+    WasmQuery,
 };
 use cw20::Cw20ExecuteMsg;
 
 use cw_asset::{Asset, AssetInfo, AssetList};
-use cw_bigint::{BigInt, BigUint};
 
-use astroport::asset::PairInfo;
-use astroport::pair::{ExecuteMsg, PoolResponse, SimulationResponse, MAX_ALLOWED_SLIPPAGE};
-
-const POW_32: u128 = 2u128.pow(32);
-
-/// Convert a cw_bigint::BigUint to cosmwasm_std::Uint128
-pub fn biguint_to_uint128(bui: &BigUint) -> StdResult<Uint128> {
-    let digits = bui.to_u32_digits();
-    let mut factor = Uint128::new(1u128);
-    let mut ui = Uint128::zero();
-    for digit in &digits {
-        ui = ui.checked_add(Uint128::new(u128::from(*digit)).checked_mul(factor)?)?;
-        factor = factor.checked_mul(Uint128::new(POW_32))?;
-    }
-    Ok(ui)
-}
-
-/// Convert a num_bigint::BigInt to cosmwasm_std::Uint128
-pub fn bigint_to_uint128(bi: &BigInt) -> StdResult<Uint128> {
-    biguint_to_uint128(
-        &bi.to_biguint().ok_or_else(|| StdError::generic_err(format!("big int is negative: {}", bi)))?
-    )
-}
+use astroport::asset::{AssetInfo as LegacyAssetInfo, PairInfo};
+use astroport::factory::{FeeInfoResponse, PairType};
+use astroport::generator::{PendingTokenResponse, RewardInfoResponse};
+use astroport::pair::{
+    ConfigResponse, ExecuteMsg, PoolResponse, SimulationResponse, StablePoolConfig,
+    MAX_ALLOWED_SLIPPAGE,
+};
+use astroport::router::{SimulateSwapOperationsResponse, SwapOperation};
 
 /// Extract response from reply
 pub fn unwrap_reply(reply: Reply) -> StdResult<SubMsgExecutionResponse> {
@@ -46,36 +31,115 @@ pub fn event_contains_attr(event: &Event, key: &str, value: &str) -> bool {
         .any(|attr| attr.key == key && attr.value == value)
 }
 
+/// Find the first event in a submessage response that contains a specific key-value pair
+pub fn find_event<'a>(
+    res: &'a SubMsgExecutionResponse,
+    key: &str,
+    value: &str,
+) -> StdResult<&'a Event> {
+    res.events
+        .iter()
+        .find(|event| event_contains_attr(event, key, value))
+        .ok_or_else(|| StdError::generic_err(format!("cannot find `{}` event", value)))
+}
+
+/// Find the value of an attribute in an event by its key
+pub fn read_attribute(event: &Event, key: &str) -> StdResult<String> {
+    event
+        .attributes
+        .iter()
+        .find(|attr| attr.key == key)
+        .map(|attr| attr.value.clone())
+        .ok_or_else(|| StdError::generic_err(format!("cannot find `{}` attribute", key)))
+}
+
+/// Parse an asset info from the string representation used in Astroport's event attributes
+///
+/// If the string can be validated as a Terra address, then we assume it is a CW20; otherwise we
+/// assume it is a native coin
+pub fn parse_asset_info(api: &dyn Api, info_str: &str) -> AssetInfo {
+    if let Ok(contract_addr) = api.addr_validate(info_str) {
+        AssetInfo::cw20(contract_addr)
+    } else {
+        AssetInfo::native(info_str)
+    }
+}
+
+/// Parse the asset returned by a swap from the `swap` event emitted by the Astroport pair
+pub fn parse_swap_event(api: &dyn Api, res: &SubMsgExecutionResponse) -> StdResult<Asset> {
+    let event = find_event(res, "action", "swap")?;
+    let ask_asset_str = read_attribute(event, "ask_asset")?;
+    let return_amount_str = read_attribute(event, "return_amount")?;
+
+    Ok(Asset::new(
+        parse_asset_info(api, &ask_asset_str),
+        Uint128::from_str(&return_amount_str)?,
+    ))
+}
+
+/// Parse the assets refunded by burning liquidity tokens from the `withdraw_liquidity` event
+/// emitted by the Astroport pair
+///
+/// The `refund_assets` attribute is in the format of `{amount0}{info0}, {amount1}{info1}`, e.g.
+/// `12345uusd, 69420terra1...`
+pub fn parse_withdraw_liquidity_event(
+    api: &dyn Api,
+    res: &SubMsgExecutionResponse,
+) -> StdResult<AssetList> {
+    let event = find_event(res, "action", "withdraw_liquidity")?;
+    let refund_assets_str = read_attribute(event, "refund_assets")?;
+
+    let mut assets = AssetList::new();
+    for asset_str in refund_assets_str.split(", ") {
+        let idx = asset_str
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| StdError::generic_err(format!("invalid refund asset: {}", asset_str)))?;
+        let (amount_str, info_str) = asset_str.split_at(idx);
+        assets.add(&Asset::new(
+            parse_asset_info(api, info_str),
+            Uint128::from_str(amount_str)?,
+        ))?;
+    }
+    assets.purge();
+
+    Ok(assets)
+}
+
 /// Handle deposit:
-/// - For CW20, draw the token from the sender's wallet, and return a `Some<CosmosMsg>`
-/// - For native, assert the declared has indeed been transferred along with the message, return `None`
+/// - If the asset has already been received by the contract (i.e. native coins sent along with the
+///   message, or a CW20 token sent via the `Receive` hook), assert the declared amount has indeed
+///   been received, and return `None`
+/// - Otherwise, for CW20, draw the token from the sender's wallet, and return a `Some<CosmosMsg>`
 pub fn handle_deposit(
     claimed_deposit: &Asset,
-    received_coins: &mut AssetList,
+    received_assets: &mut AssetList,
     sender_addr: &Addr,
     contract_addr: &Addr,
 ) -> StdResult<Option<CosmosMsg>> {
-    match claimed_deposit.info {
-        AssetInfo::Cw20(_) => Ok(Some(
+    // We need to have `received_asset` as a clone here to prevent the `cannot borrow as mutable
+    // because it is also borrowed as immutable` error
+    let received_asset = received_assets.find(&claimed_deposit.info).cloned();
+
+    match (&claimed_deposit.info, received_asset) {
+        (AssetInfo::Cw20(_), None) => Ok(Some(
             claimed_deposit.transfer_from_msg(sender_addr, contract_addr)?,
         )),
-        AssetInfo::Native(_) => {
-            // We need to have `received_coin` as a clone here to prevent the `cannot borrow as 
-            // mutable because it is also borrowed as immutable` error
-            let received_coin = received_coins
-                .find(&claimed_deposit.info)
-                .ok_or_else(|| StdError::generic_err(
-                    format!("invalid deposit: expected {}, received none", claimed_deposit)
-                ))?
-                .clone();
-
-            if received_coin != *claimed_deposit {
-                return Err(StdError::generic_err(
-                    format!("invalid deposit: expected {}, received {}", claimed_deposit, received_coin.amount)
-                ));
+        (_, received_asset) => {
+            let received_asset = received_asset.ok_or_else(|| {
+                StdError::generic_err(format!(
+                    "invalid deposit: expected {}, received none",
+                    claimed_deposit
+                ))
+            })?;
+
+            if received_asset != *claimed_deposit {
+                return Err(StdError::generic_err(format!(
+                    "invalid deposit: expected {}, received {}",
+                    claimed_deposit, received_asset.amount
+                )));
             }
 
-            received_coins.deduct(&received_coin)?;
+            received_assets.deduct(&received_asset)?;
 
             Ok(None)
         }
@@ -84,32 +148,124 @@ pub fn handle_deposit(
 
 // Handle multiple deposits by invoking `handle_deposit` on each of the claimed deposit
 //
-// This function takes a mutable asset list `received_coins` which is all the native tokens the user
-// sent to the contract. For every native coin depsosit we processed, we deduct it from this list.
-// At the end, we check whether this list is empty. If not, it means the user has sent extra funds,
-// and we throw an error. 
+// This function takes a mutable asset list `received_assets` which is all the assets the contract
+// has already received from the user, i.e. native coins sent along with the message, or a CW20
+// token sent via the `Receive` hook. For every such deposit we processed, we deduct it from this
+// list. At the end, we check whether this list is empty. If not, it means the user has sent extra
+// funds, and we throw an error.
 pub fn handle_deposits(
     claimed_deposits: &AssetList,
-    received_coins: &mut AssetList,
+    received_assets: &mut AssetList,
     sender_addr: &Addr,
     contract_addr: &Addr,
 ) -> StdResult<Vec<CosmosMsg>> {
     let mut msgs: Vec<CosmosMsg> = vec![];
     for deposit in claimed_deposits {
-        if let Some(msg) = handle_deposit(deposit, received_coins, sender_addr, contract_addr)? {
+        if let Some(msg) = handle_deposit(deposit, received_assets, sender_addr, contract_addr)? {
             msgs.push(msg);
         }
     }
 
-    if received_coins.len() > 0 {
-        return Err(StdError::generic_err(
-            format!("extra deposit received: {}", received_coins)
-        ))
+    if received_assets.len() > 0 {
+        return Err(StdError::generic_err(format!(
+            "extra deposit received: {}",
+            received_assets
+        )));
     }
 
     Ok(msgs)
 }
 
+/// Query the Astroport factory contract of the fee settings of a pair type
+pub fn query_fee_info(
+    querier: &QuerierWrapper,
+    factory_addr: &Addr,
+    pair_type: &PairType,
+) -> StdResult<FeeInfoResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: factory_addr.to_string(),
+        msg: to_binary(&astroport::factory::QueryMsg::FeeInfo {
+            pair_type: pair_type.clone(),
+        })?,
+    }))
+}
+
+/// Query the Astroport factory of the pair registered for the given assets
+pub fn query_factory_pair(
+    querier: &QuerierWrapper,
+    factory_addr: &Addr,
+    asset_infos: &[LegacyAssetInfo; 2],
+) -> StdResult<PairInfo> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: factory_addr.to_string(),
+        msg: to_binary(&astroport::factory::QueryMsg::Pair {
+            asset_infos: asset_infos.clone(),
+        })?,
+    }))
+}
+
+/// Query the Astroport factory of the address of the generator
+pub fn query_generator(querier: &QuerierWrapper, factory_addr: &Addr) -> StdResult<Addr> {
+    let config: astroport::factory::ConfigResponse =
+        querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: factory_addr.to_string(),
+            msg: to_binary(&astroport::factory::QueryMsg::Config {})?,
+        }))?;
+    config
+        .generator_address
+        .ok_or_else(|| StdError::generic_err("generator is not set in the factory"))
+}
+
+/// Query the Astroport generator of the amount of liquidity tokens staked by a user
+pub fn query_generator_deposit(
+    querier: &QuerierWrapper,
+    generator_addr: &Addr,
+    liquidity_token_addr: &Addr,
+    user_addr: &Addr,
+) -> StdResult<Uint128> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: generator_addr.to_string(),
+        msg: to_binary(&astroport::generator::QueryMsg::Deposit {
+            lp_token: liquidity_token_addr.clone(),
+            user: user_addr.clone(),
+        })?,
+    }))
+}
+
+/// Query the Astroport generator of the rewards pending for a user who staked the given liquidity
+/// token, i.e. the ASTRO reward and the proxy reward if any
+pub fn query_pending_rewards(
+    querier: &QuerierWrapper,
+    generator_addr: &Addr,
+    liquidity_token_addr: &Addr,
+    user_addr: &Addr,
+) -> StdResult<AssetList> {
+    let reward_info: RewardInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: generator_addr.to_string(),
+        msg: to_binary(&astroport::generator::QueryMsg::RewardInfo {
+            lp_token: liquidity_token_addr.clone(),
+        })?,
+    }))?;
+    let pending: PendingTokenResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: generator_addr.to_string(),
+        msg: to_binary(&astroport::generator::QueryMsg::PendingToken {
+            lp_token: liquidity_token_addr.clone(),
+            user: user_addr.clone(),
+        })?,
+    }))?;
+
+    let mut rewards = AssetList::new();
+    rewards.add(&Asset::cw20(reward_info.base_reward_token, pending.pending))?;
+    if let (Some(proxy_reward_token), Some(pending_on_proxy)) =
+        (reward_info.proxy_reward_token, pending.pending_on_proxy)
+    {
+        rewards.add(&Asset::cw20(proxy_reward_token, pending_on_proxy))?;
+    }
+    rewards.purge();
+
+    Ok(rewards)
+}
+
 /// Query an Astroport pair contract of its basic info
 pub fn query_pair(querier: &QuerierWrapper, pair_addr: &Addr) -> StdResult<PairInfo> {
     querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
@@ -127,6 +283,25 @@ pub fn query_pool(querier: &QuerierWrapper, pair_addr: &Addr) -> StdResult<PoolR
     }))
 }
 
+/// Query an Astroport stableswap pair contract of its current amplification coefficient
+pub fn query_stable_amp(querier: &QuerierWrapper, pair_addr: &Addr) -> StdResult<u64> {
+    let config: ConfigResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_addr.to_string(),
+        msg: to_binary(&astroport::pair::QueryMsg::Config {})?,
+    }))?;
+    let params: StablePoolConfig = match config.params {
+        Some(params) => from_binary(&params)?,
+        None => {
+            return Err(StdError::generic_err(
+                "stableswap pair config contains no params",
+            ))
+        }
+    };
+
+    // Astroport reports the amplification coefficient as a `Decimal`, but it is always an integer
+    Ok((Uint128::new(1) * params.amp).u128() as u64)
+}
+
 /// Simulate the outcome of a swap
 pub fn query_simulation(
     querier: &QuerierWrapper,
@@ -141,25 +316,51 @@ pub fn query_simulation(
     }))
 }
 
+/// Simulate the amount returned by swapping through the Astroport router along the given route
+pub fn query_route_simulation(
+    querier: &QuerierWrapper,
+    router_addr: &Addr,
+    offer_amount: Uint128,
+    route: Vec<SwapOperation>,
+) -> StdResult<Uint128> {
+    let res: SimulateSwapOperationsResponse =
+        querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: router_addr.to_string(),
+            msg: to_binary(&astroport::router::QueryMsg::SimulateSwapOperations {
+                offer_amount,
+                operations: route,
+            })?,
+        }))?;
+    Ok(res.amount)
+}
+
 /// Generate a submessage for swapping an asset using an Astroport pool, and deduct the asset to be
 /// offered from the list of available assets.
 ///
-/// NOTE: 
-/// 
-/// - We use reply_id: 1
-/// - We use Astroport's maximum allowed slippage. To limit slippage, the frontend should calculate
-///   and supply the `minimum_received` parameter. 
+/// NOTE:
+///
+/// - We use reply_id: 1 for swaps during `Enter`, reply_id: 4 for swaps during `Exit`, and
+///   reply_id: 7 for swaps of withdrawn assets during `MigrateLiquidity`
+/// - If `max_spread` is not specified, we use Astroport's maximum allowed slippage. To limit
+///   slippage, the frontend should calculate and supply the `minimum_received` parameter.
 pub fn build_swap_submsgs(
-    pair_addr: &Addr, 
-    available_assets: &mut AssetList, 
+    pair_addr: &Addr,
+    available_assets: &mut AssetList,
     offer_asset: &Asset,
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
+    reply_id: u64,
 ) -> StdResult<Vec<SubMsg>> {
+    let max_spread = match max_spread {
+        Some(max_spread) => max_spread,
+        None => Decimal::from_str(MAX_ALLOWED_SLIPPAGE)?,
+    };
     let msg = match &offer_asset.info {
         AssetInfo::Cw20(_) => offer_asset.send_msg(
             pair_addr,
             to_binary(&astroport::pair::Cw20HookMsg::Swap {
-                belief_price: None,
-                max_spread: Some(Decimal::from_str(MAX_ALLOWED_SLIPPAGE)?),
+                belief_price,
+                max_spread: Some(max_spread),
                 to: None,
             })?,
         )?,
@@ -167,8 +368,8 @@ pub fn build_swap_submsgs(
             contract_addr: pair_addr.to_string(),
             msg: to_binary(&ExecuteMsg::Swap {
                 offer_asset: offer_asset.clone().into(),
-                belief_price: None,
-                max_spread: Some(Decimal::from_str(MAX_ALLOWED_SLIPPAGE)?),
+                belief_price,
+                max_spread: Some(max_spread),
                 to: None,
             })?,
             funds: vec![Coin {
@@ -180,16 +381,73 @@ pub fn build_swap_submsgs(
 
     available_assets.deduct(offer_asset)?;
 
-    Ok(vec![SubMsg::reply_on_success(msg, 1)])
+    Ok(vec![SubMsg::reply_on_success(msg, reply_id)])
+}
+
+/// Generate a submessage for swapping an asset through the Astroport router along the given route.
+/// The asset received is sent back to this contract
+///
+/// NOTE: We use reply_id: 5
+pub fn build_route_swap_submsgs(
+    router_addr: &Addr,
+    offer_asset: &Asset,
+    route: Vec<SwapOperation>,
+) -> StdResult<Vec<SubMsg>> {
+    let msg = match &offer_asset.info {
+        AssetInfo::Cw20(_) => offer_asset.send_msg(
+            router_addr,
+            to_binary(&astroport::router::Cw20HookMsg::ExecuteSwapOperations {
+                operations: route,
+                minimum_receive: None,
+                to: None,
+            })?,
+        )?,
+        AssetInfo::Native(denom) => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: router_addr.to_string(),
+            msg: to_binary(&astroport::router::ExecuteMsg::ExecuteSwapOperations {
+                operations: route,
+                minimum_receive: None,
+                to: None,
+            })?,
+            funds: vec![Coin {
+                denom: denom.clone(),
+                amount: offer_asset.amount,
+            }],
+        }),
+    };
+
+    Ok(vec![SubMsg::reply_on_success(msg, 5)])
+}
+
+/// Return the offer and ask asset infos of a swap operation
+pub fn parse_swap_operation(operation: &SwapOperation) -> (AssetInfo, AssetInfo) {
+    match operation {
+        SwapOperation::NativeSwap {
+            offer_denom,
+            ask_denom,
+        } => (AssetInfo::native(offer_denom), AssetInfo::native(ask_denom)),
+        SwapOperation::AstroSwap {
+            offer_asset_info,
+            ask_asset_info,
+        } => (offer_asset_info.into(), ask_asset_info.into()),
+    }
 }
 
 /// Generate submessages for providing liqudity to an Astroport pool, and deduct the assets to be
 /// provided from the list of available assets.
 ///
+/// If `auto_stake_receiver` is provided, the pair stakes the liquidity tokens in the Astroport
+/// generator on behalf of the receiver, instead of minting them to this contract.
+///
+/// If `slippage_tolerance` is provided, the pair rejects the provision if the ratio of the assets
+/// provided deviates from the pool's ratio by more than this.
+///
 /// NOTE: We use reply_id: 2
 pub fn build_provide_liquidity_submsgs(
     pair_addr: &Addr,
     available_assets: &mut AssetList,
+    auto_stake_receiver: Option<&Addr>,
+    slippage_tolerance: Option<Decimal>,
 ) -> StdResult<Vec<SubMsg>> {
     let mut submsgs: Vec<SubMsg> = vec![];
     let mut funds: Vec<Coin> = vec![];
@@ -221,9 +479,9 @@ pub fn build_provide_liquidity_submsgs(
             contract_addr: pair_addr.to_string(),
             msg: to_binary(&ExecuteMsg::ProvideLiquidity {
                 assets: assets_to_provide.try_into_legacy()?,
-                slippage_tolerance: None,
-                auto_stake: None,
-                receiver: None,
+                slippage_tolerance,
+                auto_stake: auto_stake_receiver.map(|_| true),
+                receiver: auto_stake_receiver.map(|receiver| receiver.to_string()),
             })?,
             funds,
         },
@@ -232,3 +490,50 @@ pub fn build_provide_liquidity_submsgs(
 
     Ok(submsgs)
 }
+
+/// Generate a message for withdrawing liquidity tokens staked by this contract from the Astroport
+/// generator. The generator pays out the pending rewards along with the liquidity tokens
+pub fn build_unstake_msg(
+    generator_addr: &Addr,
+    liquidity_token_addr: &Addr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: generator_addr.to_string(),
+        msg: to_binary(&astroport::generator::ExecuteMsg::Withdraw {
+            lp_token: liquidity_token_addr.clone(),
+            amount,
+        })?,
+        funds: vec![],
+    }))
+}
+
+/// Generate a submessage for claiming the rewards pending for this contract's liquidity tokens
+/// staked in the Astroport generator, by withdrawing zero liquidity tokens
+///
+/// NOTE: We use reply_id: 8
+pub fn build_claim_rewards_submsgs(
+    generator_addr: &Addr,
+    liquidity_token_addr: &Addr,
+) -> StdResult<Vec<SubMsg>> {
+    let msg = build_unstake_msg(generator_addr, liquidity_token_addr, Uint128::zero())?;
+    Ok(vec![SubMsg::reply_on_success(msg, 8)])
+}
+
+/// Generate a submessage for burning liquidity tokens of an Astroport pool in exchange for the
+/// underlying assets
+///
+/// NOTE: We use reply_id: 3 for withdrawals during `Exit`, and reply_id: 6 for withdrawals during
+/// `MigrateLiquidity`
+pub fn build_withdraw_liquidity_submsgs(
+    pair_addr: &Addr,
+    shares: &Asset,
+    reply_id: u64,
+) -> StdResult<Vec<SubMsg>> {
+    let msg = shares.send_msg(
+        pair_addr,
+        to_binary(&astroport::pair::Cw20HookMsg::WithdrawLiquidity {})?,
+    )?;
+
+    Ok(vec![SubMsg::reply_on_success(msg, reply_id)])
+}
@@ -4,7 +4,7 @@ pub mod math;
 pub mod msg;
 pub mod state;
 
-#[cfg(test)]
-mod test_utils;
 #[cfg(test)]
 mod contract_tests;
+#[cfg(test)]
+mod test_utils;
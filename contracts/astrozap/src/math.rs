@@ -1,119 +1,964 @@
-use cw_bigint::BigInt;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Neg;
 
-/// The maximum number of iterations to do when solving the quadratic equation
-const MAX_ITERATIONS: usize = 32;
+use cosmwasm_std::{Decimal, StdError, StdResult, Uint128, Uint512};
 
-/// Commission rate of the Astroport XYK pool, set to 0.3%
+/// The maximum number of iterations to do when computing the StableSwap invariant or pool depths
+const MAX_ITERATIONS: u32 = 32;
+
+/// The maximum number of bisections to do when solving the stableswap equation. Each bisection
+/// halves the search range, so 128 is enough to narrow down any `u128` range to a single integer
+const MAX_BISECTIONS: u32 = 128;
+
+/// Number of assets in an Astroport stableswap pool
+const N_COINS: u64 = 2;
+
+/// Number of integer offer amounts on each side of the solver's root that are evaluated when
+/// refining the solution against the XYK pair's rounding
+const REFINEMENT_RADIUS: u128 = 8;
+
+/// Decimal fractional precision used by Astroport's XYK pair when computing swaps
+const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+
+/// A signed 512-bit integer, represented by its sign and absolute value
 ///
-/// We can technically query the factory contract for this number, but this is, in my opinion,
-/// unnecessary and a waste of gas because the rate is almost never going to change. If it does
-/// change, we can always update this constant here and migrate the contract.
-const COMMISSION_RATE_BPS: u64 = 30;
+/// `cosmwasm_std` only provides unsigned fixed-width integers, while the coefficients of `Quadratic`
+/// and the values of the functions we solve may be negative. Only the operations needed by the
+/// solvers are implemented. Same as `BigInt`, division truncates toward zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Int512 {
+    negative: bool,
+    abs: Uint512,
+}
+
+impl Int512 {
+    pub fn new(negative: bool, abs: Uint512) -> Self {
+        Self {
+            // zero is always non-negative, so that equality comparison works as expected
+            negative: negative && !abs.is_zero(),
+            abs,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::new(false, Uint512::zero())
+    }
+
+    /// Compute `x - y` for unsigned `x` and `y`; unlike `Uint512::checked_sub`, this never fails
+    pub fn difference(x: Uint512, y: Uint512) -> Self {
+        if x >= y {
+            Self::new(false, x - y)
+        } else {
+            Self::new(true, y - x)
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> Uint512 {
+        self.abs
+    }
+
+    pub fn checked_add(self, other: Self) -> StdResult<Self> {
+        if self.negative == other.negative {
+            Ok(Self::new(self.negative, self.abs.checked_add(other.abs)?))
+        } else if self.negative {
+            Ok(Self::difference(other.abs, self.abs))
+        } else {
+            Ok(Self::difference(self.abs, other.abs))
+        }
+    }
+
+    pub fn checked_sub(self, other: Self) -> StdResult<Self> {
+        self.checked_add(-other)
+    }
+
+    pub fn checked_mul(self, other: Self) -> StdResult<Self> {
+        Ok(Self::new(
+            self.negative != other.negative,
+            self.abs.checked_mul(other.abs)?,
+        ))
+    }
+
+    pub fn checked_div(self, other: Self) -> StdResult<Self> {
+        Ok(Self::new(
+            self.negative != other.negative,
+            self.abs.checked_div(other.abs)?,
+        ))
+    }
+
+    /// Convert to `Uint128`, failing if the number is negative or too big
+    pub fn to_uint128(self) -> StdResult<Uint128> {
+        if self.negative {
+            return Err(StdError::generic_err(format!(
+                "integer is negative: {}",
+                self
+            )));
+        }
+        Uint128::try_from(self.abs).map_err(|err| StdError::generic_err(err.to_string()))
+    }
+}
+
+impl From<Uint512> for Int512 {
+    fn from(abs: Uint512) -> Self {
+        Self::new(false, abs)
+    }
+}
+
+impl From<Uint128> for Int512 {
+    fn from(abs: Uint128) -> Self {
+        Self::new(false, abs.into())
+    }
+}
+
+impl From<u128> for Int512 {
+    fn from(abs: u128) -> Self {
+        Self::new(false, abs.into())
+    }
+}
+
+impl Neg for Int512 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(!self.negative, self.abs)
+    }
+}
+
+impl Ord for Int512 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.abs.cmp(&other.abs),
+            (true, true) => other.abs.cmp(&self.abs),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+}
+
+impl PartialOrd for Int512 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Int512 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negative {
+            write!(f, "-{}", self.abs)
+        } else {
+            write!(f, "{}", self.abs)
+        }
+    }
+}
+
+/// Outcome of solving an equation, along with diagnostics of the solver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Solution {
+    /// The solution, i.e. the amount of offer asset to swap
+    pub root: Uint128,
+    /// Number of iterations used by the solver; zero for closed-form solutions
+    pub iterations: u32,
+    /// Value of the function at `root`
+    pub residual: Int512,
+    /// Whether the solver converged before reaching the maximum number of iterations. Always true
+    /// for the closed-form solution of `Quadratic`, and for the bisection of `StableSwap`, which
+    /// narrows any 128-bit range down to one within `MAX_BISECTIONS`
+    pub converged: bool,
+}
 
 /// Equation describing the relation between the optimal swap amount (x) and the asset amounts. It
 /// is a quadratic equation of the form `a * x^2 + b * x + c = 0` where `a, b, c >= 0`. For details,
 /// see the document `docs/astrozap.pdf`
 pub struct Quadratic {
     /// Coefficient of the quadratic term
-    pub a: BigInt,
+    pub a: Int512,
     /// Coefficient of the linear term
-    pub b: BigInt,
+    pub b: Int512,
     /// The constant term
-    pub c: BigInt,
+    pub c: Int512,
 }
 
 impl Quadratic {
     /// Create a new quadratic equation instance using asset amounts defined in the `docs/astrozap.pdf`
+    ///
+    /// `commission_rate_bps` is the pair's total commission rate in basis points, as reported by the
+    /// Astroport factory
+    ///
+    /// With 128-bit inputs, none of the terms can exceed 400 bits, so no overflow check is needed
     pub fn from_asset_amounts(
-        offer_user: &BigInt,
-        offer_pool: &BigInt,
-        ask_user: &BigInt,
-        ask_pool: &BigInt,
+        offer_user: Uint128,
+        offer_pool: Uint128,
+        ask_user: Uint128,
+        ask_pool: Uint128,
+        commission_rate_bps: u64,
     ) -> Self {
+        let offer_user = Uint512::from(offer_user);
+        let offer_pool = Uint512::from(offer_pool);
+        let ask_user = Uint512::from(ask_user);
+        let ask_pool = Uint512::from(ask_pool);
+
         let a = ask_pool + ask_user;
 
         // the 1st term of b
-        let b1 = offer_pool * &a * 2;
+        let b1 = offer_pool * a * Uint512::from(2u8);
         // the 2nd term of b
-        let b2 = ask_pool * (offer_pool + offer_user) * COMMISSION_RATE_BPS / 10000;
+        let b2 = ask_pool * (offer_pool + offer_user) * Uint512::from(commission_rate_bps)
+            / Uint512::from(10000u16);
         // combine the two terms
-        let b = b1 - b2;
+        let b = Int512::difference(b1, b2);
 
-        let c = offer_pool * (offer_pool * ask_user - offer_user * ask_pool);
+        let c = Int512::difference(
+            offer_pool * offer_pool * ask_user,
+            offer_pool * offer_user * ask_pool,
+        );
 
-        Self { a, b, c }
+        Self { a: a.into(), b, c }
     }
 
     /// Compute value of the function by the given x
     ///
     /// f(x) = a * x * x + b * x + c
-    pub fn compute_value(&self, x: &BigInt) -> BigInt {
-        &self.a * x * x + &self.b * x + &self.c
+    pub fn compute_value(&self, x: Int512) -> StdResult<Int512> {
+        self.a
+            .checked_mul(x)?
+            .checked_mul(x)?
+            .checked_add(self.b.checked_mul(x)?)?
+            .checked_add(self.c)
     }
 
-    /// Compute value of the function by the given x
+    /// Solve the quadratic equation `a * x^2 + b * x + c = 0` using the quadratic formula
     ///
-    /// f'(x) = 2 * a * x + b
-    pub fn compute_deriv_value(&self, x: &BigInt) -> BigInt {
-        2 * &self.a * x + &self.b
+    /// x = (-b + isqrt(b^2 - 4ac)) / 2a
+    ///
+    /// Since `a > 0` and `c <= 0`, this is the equation's only non-negative root. Flooring the
+    /// square root before dividing gives the same result as flooring the exact root, so the output
+    /// is the greatest integer not exceeding the optimal swap amount.
+    pub fn solve_exact(&self) -> StdResult<Solution> {
+        if self.a == Int512::zero() {
+            return Err(StdError::generic_err("quadratic term is zero"));
+        }
+
+        let discriminant = self.b.checked_mul(self.b)?.checked_sub(
+            Int512::from(4u128)
+                .checked_mul(self.a)?
+                .checked_mul(self.c)?,
+        )?;
+        if discriminant.is_negative() {
+            return Err(StdError::generic_err(
+                "quadratic equation has negative discriminant",
+            ));
+        }
+
+        let root = Int512::from(isqrt(discriminant.abs()))
+            .checked_sub(self.b)?
+            .checked_div(Int512::from(2u128).checked_mul(self.a)?)?;
+
+        Ok(Solution {
+            root: root.to_uint128()?,
+            iterations: 0,
+            residual: self.compute_value(root)?,
+            converged: true,
+        })
     }
+}
 
-    /// Solve the quadratic equation `a * x^2 + b * x + c = 0` using Newton's method
+/// Compute the integer square root of a number, i.e. the greatest integer whose square does not
+/// exceed it, using Newton's method
+///
+/// The initial guess `2^(floor(bits / 2) + 1)` is always greater than the square root, from which the
+/// iterations decrease monotonically until reaching the answer.
+pub fn isqrt(n: Uint512) -> Uint512 {
+    if n.is_zero() {
+        return n;
+    }
+
+    let mut x = pow2(bits(n) / 2 + 1);
+    loop {
+        let y = (x + n / x) / Uint512::from(2u8);
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Number of bits needed to represent the number, i.e. the position of its highest set bit
+fn bits(n: Uint512) -> u32 {
+    let bytes = n.to_be_bytes();
+    match bytes.iter().position(|byte| *byte != 0) {
+        Some(i) => (64 - i as u32) * 8 - bytes[i].leading_zeros(),
+        None => 0,
+    }
+}
+
+/// Compute 2 to the power of the given exponent. `Uint512` does not implement the left shift
+/// operator, so we set the bit directly
+fn pow2(exp: u32) -> Uint512 {
+    let mut bytes = [0u8; 64];
+    bytes[63 - (exp / 8) as usize] = 1 << (exp % 8);
+    Uint512::from_be_bytes(bytes)
+}
+
+/// Equation describing the relation between the optimal swap amount (x) and the asset amounts in an
+/// Astroport stableswap pool
+///
+/// Same as with XYK pools, the optimal swap is the one after which the user's assets are in the same
+/// ratio as the pool's, so that the liquidity tokens minted are not limited by either asset:
+///
+/// f(x) = (offer_user - x) * (ask_pool - y(x) - m(x)) - (ask_user + y(x)) * (offer_pool + x) = 0
+///
+/// where y(x) is the return amount of swapping x under the StableSwap invariant, and m(x) the
+/// maker fee, which also leaves the pool. Unlike the XYK case, y(x) does not have a closed form, so
+/// the equation cannot be reduced to a polynomial
+pub struct StableSwap {
+    pub offer_user: Uint128,
+    pub offer_pool: Uint128,
+    pub ask_user: Uint128,
+    pub ask_pool: Uint128,
+    /// Amplification coefficient of the pool
+    pub amp: u64,
+    /// The pool's total commission rate in basis points
+    pub commission_rate_bps: u64,
+    /// The share of the commission sent to the maker, in basis points; zero if the factory has no
+    /// fee address
+    pub maker_fee_bps: u64,
+}
+
+impl StableSwap {
+    /// Create a new stableswap equation instance using the asset amounts, the pool's amplification
+    /// coefficient, its commission rate, and the maker's share of the commission
+    pub fn from_asset_amounts(
+        offer_user: Uint128,
+        offer_pool: Uint128,
+        ask_user: Uint128,
+        ask_pool: Uint128,
+        amp: u64,
+        commission_rate_bps: u64,
+        maker_fee_bps: u64,
+    ) -> Self {
+        Self {
+            offer_user,
+            offer_pool,
+            ask_user,
+            ask_pool,
+            amp,
+            commission_rate_bps,
+            maker_fee_bps,
+        }
+    }
+
+    /// Compute the amount of ask asset returned by swapping x offer asset, after commission, and
+    /// the amount of ask asset sent to the maker
+    ///
+    /// Follows `compute_swap` of Astroport's stableswap pair contract
+    pub fn compute_swap(&self, x: Uint128) -> StdResult<(Uint512, Uint512)> {
+        let leverage = self.amp * N_COINS;
+        let d = compute_d(leverage, self.offer_pool, self.ask_pool)?;
+        let new_ask_pool = compute_y(
+            leverage,
+            Uint512::from(self.offer_pool) + Uint512::from(x),
+            d,
+        )?;
+
+        let return_amount = Uint512::from(self.ask_pool).saturating_sub(new_ask_pool);
+        let commission_amount =
+            return_amount * Uint512::from(self.commission_rate_bps) / Uint512::from(10000u16);
+        let maker_fee_amount =
+            commission_amount * Uint512::from(self.maker_fee_bps) / Uint512::from(10000u16);
+
+        Ok((return_amount - commission_amount, maker_fee_amount))
+    }
+
+    /// Compute value of the function by the given x
+    pub fn compute_value(&self, x: Uint128) -> StdResult<Int512> {
+        let (y, maker_fee_amount) = self.compute_swap(x)?;
+        Ok(Int512::difference(
+            Uint512::from(self.offer_user - x)
+                * (Uint512::from(self.ask_pool) - y - maker_fee_amount),
+            (Uint512::from(self.ask_user) + y)
+                * (Uint512::from(self.offer_pool) + Uint512::from(x)),
+        ))
+    }
+
+    /// Solve the equation `f(x) = 0` using bisection over `[0, offer_user]`
     ///
-    /// If `MAX_ITERATION` is reached without convergence, we simply return the latest x value. The
-    /// x value at this time does not represent the optimal swap amount, but it is fine because we
-    /// will check slippage tolerance at the very end of the function call, so liquidity provisions
-    /// with too big slippage will be reverted.
-    /// 
-    /// Also, in practice, almost all such equations converge in 4 - 5 iterations.
-    pub fn solve(&self) -> BigInt {
-        let mut x_prev: BigInt = 0.into();
-        let mut x = x_prev.clone();
-
-        // x_{n+1} = x_n - f(x_n) / f'(x_n)
-        for _ in 0..MAX_ITERATIONS {
-            let val = self.compute_value(&x_prev);
-            let deriv_val = self.compute_deriv_value(&x_prev);
-            x = &x_prev - val / deriv_val;
-            if x == x_prev {
-                break;
+    /// f(x) is monotonically decreasing in this range, so bisection is guaranteed to converge. We
+    /// return the greatest integer x such that f(x) >= 0, i.e. we err on the side of swapping
+    /// slightly less than optimal.
+    pub fn solve(&self) -> StdResult<Solution> {
+        let zero = Int512::zero();
+        let mut low = Uint128::zero();
+        let mut high = self.offer_user;
+        let mut iterations = 0;
+
+        let low_value = self.compute_value(low)?;
+        if low_value <= zero {
+            return Ok(Solution {
+                root: low,
+                iterations,
+                residual: low_value,
+                converged: true,
+            });
+        }
+        let high_value = self.compute_value(high)?;
+        if high_value >= zero {
+            return Ok(Solution {
+                root: high,
+                iterations,
+                residual: high_value,
+                converged: true,
+            });
+        }
+
+        while iterations < MAX_BISECTIONS && high - low > Uint128::new(1) {
+            let mid = low + (high - low) / Uint128::new(2);
+            if self.compute_value(mid)? >= zero {
+                low = mid;
             } else {
-                x_prev = x.clone()
+                high = mid;
             }
+            iterations += 1;
         }
 
-        x
+        Ok(Solution {
+            root: low,
+            iterations,
+            residual: self.compute_value(low)?,
+            converged: high - low <= Uint128::new(1),
+        })
+    }
+}
+
+/// Compute the StableSwap invariant D of a pool with the given asset depths using Newton's method
+///
+/// Follows `compute_d` of Astroport's stableswap pair contract, where `leverage = amp * N_COINS`.
+/// With 128-bit inputs, D is at most 129 bits and D^3 at most 387 bits, so 512-bit arithmetic does
+/// not overflow. Fails if either depth is zero
+pub fn compute_d(leverage: u64, amount_a: Uint128, amount_b: Uint128) -> StdResult<Uint512> {
+    let n_coins = Uint512::from(N_COINS);
+    let leverage = Uint512::from(leverage);
+    let amount_a = Uint512::from(amount_a);
+    let amount_b = Uint512::from(amount_b);
+
+    let sum_x = amount_a + amount_b;
+    if sum_x.is_zero() {
+        return Ok(sum_x);
+    }
+
+    let mut d = sum_x;
+    for _ in 0..MAX_ITERATIONS {
+        let d_product =
+            ((d * d).checked_div(amount_a * n_coins)? * d).checked_div(amount_b * n_coins)?;
+        let d_prev = d;
+        // d = (leverage * sum_x + d_p * n_coins) * d / ((leverage - 1) * d + (n_coins + 1) * d_p)
+        d = ((leverage * sum_x + d_product * n_coins) * d).checked_div(
+            leverage.checked_sub(Uint512::from(1u8))? * d
+                + (n_coins + Uint512::from(1u8)) * d_product,
+        )?;
+        if d == d_prev {
+            break;
+        }
+    }
+
+    Ok(d)
+}
+
+/// Compute the depth of one asset in the pool given the depth of the other and the invariant D,
+/// using Newton's method
+///
+/// Follows `calc_y` of Astroport's stableswap pair contract, where `leverage = amp * N_COINS`
+pub fn compute_y(leverage: u64, new_source_amount: Uint512, d: Uint512) -> StdResult<Uint512> {
+    let n_coins = Uint512::from(N_COINS);
+    let leverage = Uint512::from(leverage);
+
+    // c = D ** (n + 1) / (n ** n * prod' * leverage)
+    let c = (d * d * d).checked_div(new_source_amount * n_coins * n_coins * leverage)?;
+    // b = sum' + D / leverage
+    let b = new_source_amount + d.checked_div(leverage)?;
+
+    // y = (y ** 2 + c) / (2 * y + b - D)
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        y = (y * y + c).checked_div((n_coins * y + b).checked_sub(d)?)?;
+        if y == y_prev {
+            break;
+        }
+    }
+
+    Ok(y)
+}
+
+/// Compute the marginal price of the ask asset, in units of the offer asset, in a stableswap pool,
+/// i.e. the price an infinitesimal swap would get before commission
+///
+/// Differentiating the invariant `leverage * (x + y) + D = leverage * D + D^3 / (4 * x * y)`, where
+/// x and y are the offer and ask pool depths, gives:
+///
+/// -dx/dy = (leverage + D^3 / (4 * x * y^2)) / (leverage + D^3 / (4 * x^2 * y))
+///
+/// We multiply both terms by `x * y` so that the only division is the final one. As `leverage`
+/// approaches zero, this reduces to `x / y`, the spot price of an XYK pool
+pub fn compute_stable_spot_price(
+    amp: u64,
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+) -> StdResult<Decimal> {
+    let leverage = amp * N_COINS;
+    let d = compute_d(leverage, offer_pool, ask_pool)?;
+    let d_cubed_over_n_n = d * d * d / Uint512::from(N_COINS * N_COINS);
+    let leverage_x_y =
+        Uint512::from(leverage) * Uint512::from(offer_pool) * Uint512::from(ask_pool);
+
+    let numerator = leverage_x_y + d_cubed_over_n_n.checked_div(Uint512::from(ask_pool))?;
+    let denominator = leverage_x_y + d_cubed_over_n_n.checked_div(Uint512::from(offer_pool))?;
+
+    let price = (numerator * Uint512::from(DECIMAL_FRACTIONAL)).checked_div(denominator)?;
+    let price = Uint128::try_from(price).map_err(|err| StdError::generic_err(err.to_string()))?;
+    Ok(Decimal::from_ratio(price, DECIMAL_FRACTIONAL))
+}
+
+/// Exact reimplementation of the swap and liquidity provision math of Astroport's XYK pair,
+/// including the rounding applied at each step
+///
+/// The root of `Quadratic` is the real-valued optimum, while the pair rounds down both the return
+/// amount of the swap and the amount of liquidity tokens minted. We use this to evaluate integer
+/// offer amounts around the root and pick the one that actually mints the most shares
+pub struct XykPair {
+    pub offer_user: Uint128,
+    pub offer_pool: Uint128,
+    pub ask_user: Uint128,
+    pub ask_pool: Uint128,
+    pub total_share: Uint128,
+    /// The pool's total commission rate in basis points
+    pub commission_rate_bps: u64,
+    /// The share of the commission sent to the maker, in basis points; zero if the factory has no
+    /// fee address
+    pub maker_fee_bps: u64,
+}
+
+/// Outcome of swapping `offer_amount` in an XYK pool and providing the resulting assets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XykOutcome {
+    pub offer_amount: Uint128,
+    pub return_amount: Uint128,
+    /// Amount of liquidity tokens minted
+    pub shares: Uint128,
+    /// Amount of offer asset in excess of the pool's ratio, which is refunded to the user
+    pub refund_offer: Uint128,
+    /// Amount of ask asset in excess of the pool's ratio, which is refunded to the user
+    pub refund_ask: Uint128,
+}
+
+impl XykPair {
+    /// Compute the amount of ask asset returned by swapping x offer asset, after commission, and
+    /// the amount of ask asset sent to the maker
+    ///
+    /// Follows `compute_swap` of Astroport's XYK pair contract, which computes
+    /// `ask_pool - offer_pool * ask_pool / (offer_pool + x)` as a decimal with 18 fractional digits
+    /// before rounding it down
+    pub fn compute_swap(&self, x: Uint128) -> StdResult<(Uint128, Uint128)> {
+        let fractional = Uint512::from(DECIMAL_FRACTIONAL);
+        let offer_pool = Uint512::from(self.offer_pool);
+        let ask_pool = Uint512::from(self.ask_pool);
+
+        let cp = offer_pool * ask_pool;
+        let return_amount = (ask_pool * fractional
+            - cp * fractional / (offer_pool + Uint512::from(x)))
+            / fractional;
+        let commission_amount =
+            return_amount * Uint512::from(self.commission_rate_bps) / Uint512::from(10000u16);
+        let maker_fee_amount =
+            commission_amount * Uint512::from(self.maker_fee_bps) / Uint512::from(10000u16);
+
+        Ok((
+            Int512::from(return_amount - commission_amount).to_uint128()?,
+            Int512::from(maker_fee_amount).to_uint128()?,
+        ))
+    }
+
+    /// Compute the outcome of swapping x offer asset, then providing the user's assets
+    pub fn compute_outcome(&self, x: Uint128) -> StdResult<XykOutcome> {
+        let (return_amount, maker_fee_amount) = self.compute_swap(x)?;
+
+        let deposits = [
+            self.offer_user.checked_sub(x)?,
+            self.ask_user.checked_add(return_amount)?,
+        ];
+        let pools = [
+            self.offer_pool.checked_add(x)?,
+            self.ask_pool
+                .checked_sub(return_amount)?
+                .checked_sub(maker_fee_amount)?,
+        ];
+        let (assets_used, shares) = compute_xyk_provide(deposits, pools, self.total_share)?;
+
+        Ok(XykOutcome {
+            offer_amount: x,
+            return_amount,
+            shares,
+            refund_offer: deposits[0] - assets_used[0],
+            refund_ask: deposits[1] - assets_used[1],
+        })
+    }
+
+    /// Evaluate the integer offer amounts within `REFINEMENT_RADIUS` of the given root, and return
+    /// the outcome that mints the most shares. Among outcomes minting the same amount, we pick the
+    /// one with the least refund, valued at the pool's price before the swap
+    pub fn refine(&self, root: Uint128) -> StdResult<XykOutcome> {
+        let radius = Uint128::new(REFINEMENT_RADIUS);
+        let low = root.saturating_sub(radius);
+        let high = std::cmp::min(root.saturating_add(radius), self.offer_user);
+
+        let mut best = self.compute_outcome(low)?;
+        let mut x = low + Uint128::new(1);
+        while x <= high {
+            let outcome = self.compute_outcome(x)?;
+            if outcome.shares > best.shares
+                || (outcome.shares == best.shares
+                    && self.refund_value(&outcome) < self.refund_value(&best))
+            {
+                best = outcome;
+            }
+            x += Uint128::new(1);
+        }
+
+        Ok(best)
+    }
+
+    /// Value of the refund in an outcome, in units of the ask asset multiplied by `offer_pool`
+    fn refund_value(&self, outcome: &XykOutcome) -> Uint512 {
+        Uint512::from(outcome.refund_offer) * Uint512::from(self.ask_pool)
+            + Uint512::from(outcome.refund_ask) * Uint512::from(self.offer_pool)
+    }
+}
+
+/// Compute the amounts of assets used by an XYK pair to mint liquidity tokens when providing the
+/// given deposits, and the amount of liquidity tokens minted
+///
+/// The pair mints `min(deposit_i * total_share / pool_i)` shares, rounded down, and keeps all the
+/// deposits. The least deposits that mint the same amount are `ceil(shares * pool_i / total_share)`;
+/// anything beyond them is excess that the contract refunds instead of providing
+pub fn compute_xyk_provide(
+    deposits: [Uint128; 2],
+    pools: [Uint128; 2],
+    total_share: Uint128,
+) -> StdResult<([Uint128; 2], Uint128)> {
+    let shares = std::cmp::min(
+        deposits[0].multiply_ratio(total_share, pools[0]),
+        deposits[1].multiply_ratio(total_share, pools[1]),
+    );
+
+    let assets_used = [
+        multiply_ratio_ceil(shares, pools[0], total_share)?,
+        multiply_ratio_ceil(shares, pools[1], total_share)?,
+    ];
+
+    Ok((assets_used, shares))
+}
+
+/// Compute the amount of liquidity tokens minted by a stableswap pair when providing the given
+/// deposits
+///
+/// Follows `provide_liquidity` of Astroport's stableswap pair contract, which keeps all the
+/// deposits regardless of their ratio, and mints shares in proportion to the increase of the
+/// invariant D:
+/// `total_share * (D(pools + deposits) - D(pools)) / D(pools)`, rounded down
+pub fn compute_stable_provide(
+    amp: u64,
+    deposits: [Uint128; 2],
+    pools: [Uint128; 2],
+    total_share: Uint128,
+) -> StdResult<Uint128> {
+    let leverage = amp * N_COINS;
+    let init_d = compute_d(leverage, pools[0], pools[1])?;
+    let deposit_d = compute_d(
+        leverage,
+        pools[0].checked_add(deposits[0])?,
+        pools[1].checked_add(deposits[1])?,
+    )?;
+
+    let shares = Uint512::from(total_share)
+        .checked_mul(deposit_d.checked_sub(init_d)?)?
+        .checked_div(init_d)?;
+    Int512::from(shares).to_uint128()
+}
+
+/// Compute `ceil(x * numerator / denominator)`
+fn multiply_ratio_ceil(x: Uint128, numerator: Uint128, denominator: Uint128) -> StdResult<Uint128> {
+    let product = Uint512::from(x).checked_mul(Uint512::from(numerator))?;
+    let denominator = Uint512::from(denominator);
+    let quotient = product.checked_div(denominator)?;
+    if quotient * denominator == product {
+        Int512::from(quotient).to_uint128()
+    } else {
+        Int512::from(quotient + Uint512::from(1u8)).to_uint128()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::helpers::bigint_to_uint128;
-    use cosmwasm_std::Uint128;
 
     fn mock_equation() -> Quadratic {
         Quadratic::from_asset_amounts(
-            &100000000000u128.into(),
-            &118070429547232u128.into(),
-            &0.into(),
-            &1451993415113u128.into(),
+            Uint128::new(100000000000),
+            Uint128::new(118070429547232),
+            Uint128::zero(),
+            Uint128::new(1451993415113),
+            30,
         )
     }
 
     #[test]
     fn should_create_from_asset_amounts() {
         let qe = mock_equation();
-        assert_eq!(qe.a, BigInt::from(1451993415113u128));
-        assert_eq!(qe.b, BigInt::from(342360224387597541370186081u128));
-        assert_eq!(qe.c,BigInt::from(-17143748622214425401611721600000000000i128));
+        assert_eq!(qe.a, Int512::from(1451993415113u128));
+        assert_eq!(qe.b, Int512::from(342360224387597541370186081u128));
+        assert_eq!(
+            qe.c,
+            -Int512::from(Uint512::from(17143748622214425401611721600000000000u128))
+        );
+    }
+
+    #[test]
+    fn should_handle_signed_arithmetic() {
+        let two = Int512::from(2u128);
+        let three = Int512::from(3u128);
+        assert_eq!(two.checked_sub(three).unwrap(), -Int512::from(1u128));
+        assert_eq!((-two).checked_add(three).unwrap(), Int512::from(1u128));
+        assert_eq!((-two).checked_mul(-three).unwrap(), Int512::from(6u128));
+        assert_eq!((-three).checked_div(two).unwrap(), -Int512::from(1u128));
+        assert_eq!(two.checked_sub(two).unwrap(), Int512::zero());
+        assert!(-three < -two && -two < Int512::zero() && Int512::zero() < two);
+        assert_eq!((-two).to_string(), "-2");
+        assert_eq!(
+            (-two).to_uint128(),
+            Err(StdError::generic_err("integer is negative: -2"))
+        );
+    }
+
+    #[test]
+    fn should_compute_integer_square_root() {
+        assert_eq!(isqrt(Uint512::from(0u128)), Uint512::from(0u128));
+        assert_eq!(isqrt(Uint512::from(1u128)), Uint512::from(1u128));
+        assert_eq!(isqrt(Uint512::from(15u128)), Uint512::from(3u128));
+        assert_eq!(isqrt(Uint512::from(16u128)), Uint512::from(4u128));
+        assert_eq!(isqrt(Uint512::from(17u128)), Uint512::from(4u128));
+
+        let root = Uint512::from(u128::MAX);
+        assert_eq!(isqrt(root * root), root);
+        assert_eq!(
+            isqrt(root * root - Uint512::from(1u8)),
+            root - Uint512::from(1u8)
+        );
+    }
+
+    fn mock_stableswap_equation() -> StableSwap {
+        StableSwap::from_asset_amounts(
+            Uint128::new(100000000000),
+            Uint128::new(2937863752918),
+            Uint128::zero(),
+            Uint128::new(2961459937027),
+            10,
+            5,
+            0,
+        )
     }
 
     #[test]
-    fn should_solve_equation() {
+    fn should_solve_equation_exactly() {
         let qe = mock_equation();
-        let offer_amount_bi = qe.solve();
-        let offer_amount = bigint_to_uint128(&offer_amount_bi).unwrap();
-        assert_eq!(offer_amount, Uint128::new(50064546170u128));
+        let solution = qe.solve_exact().unwrap();
+        let offer_amount = solution.root;
+        assert_eq!(offer_amount, Uint128::new(50064546169u128));
+        assert_eq!(solution.iterations, 0);
+        assert!(solution.converged);
+
+        // The solution must be the greatest integer at which the function value is non-positive
+        let zero = Int512::zero();
+        assert!(qe.compute_value(offer_amount.into()).unwrap() <= zero);
+        assert!(
+            qe.compute_value((offer_amount + Uint128::new(1)).into())
+                .unwrap()
+                > zero
+        );
+    }
+
+    #[test]
+    fn should_reject_negative_discriminant() {
+        // x^2 + 1 = 0 has no real root
+        let qe = Quadratic {
+            a: 1u128.into(),
+            b: 0u128.into(),
+            c: 1u128.into(),
+        };
+        assert_eq!(
+            qe.solve_exact(),
+            Err(StdError::generic_err(
+                "quadratic equation has negative discriminant"
+            ))
+        );
+    }
+
+    #[test]
+    fn should_compute_stableswap_invariant() {
+        // For a balanced pool, the invariant equals the sum of the asset depths
+        let amount = Uint128::new(1000000000000);
+        let d = compute_d(20, amount, amount).unwrap();
+        assert_eq!(d, Uint512::from(2000000000000u128));
+        assert_eq!(compute_y(20, amount.into(), d).unwrap(), amount.into());
+    }
+
+    #[test]
+    fn should_reject_empty_stable_reserve() {
+        // An empty reserve must fail instead of panicking on division by zero
+        let amount = Uint128::new(1000000000000);
+        assert!(compute_d(20, amount, Uint128::zero()).is_err());
+        assert!(compute_stable_spot_price(10, Uint128::zero(), amount).is_err());
+
+        let eq = StableSwap {
+            ask_pool: Uint128::zero(),
+            ..mock_stableswap_equation()
+        };
+        assert!(eq.solve().is_err());
+    }
+
+    #[test]
+    fn should_compute_stable_spot_price() {
+        // For a balanced pool, the spot price is 1 regardless of the amplification
+        let amount = Uint128::new(1000000000000);
+        assert_eq!(
+            compute_stable_spot_price(10, amount, amount).unwrap(),
+            Decimal::one()
+        );
+
+        // For an imbalanced pool, the spot price lies between 1 and the XYK price, closer to 1 the
+        // higher the amplification
+        let offer_pool = Uint128::new(3000000000000);
+        let ask_pool = Uint128::new(1000000000000);
+        let xyk_price = Decimal::from_ratio(offer_pool, ask_pool);
+        let low_amp_price = compute_stable_spot_price(1, offer_pool, ask_pool).unwrap();
+        let high_amp_price = compute_stable_spot_price(100, offer_pool, ask_pool).unwrap();
+        assert!(Decimal::one() < high_amp_price);
+        assert!(high_amp_price < low_amp_price);
+        assert!(low_amp_price < xyk_price);
+
+        // Swapping the other way quotes the reciprocal price
+        let reverse_price = compute_stable_spot_price(100, ask_pool, offer_pool).unwrap();
+        let product = high_amp_price * Uint128::new(1000000) * reverse_price;
+        assert!(product >= Uint128::new(999999) && product <= Uint128::new(1000000));
+    }
+
+    #[test]
+    fn should_solve_stableswap_equation() {
+        let eq = mock_stableswap_equation();
+        let solution = eq.solve().unwrap();
+        let offer_amount = solution.root;
+        assert_eq!(offer_amount, Uint128::new(49395594273u128));
+        assert!(solution.converged);
+
+        // The solution must be the greatest integer at which the function value is non-negative
+        let zero = Int512::zero();
+        assert!(eq.compute_value(offer_amount).unwrap() >= zero);
+        assert!(eq.compute_value(offer_amount + Uint128::new(1)).unwrap() < zero);
+    }
+
+    #[test]
+    fn should_deduct_stable_maker_fee() {
+        let eq = StableSwap {
+            maker_fee_bps: 5000,
+            ..mock_stableswap_equation()
+        };
+
+        // Half of the commission is sent to the maker, leaving the return amount unchanged
+        let offer_amount = Uint128::new(49395594273u128);
+        let (return_amount, maker_fee_amount) = eq.compute_swap(offer_amount).unwrap();
+        assert_eq!(return_amount, Uint512::from(49331679227u128));
+        assert_eq!(maker_fee_amount, Uint512::from(12339089u128));
+
+        // The maker fee leaves the pool, so less needs to be swapped to reach the pool's ratio
+        let solution = eq.solve().unwrap();
+        assert!(solution.root < offer_amount);
+        let zero = Int512::zero();
+        assert!(eq.compute_value(solution.root).unwrap() >= zero);
+        assert!(eq.compute_value(solution.root + Uint128::new(1)).unwrap() < zero);
+    }
+
+    #[test]
+    fn should_compute_xyk_provide() {
+        // Depositing 105 and 50 into a 1000:500 pool mints 10 shares; 5 of the first asset is excess
+        let (assets_used, shares) = compute_xyk_provide(
+            [Uint128::new(105), Uint128::new(50)],
+            [Uint128::new(1000), Uint128::new(500)],
+            Uint128::new(100),
+        )
+        .unwrap();
+        assert_eq!(shares, Uint128::new(10));
+        assert_eq!(assets_used, [Uint128::new(100), Uint128::new(50)]);
+    }
+
+    #[test]
+    fn should_compute_stable_provide() {
+        let pools = [Uint128::new(1000000000000), Uint128::new(1000000000000)];
+        let total_share = Uint128::new(1000000000000);
+
+        // Depositing at the pool's ratio mints shares pro rata
+        let shares = compute_stable_provide(
+            10,
+            [Uint128::new(1000000000), Uint128::new(1000000000)],
+            pools,
+            total_share,
+        )
+        .unwrap();
+        assert_eq!(shares, Uint128::new(1000000000));
+
+        // A one-sided deposit still mints shares, slightly fewer than half as many as above, rather
+        // than none as the XYK pro-rata formula would
+        let shares = compute_stable_provide(
+            10,
+            [Uint128::new(1000000000), Uint128::zero()],
+            pools,
+            total_share,
+        )
+        .unwrap();
+        assert_eq!(shares, Uint128::new(499988642));
+    }
+
+    #[test]
+    fn should_refine_xyk_solution() {
+        let pair = XykPair {
+            offer_user: Uint128::new(100000000000),
+            offer_pool: Uint128::new(118070429547232),
+            ask_user: Uint128::zero(),
+            ask_pool: Uint128::new(1451993415113),
+            total_share: Uint128::new(12966110801826),
+            commission_rate_bps: 30,
+            maker_fee_bps: 0,
+        };
+
+        // The exact root of the quadratic leaves 7 uusd in excess of the pool's ratio
+        let root = mock_equation().solve_exact().unwrap().root;
+        let outcome = pair.compute_outcome(root).unwrap();
+        assert_eq!(outcome.return_amount, Uint128::new(613571013));
+        assert_eq!(outcome.shares, Uint128::new(5481424982));
+
+        // Offering slightly less mints one more share with nothing in excess
+        let refined = pair.refine(root).unwrap();
+        assert_eq!(
+            refined,
+            XykOutcome {
+                offer_amount: Uint128::new(50064546167),
+                return_amount: Uint128::new(613571013),
+                shares: Uint128::new(5481424983),
+                refund_offer: Uint128::zero(),
+                refund_ask: Uint128::zero(),
+            }
+        );
     }
 }
@@ -1,28 +1,359 @@
-use cosmwasm_std::{Empty, Uint128};
+use cosmwasm_std::{Decimal, Uint128};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cw_asset::{AssetUnchecked, AssetListUnchecked};
+use cw20::{Cw20ReceiveMsg, Expiration};
 
-/// We currently don't need any parameter for instantiation and migration
-pub type InstantiateMsg = Empty;
-pub type MigrateMsg = Empty;
+use astroport::factory::PairType;
+use astroport::router::SwapOperation;
+
+use cw_asset::{AssetInfoUnchecked, AssetListUnchecked, AssetUnchecked};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    /// Address of the Astroport factory contract
+    pub factory: String,
+    /// Address of the Astroport router contract
+    pub router: String,
+    /// Address that can update the config
+    pub owner: String,
+    /// Fee charged on the deposits of `Enter`, `EnterByAssets`, `EnterViaRouter`, and
+    /// `EnterMany`, in basis points
+    pub fee_bps: u64,
+    /// Address that receives the fee
+    pub fee_collector: String,
+    /// If provided and not empty, only these pairs can be entered
+    pub allowlist: Option<Vec<String>>,
+    /// Pairs that cannot be entered
+    pub denylist: Option<Vec<String>>,
+    /// Addresses that can execute `Compound` besides the owner, e.g. bots
+    pub keepers: Option<Vec<String>>,
+}
+
+/// The stored config is kept as is during migration. Contracts deployed before some of the config
+/// fields were introduced need those fields to be provided; fields that are already stored are
+/// ignored
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Address of the Astroport factory contract
+    pub factory: Option<String>,
+    /// Address of the Astroport router contract
+    pub router: Option<String>,
+    /// Address that can update the config
+    pub owner: Option<String>,
+    /// Fee charged on the deposits of `Enter`, `EnterByAssets`, `EnterViaRouter`, and
+    /// `EnterMany`, in basis points
+    pub fee_bps: Option<u64>,
+    /// Address that receives the fee
+    pub fee_collector: Option<String>,
+    /// If provided and not empty, only these pairs can be entered. Ignored if the allowlist is
+    /// already stored
+    pub allowlist: Option<Vec<String>>,
+    /// Pairs that cannot be entered. Ignored if the denylist is already stored
+    pub denylist: Option<Vec<String>>,
+    /// Addresses that can execute `Compound` besides the owner. Ignored if the keepers are already
+    /// stored
+    pub keepers: Option<Vec<String>>,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
+    /// Implementation of the CW20 receive interface. See `Cw20HookMsg` for available hooks
+    Receive(Cw20ReceiveMsg),
     /// Provide specified assets to a pair
     ///
     /// NOTE:
     ///
     /// - For CW20 tokens, the sender must have approved allowance. For native coins, the exact
-    /// amount must be sent along with the message
+    /// amount must be sent along with the message. To deposit a single CW20 token without
+    /// approving allowance, send it with the `Enter` hook instead
     ///
     /// - The frontend should calculate `minimum_received` and supply it as an input paramter
+    ///
+    /// - The liquidity tokens and any leftover asset are sent to `recipient` if provided, or to the
+    /// sender otherwise. `recipient` must not be this contract
+    ///
+    /// - If `auto_stake` is true, the liquidity tokens are instead staked in the Astroport generator
+    /// on behalf of the recipient
+    ///
+    /// - If the pool is empty, both assets must be deposited, and `initial_price` must be provided
+    /// as the amount of the pair's second asset per unit of its first asset. The deposits are
+    /// provided directly as the initial liquidity in this ratio, and any excess is refunded. For
+    /// non-empty pools, `initial_price` must not be provided
+    ///
+    /// - If `deadline` is provided and has passed by the time the message is executed, the message
+    /// fails without handling any deposit
+    ///
+    /// - `max_spread` and `belief_price` are passed to the pair for the swap. If only `max_spread` is
+    /// provided, the belief price defaults to the pool's spot price before the swap, which for
+    /// stableswap pools is the marginal price under the StableSwap invariant. If neither is
+    /// provided, Astroport's maximum allowed slippage is used, and `minimum_received` is the only
+    /// protection against slippage
+    ///
+    /// - If `provide_slippage_tolerance` is provided, it is passed to the pair when providing
+    /// liquidity, so that the pair rejects the provision if the pool's ratio has moved too far from
+    /// that of the assets provided. It is not used when providing the initial liquidity of an empty
+    /// pool, as there is no ratio to compare against
+    ///
+    /// - The protocol fee, and the referral fee if `referrer` is provided, are deducted from the
+    /// deposits before computing the swap. The referral fee is capped at 50 bps, separately from
+    /// the protocol fee
+    ///
+    /// - If `deposits` is omitted, the native coins sent along with the message are deposited. CW20
+    /// tokens can only be deposited by specifying them in `deposits`
     Enter {
         pair: String,
+        deposits: Option<AssetListUnchecked>,
+        minimum_received: Option<Uint128>,
+        recipient: Option<String>,
+        auto_stake: Option<bool>,
+        initial_price: Option<Decimal>,
+        deadline: Option<Expiration>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        provide_slippage_tolerance: Option<Decimal>,
+        referrer: Option<Referrer>,
+    },
+    /// Same as `Enter`, but the pair is specified by its assets instead of its address, and is looked
+    /// up from the Astroport factory
+    ///
+    /// NOTE:
+    ///
+    /// - The assets may be specified in any order
+    ///
+    /// - If `pair_type` is provided, the pair found must be of this type
+    EnterByAssets {
+        asset_infos: [AssetInfoUnchecked; 2],
+        pair_type: Option<PairType>,
+        deposits: Option<AssetListUnchecked>,
+        minimum_received: Option<Uint128>,
+        recipient: Option<String>,
+        auto_stake: Option<bool>,
+        initial_price: Option<Decimal>,
+        deadline: Option<Expiration>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        provide_slippage_tolerance: Option<Decimal>,
+        referrer: Option<Referrer>,
+    },
+    /// Swap the deposited asset, which does not need to be one of the pair's assets, through the
+    /// Astroport router along `route`, then provide the asset received to the pair the same way as
+    /// `Enter`
+    ///
+    /// NOTE:
+    ///
+    /// - `route` must start from the deposited asset, and end in one of the pair's assets
+    ///
+    /// - For a CW20 token, the sender must have approved allowance. To deposit a CW20 token without
+    /// approving allowance, send it with the `EnterViaRouter` hook instead
+    ///
+    /// - `minimum_received`, `recipient`, `auto_stake`, and `provide_slippage_tolerance` work the
+    /// same way as in `Enter`. The pool must not be empty
+    ///
+    /// - The protocol fee is deducted from the deposit before swapping it through the router
+    EnterViaRouter {
+        pair: String,
+        deposit: AssetUnchecked,
+        route: Vec<SwapOperation>,
+        minimum_received: Option<Uint128>,
+        recipient: Option<String>,
+        auto_stake: Option<bool>,
+        provide_slippage_tolerance: Option<Decimal>,
+    },
+    /// Split the deposits among multiple pairs according to their weights, and provide each share to
+    /// the respective pair the same way as `Enter`. The pairs are entered one after another, in the
+    /// order they are specified
+    ///
+    /// NOTE:
+    ///
+    /// - Each deposited asset is split among all targets, so each pair must contain all deposited
+    /// assets. Any rounding remainder goes to the last target
+    ///
+    /// - None of the pools may be empty
+    ///
+    /// - Deposits are handled the same way as in `Enter`. `minimum_received` applies to each pair
+    /// individually; `recipient`, `auto_stake`, and `provide_slippage_tolerance` apply to all of
+    /// them
+    ///
+    /// - The protocol fee is deducted from the deposits before splitting them
+    EnterMany {
+        targets: Vec<EnterTarget>,
         deposits: AssetListUnchecked,
+        recipient: Option<String>,
+        auto_stake: Option<bool>,
+        provide_slippage_tolerance: Option<Decimal>,
+    },
+    /// Burn the given amount of liquidity tokens of `from_pair`, and provide the withdrawn assets
+    /// to `to_pair`. See `Cw20HookMsg::MigrateLiquidity`
+    ///
+    /// NOTE: The sender must have approved allowance of the liquidity tokens. To migrate without
+    /// approving allowance, send the liquidity tokens with the `MigrateLiquidity` hook instead
+    MigrateLiquidity {
+        from_pair: String,
+        to_pair: String,
+        amount: Uint128,
+        minimum_received: Option<Uint128>,
+        route: Option<Vec<SwapOperation>>,
+    },
+    /// Burn vault shares of the given liquidity token, and withdraw the liquidity tokens they are
+    /// worth from the Astroport generator to the sender. See `Cw20HookMsg::Stake`
+    ///
+    /// NOTE: The generator pays out the pending rewards whenever liquidity tokens are withdrawn.
+    /// These are kept for the remaining stakers, and compounded by the next `Compound`
+    Unstake { lp_token: String, shares: Uint128 },
+    /// Claim the rewards of the pair's liquidity tokens staked with the `Stake` hook, swap those
+    /// not in the pair through the router, and provide them to the pair the same way as `Enter`.
+    /// The liquidity tokens minted are staked on behalf of this contract, so they accrue to the
+    /// existing vault shares
+    ///
+    /// NOTE:
+    ///
+    /// - Only the owner and the keepers in the config can execute this, as the swaps are exposed to
+    /// sandwich attacks. The sender should calculate `minimum_received` and supply it as an input
+    /// parameter
+    ///
+    /// - Each reward not in the pair must have a route in `routes` that starts from it and ends in
+    /// one of the pair's assets. Routes starting from an asset with no rewards are ignored
+    ///
+    /// - Rewards received when liquidity tokens are staked or unstaked, and any asset left over
+    /// from the last `Compound`, are compounded along with the pending rewards
+    Compound {
+        pair: String,
+        routes: Vec<Vec<SwapOperation>>,
+        minimum_received: Option<Uint128>,
+    },
+    /// Update the config. Only the owner can execute this
+    ///
+    /// NOTE:
+    ///
+    /// - While `paused` is true, no pair can be entered, including via the router, `EnterMany`, and
+    /// `MigrateLiquidity`. Exiting is still allowed
+    ///
+    /// - `allowlist`, `denylist`, and `keepers`, if provided, replace the existing lists. An empty
+    /// allowlist allows all pairs that are not in the denylist
+    UpdateConfig {
+        owner: Option<String>,
+        fee_bps: Option<u64>,
+        fee_collector: Option<String>,
+        paused: Option<bool>,
+        allowlist: Option<Vec<String>>,
+        denylist: Option<Vec<String>>,
+        keepers: Option<Vec<String>>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Referrer {
+    /// Address that receives the referral fee
+    pub address: String,
+    /// Referral fee charged on the deposits, in basis points
+    pub fee_bps: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EnterTarget {
+    /// Address of the pair to enter
+    pub pair: String,
+    /// Share of the deposits to be provided to this pair, relative to the sum of all weights
+    pub weight: u64,
+    /// Minimum amount of liquidity tokens of this pair to receive
+    pub minimum_received: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    /// Provide the CW20 token sent along with this message to a pair
+    ///
+    /// NOTE:
+    ///
+    /// - The CW20 token sent is the only asset deposited. To deposit a CW20 token together with
+    /// native coins, use `ExecuteMsg::Enter` instead
+    ///
+    /// - The frontend should calculate `minimum_received` and supply it as an input paramter
+    ///
+    /// - The liquidity tokens and any leftover asset are sent to `recipient` if provided, or to the
+    /// sender of the CW20 token otherwise
+    ///
+    /// - If `auto_stake` is true, the liquidity tokens are instead staked in the Astroport generator
+    /// on behalf of the recipient
+    ///
+    /// - If `deadline` is provided and has passed by the time the message is executed, the message
+    /// fails and the CW20 token is returned to the sender
+    ///
+    /// - `max_spread` and `belief_price` are passed to the pair for the swap, and
+    /// `provide_slippage_tolerance` when providing liquidity. See `ExecuteMsg::Enter`
+    ///
+    /// - The protocol fee, and the referral fee if `referrer` is provided, are deducted from the
+    /// CW20 token before computing the swap. See `ExecuteMsg::Enter`
+    Enter {
+        pair: String,
+        minimum_received: Option<Uint128>,
+        recipient: Option<String>,
+        auto_stake: Option<bool>,
+        deadline: Option<Expiration>,
+        max_spread: Option<Decimal>,
+        belief_price: Option<Decimal>,
+        provide_slippage_tolerance: Option<Decimal>,
+        referrer: Option<Referrer>,
+    },
+    /// Swap the CW20 token sent along with this message through the Astroport router along `route`,
+    /// then provide the asset received to a pair. See `ExecuteMsg::EnterViaRouter`
+    EnterViaRouter {
+        pair: String,
+        route: Vec<SwapOperation>,
+        minimum_received: Option<Uint128>,
+        recipient: Option<String>,
+        auto_stake: Option<bool>,
+        provide_slippage_tolerance: Option<Decimal>,
+    },
+    /// Burn the liquidity tokens of `from_pair` sent along with this message, and provide the
+    /// withdrawn assets to `to_pair` the same way as `ExecuteMsg::Enter`
+    ///
+    /// NOTE:
+    ///
+    /// - The CW20 token sent must be the liquidity token of `from_pair`
+    ///
+    /// - `to_pair` must not be empty. If it contains only one of the withdrawn assets, the other
+    /// one is first swapped into it in `from_pair`
+    ///
+    /// - If `to_pair` contains neither of the withdrawn assets, `route` must be provided, and must
+    /// start from one of them and end in one of the assets of `to_pair`. The other withdrawn asset
+    /// is first swapped into the one `route` starts from in `from_pair`, which is then swapped
+    /// through the Astroport router along `route`. Otherwise, `route` must not be provided
+    ///
+    /// - The frontend should calculate `minimum_received` and supply it as an input paramter
+    MigrateLiquidity {
+        from_pair: String,
+        to_pair: String,
+        minimum_received: Option<Uint128>,
+        route: Option<Vec<SwapOperation>>,
+    },
+    /// Stake the liquidity tokens sent along with this message in the Astroport generator on behalf
+    /// of this contract, and issue vault shares of them to the sender. The rewards are compounded
+    /// into more liquidity tokens with `ExecuteMsg::Compound`
+    ///
+    /// NOTE:
+    ///
+    /// - The CW20 token sent must be the liquidity token of `pair`, which must be registered with
+    /// the Astroport factory
+    ///
+    /// - `auto_stake` stakes the liquidity tokens on behalf of the recipient instead, in which case
+    /// the generator pays the rewards to the recipient, and they cannot be compounded
+    Stake { pair: String },
+    /// Burn the liquidity tokens sent along with this message, swap the withdrawn asset that is not
+    /// `ask_asset` into `ask_asset`, and return the combined amount to the user
+    ///
+    /// NOTE:
+    ///
+    /// - The CW20 token sent must be the liquidity token of the specified pair
+    ///
+    /// - The frontend should calculate `minimum_received` and supply it as an input paramter
+    Exit {
+        pair: String,
+        ask_asset: AssetInfoUnchecked,
         minimum_received: Option<Uint128>,
     },
 }
@@ -30,12 +361,58 @@ pub enum ExecuteMsg {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
+    /// The contract's config. Returns `ConfigResponse`
+    Config {},
     /// Compute the amount of liquidity tokens that will be minted by executing the `Enter` command
-    /// with the given assets. Returns `SimulateResponse`
+    /// with the given assets, net of the protocol fee and the referral fee if `referral_fee_bps` is
+    /// provided. Returns `SimulateResponse`
     SimulateEnter {
         pair: String,
         deposits: AssetListUnchecked,
+        initial_price: Option<Decimal>,
+        referral_fee_bps: Option<u64>,
+    },
+    /// Same as `SimulateEnter`, but the pair is specified by its assets instead of its address. See
+    /// `ExecuteMsg::EnterByAssets`
+    SimulateEnterByAssets {
+        asset_infos: [AssetInfoUnchecked; 2],
+        pair_type: Option<PairType>,
+        deposits: AssetListUnchecked,
+        initial_price: Option<Decimal>,
+        referral_fee_bps: Option<u64>,
+    },
+    /// Compute the amount of `ask_asset` that will be received by burning the given amount of
+    /// liquidity tokens with the `Exit` hook. Returns `SimulateExitResponse`
+    SimulateExit {
+        pair: String,
+        lp_amount: Uint128,
+        ask_asset: AssetInfoUnchecked,
+    },
+    /// Compute the amount of liquidity tokens of `to_pair` that will be minted by migrating the
+    /// given amount of liquidity tokens of `from_pair` with the `MigrateLiquidity` hook. Returns
+    /// `SimulateMigrateLiquidityResponse`
+    SimulateMigrateLiquidity {
+        from_pair: String,
+        to_pair: String,
+        lp_amount: Uint128,
+        route: Option<Vec<SwapOperation>>,
     },
+    /// The vault shares of a user for a liquidity token staked with the `Stake` hook. Returns
+    /// `StakeInfoResponse`
+    StakeInfo { lp_token: String, user: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub factory: String,
+    pub router: String,
+    pub owner: String,
+    pub fee_bps: u64,
+    pub fee_collector: String,
+    pub paused: bool,
+    pub allowlist: Vec<String>,
+    pub denylist: Vec<String>,
+    pub keepers: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -46,4 +423,63 @@ pub struct SimulateEnterResponse {
     pub return_asset: AssetUnchecked,
     /// The amount of liquidity tokens that will be minted by providing the two assets after the swap
     pub mint_shares: Uint128,
+    /// The assets that will be refunded to the user, i.e. those in excess of the pool's ratio after
+    /// the swap, which would not mint any more liquidity tokens
+    pub refund_assets: AssetListUnchecked,
+    /// Diagnostics of the solver used to compute `offer_asset`. `None` if the pool is empty, in which
+    /// case no swap is made
+    pub solver: Option<SolverInfo>,
+    /// The protocol fee that will be deducted from the deposits
+    pub protocol_fee: AssetListUnchecked,
+    /// The referral fee that will be deducted from the deposits
+    pub referral_fee: AssetListUnchecked,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolverInfo {
+    /// Number of iterations used by the solver; zero for closed-form solutions
+    pub iterations: u32,
+    /// Value of the solved function at the solution. May be negative, so it is encoded as a string
+    pub residual: String,
+    /// Whether the solver converged. Both the closed-form XYK solution and the stableswap bisection
+    /// always do
+    pub converged: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateExitResponse {
+    /// The assets that will be withdrawn from the pool by burning the liquidity tokens
+    pub withdrawn_assets: AssetListUnchecked,
+    /// The withdrawn asset that will be offered for swap into the ask asset
+    pub offer_asset: AssetUnchecked,
+    /// The asset that will be returned as the result of swapping `offer_asset`
+    pub return_asset: AssetUnchecked,
+    /// The total amount of ask asset that will be sent to the user
+    pub receive_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateMigrateLiquidityResponse {
+    /// The assets that will be withdrawn from `from_pair` by burning the liquidity tokens
+    pub withdrawn_assets: AssetListUnchecked,
+    /// If `to_pair` contains only one of the withdrawn assets, the other one, which will be offered
+    /// for swap in `from_pair`. If `route` is provided, the withdrawn asset `route` does not start
+    /// from
+    pub offer_asset: Option<AssetUnchecked>,
+    /// The asset that will be returned as the result of swapping `offer_asset`
+    pub return_asset: Option<AssetUnchecked>,
+    /// If `route` is provided, the asset that will be returned by the router
+    pub routed_asset: Option<AssetUnchecked>,
+    /// Simulation of entering `to_pair` with the assets after the swap
+    pub enter: SimulateEnterResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakeInfoResponse {
+    /// Vault shares held by the user
+    pub shares: Uint128,
+    /// Total vault shares issued for the liquidity token
+    pub total_shares: Uint128,
+    /// Amount of liquidity tokens the user's shares are currently worth
+    pub lp_amount: Uint128,
 }
@@ -1,18 +1,123 @@
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use cw_storage_plus::{Item, Map, U64Key};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cw_asset::AssetList;
+use astroport::router::SwapOperation;
+
+use cw_asset::{Asset, AssetInfo, AssetList};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    /// Address of the Astroport factory contract, which we query for the pairs' commission rates
+    pub factory: Addr,
+    /// Address of the Astroport router contract, which we use to swap assets not in the pair
+    pub router: Addr,
+    /// Address that can update the config
+    pub owner: Addr,
+    /// Fee charged on the deposits of `Enter`, `EnterByAssets`, `EnterViaRouter`, and
+    /// `EnterMany`, in basis points. `MigrateLiquidity` and `Compound` are not charged
+    pub fee_bps: u64,
+    /// Address that receives the fee
+    pub fee_collector: Addr,
+    /// If true, entering any pair is disabled. Exiting is still allowed
+    pub paused: bool,
+    /// If not empty, only these pairs can be entered
+    pub allowlist: Vec<Addr>,
+    /// Pairs that cannot be entered
+    pub denylist: Vec<Addr>,
+    /// Addresses that can execute `Compound` besides the owner, e.g. bots
+    pub keepers: Vec<Addr>,
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+/// The config as stored by any earlier version of the contract, which may lack the fields added
+/// since. Only used during migration
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct StoredConfig {
+    pub factory: Option<Addr>,
+    pub router: Option<Addr>,
+    pub owner: Option<Addr>,
+    pub fee_bps: Option<u64>,
+    pub fee_collector: Option<Addr>,
+    pub paused: Option<bool>,
+    pub allowlist: Option<Vec<Addr>>,
+    pub denylist: Option<Vec<Addr>>,
+    pub keepers: Option<Vec<Addr>>,
+}
+
+pub const STORED_CONFIG: Item<StoredConfig> = Item::new("config");
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct CacheData {
     pub user_addr: Addr,
+    pub recipient_addr: Addr,
     pub pair_addr: Addr,
     pub liquidity_token_addr: Addr,
     pub assets: AssetList,
     pub minimum_received: Option<Uint128>,
+    pub auto_stake: bool,
+    /// Slippage tolerance passed to the pair when providing liquidity after the swap
+    pub provide_slippage_tolerance: Option<Decimal>,
+    /// When entering via the router, the asset the deposit is swapped into, along with the contract's
+    /// balance of it before the swap. The amount received from the router is the increase in balance
+    pub intermediate_asset: Option<Asset>,
+    /// When migrating liquidity, the pair the liquidity is withdrawn from
+    pub source_pair_addr: Option<Addr>,
+    /// Routes to swap the cached assets they start from through the router along, one after
+    /// another, before entering the pair. Each route is removed once its swap is made. Used when
+    /// migrating liquidity between pairs that share no asset, and when compounding rewards not in
+    /// the pair
+    pub routes: Vec<Vec<SwapOperation>>,
+    /// Pairs to be entered once the current one is done, in order. Only used by `EnterMany`
+    pub pending: Vec<PendingEnter>,
+    /// Whether the zap is a `Compound`, in which case the liquidity tokens are staked on behalf of
+    /// this contract, and the leftover assets are kept to be compounded by the next `Compound`
+    pub compounding: bool,
 }
 
-pub const CACHE: Item<CacheData> = Item::new("cache");
+/// A pair to be entered as part of `EnterMany`, with its share of the deposits
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingEnter {
+    pub pair_addr: Addr,
+    pub liquidity_token_addr: Addr,
+    pub assets: AssetList,
+    pub minimum_received: Option<Uint128>,
+}
+
+/// Id of the latest zap. Each zap caches its data under its own id, in `CACHE` or, for `Exit`, in
+/// `EXIT_CACHE`, and the cache is cleared once the zap is done, so a zap is in progress if and only
+/// if there is a cache under the latest id
+///
+/// NOTE: Replies only carry the id of the step being handled, not the zap id, so reply handlers
+/// always read the cache under the latest id. The id is therefore a guard against starting a zap
+/// while another is in progress, not a way to run several zaps at once
+pub const ZAP_ID: Item<u64> = Item::new("zap_id");
+
+pub const CACHE: Map<U64Key, CacheData> = Map::new("cache");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExitCacheData {
+    pub user_addr: Addr,
+    pub pair_addr: Addr,
+    pub ask_asset_info: AssetInfo,
+    pub assets: AssetList,
+    pub minimum_received: Option<Uint128>,
+}
+
+pub const EXIT_CACHE: Map<U64Key, ExitCacheData> = Map::new("exit_cache");
+
+/// Total vault shares issued for each liquidity token staked in the generator through `Stake`. The
+/// generator pays the rewards of these liquidity tokens to this contract, which compounds them, so
+/// each share accrues more liquidity tokens over time
+pub const TOTAL_SHARES: Map<&Addr, Uint128> = Map::new("total_shares");
+
+/// Vault shares held by each user, keyed by liquidity token and user
+pub const SHARES: Map<(&Addr, &Addr), Uint128> = Map::new("shares");
+
+/// Rewards received for each staked liquidity token outside of `Compound` (the generator pays out
+/// pending rewards whenever liquidity tokens are deposited or withdrawn), along with any asset left
+/// over from the last `Compound`. They are compounded by the next `Compound`
+pub const REWARDS: Map<&Addr, AssetList> = Map::new("rewards");
@@ -11,6 +11,14 @@ impl Api for CustomMockApi {
     /// others invalid
     fn addr_validate(&self, human: &str) -> StdResult<Addr> {
         let valid_addresses = vec![
+            "cosmos2contract",
+            "alice",
+            "bob",
+            "deployer",
+            "fee_collector",
+            "referrer",
+            "astroport_factory",
+            "astroport_router",
             "astro_token",
             "bluna_token",
             "luna_ust_pair",
@@ -19,11 +27,17 @@ impl Api for CustomMockApi {
             "astro_ust_lp_token",
             "bluna_luna_pair",
             "bluna_luna_lp_token",
+            "astro_luna_pair",
+            "astro_luna_lp_token",
+            "fake_pair",
         ];
         if valid_addresses.contains(&human) {
             self.0.addr_validate(human)
         } else {
-            Err(StdError::generic_err(format!("[mock]: invalid address: {}", human)))
+            Err(StdError::generic_err(format!(
+                "[mock]: invalid address: {}",
+                human
+            )))
         }
     }
 
@@ -1,27 +1,50 @@
 use cosmwasm_std::testing::MockQuerier;
 use cosmwasm_std::{
-    from_binary, from_slice, Addr, Empty, Querier, QuerierResult, QueryRequest, StdResult,
+    from_binary, from_slice, Addr, Coin, Empty, Querier, QuerierResult, QueryRequest, StdResult,
     SystemError, WasmQuery,
 };
+use cw20::Cw20QueryMsg;
 
 use astroport::asset::PairInfo;
+use astroport::factory::PairType;
 use astroport::pair::PoolResponse;
 
+use super::cw20_querier::Cw20Querier;
+use super::factory_querier::FactoryQuerier;
+use super::generator_querier::GeneratorQuerier;
 use super::pair_querier::PairQuerier;
+use super::router_querier::RouterQuerier;
+
+/// Address of the mock Astroport factory contract
+pub const FACTORY_ADDR: &str = "astroport_factory";
+
+/// Address of the mock Astroport generator contract
+pub const GENERATOR_ADDR: &str = "astroport_generator";
+
+/// Address of the mock Astroport router contract
+pub const ROUTER_ADDR: &str = "astroport_router";
 
 // We do not have any custom query
 type CustomQuery = Empty;
 
 pub struct CustomMockQuerier {
     base: MockQuerier<CustomQuery>,
+    cw20_querier: Cw20Querier,
+    factory_querier: FactoryQuerier,
+    generator_querier: GeneratorQuerier,
     pair_querier: PairQuerier,
+    router_querier: RouterQuerier,
 }
 
 impl Default for CustomMockQuerier {
     fn default() -> Self {
         Self {
             base: MockQuerier::<CustomQuery>::new(&[]),
+            cw20_querier: Cw20Querier::default(),
+            factory_querier: FactoryQuerier::default(),
+            generator_querier: GeneratorQuerier::default(),
             pair_querier: PairQuerier::default(),
+            router_querier: RouterQuerier::default(),
         }
     }
 }
@@ -48,9 +71,49 @@ impl CustomMockQuerier {
             QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
                 let contract_addr = Addr::unchecked(contract_addr);
 
+                // Factory, generator, router, and pair contracts have overlapping query messages
+                // (e.g. `config`), so for all but the pairs we dispatch by address rather than by
+                // message
+                if contract_addr == FACTORY_ADDR {
+                    let parse_factory_query: StdResult<astroport::factory::QueryMsg> =
+                        from_binary(msg);
+                    if let Ok(factory_query) = parse_factory_query {
+                        return self.factory_querier.handle_query(factory_query);
+                    }
+                }
+
+                if contract_addr == GENERATOR_ADDR {
+                    let parse_generator_query: StdResult<astroport::generator::QueryMsg> =
+                        from_binary(msg);
+                    if let Ok(generator_query) = parse_generator_query {
+                        return self.generator_querier.handle_query(generator_query);
+                    }
+                }
+
+                if contract_addr == ROUTER_ADDR {
+                    let parse_router_query: StdResult<astroport::router::QueryMsg> =
+                        from_binary(msg);
+                    if let Ok(router_query) = parse_router_query {
+                        return self.router_querier.handle_query(
+                            router_query,
+                            &self.factory_querier,
+                            &self.pair_querier,
+                        );
+                    }
+                }
+
                 let parse_pair_query: StdResult<astroport::pair::QueryMsg> = from_binary(msg);
                 if let Ok(pair_query) = parse_pair_query {
-                    return self.pair_querier.handle_query(&contract_addr, pair_query);
+                    return self.pair_querier.handle_query(
+                        &contract_addr,
+                        pair_query,
+                        &self.factory_querier,
+                    );
+                }
+
+                let parse_cw20_query: StdResult<Cw20QueryMsg> = from_binary(msg);
+                if let Ok(cw20_query) = parse_cw20_query {
+                    return self.cw20_querier.handle_query(&contract_addr, cw20_query);
                 }
 
                 panic!("[mock]: failed to parse wasm query {:?}", msg)
@@ -60,11 +123,61 @@ impl CustomMockQuerier {
         }
     }
 
+    pub fn set_native_balance(&mut self, address: &str, balance: &[Coin]) {
+        self.base.update_balance(address, balance.to_vec());
+    }
+
+    pub fn set_cw20_balance(&mut self, token: &str, user: &str, balance: u128) {
+        self.cw20_querier.set_balance(token, user, balance);
+    }
+
+    pub fn set_generator_deposit(&mut self, lp_token: &str, user: &str, amount: u128) {
+        self.generator_querier.set_deposit(lp_token, user, amount);
+    }
+
+    pub fn set_pending_rewards(
+        &mut self,
+        lp_token: &str,
+        user: &str,
+        pending: u128,
+        pending_on_proxy: Option<u128>,
+    ) {
+        self.generator_querier
+            .set_pending_rewards(lp_token, user, pending, pending_on_proxy);
+    }
+
+    pub fn set_reward_info(
+        &mut self,
+        lp_token: &str,
+        base_reward_token: &str,
+        proxy_reward_token: Option<&str>,
+    ) {
+        self.generator_querier
+            .set_reward_info(lp_token, base_reward_token, proxy_reward_token);
+    }
+
+    pub fn set_fee_info(&mut self, pair_type: PairType, total_fee_bps: u16, maker_fee_bps: u16) {
+        self.factory_querier
+            .set_fee_info(pair_type, total_fee_bps, maker_fee_bps);
+    }
+
+    /// Set the pair's info, and register the pair with the factory
     pub fn set_pair(&mut self, contract: &str, pair_info: PairInfo) {
+        self.factory_querier.set_pair(pair_info.clone());
+        self.pair_querier.set_pair(contract, pair_info);
+    }
+
+    /// Set the pair's info without registering it with the factory, as if it is a contract posing
+    /// as an Astroport pair
+    pub fn set_unregistered_pair(&mut self, contract: &str, pair_info: PairInfo) {
         self.pair_querier.set_pair(contract, pair_info);
     }
 
     pub fn set_pool(&mut self, contract: &str, pool_info: PoolResponse) {
         self.pair_querier.set_pool(contract, pool_info);
     }
+
+    pub fn set_amp(&mut self, contract: &str, amp: u64) {
+        self.pair_querier.set_amp(contract, amp);
+    }
 }
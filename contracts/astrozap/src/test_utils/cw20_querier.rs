@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{to_binary, Addr, QuerierResult, SystemError, Uint128};
+use cw20::{BalanceResponse, Cw20QueryMsg};
+
+#[derive(Default)]
+pub struct Cw20Querier {
+    /// Mapping token address => user address => balance
+    balances: HashMap<Addr, HashMap<Addr, Uint128>>,
+}
+
+impl Cw20Querier {
+    pub fn handle_query(&self, contract_addr: &Addr, query: Cw20QueryMsg) -> QuerierResult {
+        match query {
+            Cw20QueryMsg::Balance { address } => self.query_balance(contract_addr, address),
+
+            q => Err(SystemError::UnsupportedRequest {
+                kind: format!("[mock]: {:?}", q),
+            })
+            .into(),
+        }
+    }
+
+    fn query_balance(&self, contract_addr: &Addr, address: String) -> QuerierResult {
+        let balance = self
+            .balances
+            .get(contract_addr)
+            .and_then(|balances| balances.get(&Addr::unchecked(&address)))
+            .cloned()
+            .unwrap_or_else(Uint128::zero);
+
+        Ok(to_binary(&BalanceResponse { balance }).into()).into()
+    }
+
+    pub fn set_balance(&mut self, token: &str, user: &str, balance: u128) {
+        self.balances
+            .entry(Addr::unchecked(token))
+            .or_default()
+            .insert(Addr::unchecked(user), Uint128::new(balance));
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{to_binary, Addr, QuerierResult, SystemError};
+
+use astroport::asset::{AssetInfo as LegacyAssetInfo, PairInfo};
+use astroport::factory::{ConfigResponse, FeeInfoResponse, PairType, QueryMsg};
+
+use super::custom_mock_querier::{FACTORY_ADDR, GENERATOR_ADDR};
+
+#[derive(Default)]
+pub struct FactoryQuerier {
+    fee_infos: HashMap<String, FeeInfoResponse>,
+    pairs: HashMap<String, PairInfo>,
+}
+
+/// Same as the actual factory contract, pairs are keyed by their assets regardless of the order
+fn pair_key(asset_infos: &[LegacyAssetInfo; 2]) -> String {
+    let mut keys = asset_infos
+        .iter()
+        .map(|info| info.to_string())
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.join(":")
+}
+
+impl FactoryQuerier {
+    pub fn handle_query(&self, query: QueryMsg) -> QuerierResult {
+        match query {
+            QueryMsg::Config {} => self.query_config(),
+            QueryMsg::FeeInfo { pair_type } => self.query_fee_info(pair_type),
+            QueryMsg::Pair { asset_infos } => self.query_pair(asset_infos),
+
+            q => Err(SystemError::UnsupportedRequest {
+                kind: format!("[mock]: {:?}", q),
+            })
+            .into(),
+        }
+    }
+
+    fn query_config(&self) -> QuerierResult {
+        Ok(to_binary(&ConfigResponse {
+            owner: Addr::unchecked(FACTORY_ADDR),
+            pair_configs: vec![],
+            token_code_id: 0,
+            fee_address: None,
+            generator_address: Some(Addr::unchecked(GENERATOR_ADDR)),
+        })
+        .into())
+        .into()
+    }
+
+    fn query_fee_info(&self, pair_type: PairType) -> QuerierResult {
+        let fee_info = match self.fee_infos.get(&pair_type.to_string()) {
+            Some(fee_info) => fee_info,
+            None => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!("[mock]: fee info not set for pair type {}", pair_type),
+                    request: Default::default(),
+                })
+                .into();
+            }
+        };
+
+        Ok(to_binary(&fee_info).into()).into()
+    }
+
+    fn query_pair(&self, asset_infos: [LegacyAssetInfo; 2]) -> QuerierResult {
+        let pair_info = match self.pairs.get(&pair_key(&asset_infos)) {
+            Some(pair_info) => pair_info,
+            None => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!(
+                        "[mock]: pair not set for assets {} and {}",
+                        asset_infos[0], asset_infos[1]
+                    ),
+                    request: Default::default(),
+                })
+                .into();
+            }
+        };
+
+        Ok(to_binary(&pair_info).into()).into()
+    }
+
+    pub fn pair(&self, asset_infos: &[LegacyAssetInfo; 2]) -> Option<&PairInfo> {
+        self.pairs.get(&pair_key(asset_infos))
+    }
+
+    pub fn total_fee_bps(&self, pair_type: &PairType) -> Option<u16> {
+        self.fee_infos
+            .get(&pair_type.to_string())
+            .map(|fee_info| fee_info.total_fee_bps)
+    }
+
+    pub fn set_fee_info(&mut self, pair_type: PairType, total_fee_bps: u16, maker_fee_bps: u16) {
+        self.fee_infos.insert(
+            pair_type.to_string(),
+            FeeInfoResponse {
+                fee_address: None,
+                total_fee_bps,
+                maker_fee_bps,
+            },
+        );
+    }
+
+    pub fn set_pair(&mut self, pair_info: PairInfo) {
+        self.pairs
+            .insert(pair_key(&pair_info.asset_infos), pair_info);
+    }
+}
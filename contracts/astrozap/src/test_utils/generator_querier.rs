@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{to_binary, Addr, QuerierResult, SystemError, Uint128};
+
+use astroport::generator::{PendingTokenResponse, QueryMsg, RewardInfoResponse};
+
+#[derive(Default)]
+pub struct GeneratorQuerier {
+    /// Mapping (lp_token, user) => staked amount
+    deposits: HashMap<(String, String), Uint128>,
+    /// Mapping (lp_token, user) => pending rewards
+    pending_rewards: HashMap<(String, String), PendingTokenResponse>,
+    /// Mapping lp_token => reward info
+    reward_infos: HashMap<String, RewardInfoResponse>,
+}
+
+impl GeneratorQuerier {
+    pub fn handle_query(&self, query: QueryMsg) -> QuerierResult {
+        match query {
+            QueryMsg::Deposit { lp_token, user } => self.query_deposit(lp_token, user),
+            QueryMsg::PendingToken { lp_token, user } => self.query_pending_token(lp_token, user),
+            QueryMsg::RewardInfo { lp_token } => self.query_reward_info(lp_token),
+
+            q => Err(SystemError::UnsupportedRequest {
+                kind: format!("[mock]: {:?}", q),
+            })
+            .into(),
+        }
+    }
+
+    fn query_deposit(&self, lp_token: Addr, user: Addr) -> QuerierResult {
+        let deposit = self
+            .deposits
+            .get(&(lp_token.into(), user.into()))
+            .cloned()
+            .unwrap_or_else(Uint128::zero);
+
+        Ok(to_binary(&deposit).into()).into()
+    }
+
+    fn query_pending_token(&self, lp_token: Addr, user: Addr) -> QuerierResult {
+        let pending = self
+            .pending_rewards
+            .get(&(lp_token.into(), user.into()))
+            .cloned()
+            .unwrap_or(PendingTokenResponse {
+                pending: Uint128::zero(),
+                pending_on_proxy: None,
+            });
+
+        Ok(to_binary(&pending).into()).into()
+    }
+
+    fn query_reward_info(&self, lp_token: Addr) -> QuerierResult {
+        let reward_info = match self.reward_infos.get(&lp_token.to_string()) {
+            Some(reward_info) => reward_info,
+            None => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!("[mock]: reward info not set for lp token {}", lp_token),
+                    request: Default::default(),
+                })
+                .into();
+            }
+        };
+
+        Ok(to_binary(&reward_info).into()).into()
+    }
+
+    pub fn set_deposit(&mut self, lp_token: &str, user: &str, amount: u128) {
+        self.deposits
+            .insert((lp_token.to_string(), user.to_string()), amount.into());
+    }
+
+    pub fn set_pending_rewards(
+        &mut self,
+        lp_token: &str,
+        user: &str,
+        pending: u128,
+        pending_on_proxy: Option<u128>,
+    ) {
+        self.pending_rewards.insert(
+            (lp_token.to_string(), user.to_string()),
+            PendingTokenResponse {
+                pending: pending.into(),
+                pending_on_proxy: pending_on_proxy.map(Uint128::from),
+            },
+        );
+    }
+
+    pub fn set_reward_info(
+        &mut self,
+        lp_token: &str,
+        base_reward_token: &str,
+        proxy_reward_token: Option<&str>,
+    ) {
+        self.reward_infos.insert(
+            lp_token.to_string(),
+            RewardInfoResponse {
+                base_reward_token: Addr::unchecked(base_reward_token),
+                proxy_reward_token: proxy_reward_token.map(Addr::unchecked),
+            },
+        );
+    }
+}
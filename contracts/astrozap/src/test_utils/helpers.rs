@@ -1,8 +1,8 @@
 use cosmwasm_std::testing::MockStorage;
 use cosmwasm_std::OwnedDeps;
 
-use super::custom_mock_querier::CustomMockQuerier;
 use super::custom_mock_api::CustomMockApi;
+use super::custom_mock_querier::CustomMockQuerier;
 
 pub fn mock_dependencies() -> OwnedDeps<MockStorage, CustomMockApi, CustomMockQuerier> {
     OwnedDeps {
@@ -10,4 +10,4 @@ pub fn mock_dependencies() -> OwnedDeps<MockStorage, CustomMockApi, CustomMockQu
         api: CustomMockApi::default(),
         querier: CustomMockQuerier::default(),
     }
-}
\ No newline at end of file
+}
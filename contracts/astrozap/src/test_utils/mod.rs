@@ -1,8 +1,14 @@
 mod custom_mock_api;
 mod custom_mock_querier;
+mod cw20_querier;
+mod factory_querier;
+mod generator_querier;
 mod helpers;
 mod pair_querier;
+mod router_querier;
+mod stable_math;
 
 pub use custom_mock_api::CustomMockApi;
-pub use custom_mock_querier::CustomMockQuerier;
+pub use custom_mock_querier::{CustomMockQuerier, FACTORY_ADDR, GENERATOR_ADDR, ROUTER_ADDR};
 pub use helpers::mock_dependencies;
+pub use stable_math::compute_stable_provide;
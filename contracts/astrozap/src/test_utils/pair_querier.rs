@@ -3,23 +3,43 @@ use std::collections::HashMap;
 use cosmwasm_std::{to_binary, Addr, Decimal, QuerierResult, SystemError};
 
 use astroport::asset::{Asset as LegacyAsset, PairInfo};
-use astroport::pair::{PoolResponse, QueryMsg, SimulationResponse};
+use astroport::factory::PairType;
+use astroport::pair::{
+    ConfigResponse, PoolResponse, QueryMsg, SimulationResponse, StablePoolConfig,
+};
 use astroport_pair::contract::compute_swap;
 
+use super::factory_querier::FactoryQuerier;
+use super::stable_math::compute_stable_swap;
+
 #[derive(Default)]
 pub struct PairQuerier {
     pair_infos: HashMap<Addr, PairInfo>,
     pool_infos: HashMap<Addr, PoolResponse>,
+    amps: HashMap<Addr, u64>,
 }
 
 impl PairQuerier {
-    pub fn handle_query(&self, contract_addr: &Addr, query: QueryMsg) -> QuerierResult {
+    /// The factory querier is needed because, same as the actual pair contract, we look up the
+    /// commission rate from the factory when simulating swaps
+    pub fn handle_query(
+        &self,
+        contract_addr: &Addr,
+        query: QueryMsg,
+        factory_querier: &FactoryQuerier,
+    ) -> QuerierResult {
         match query {
             QueryMsg::Pair {} => self.query_pair(contract_addr),
             QueryMsg::Pool {} => self.query_pool(contract_addr),
-            QueryMsg::Simulation { offer_asset } => self.query_simulation(contract_addr, offer_asset),
+            QueryMsg::Config {} => self.query_config(contract_addr),
+            QueryMsg::Simulation { offer_asset } => {
+                self.query_simulation(contract_addr, offer_asset, factory_querier)
+            }
 
-            q => Err(SystemError::UnsupportedRequest { kind: format!("[mock]: {:?}", q) }).into(),
+            q => Err(SystemError::UnsupportedRequest {
+                kind: format!("[mock]: {:?}", q),
+            })
+            .into(),
         }
     }
 
@@ -28,7 +48,10 @@ impl PairQuerier {
             Some(pair_info) => pair_info,
             None => {
                 return Err(SystemError::InvalidRequest {
-                    error: format!("[mock]: pair info not set for pair {}", contract_addr.to_string()),
+                    error: format!(
+                        "[mock]: pair info not set for pair {}",
+                        contract_addr.to_string()
+                    ),
                     request: Default::default(),
                 })
                 .into();
@@ -43,7 +66,10 @@ impl PairQuerier {
             Some(pool_info) => pool_info,
             None => {
                 return Err(SystemError::InvalidRequest {
-                    error: format!("[mock]: pool info not set for pair {}", contract_addr.to_string()),
+                    error: format!(
+                        "[mock]: pool info not set for pair {}",
+                        contract_addr.to_string()
+                    ),
                     request: Default::default(),
                 })
                 .into();
@@ -53,12 +79,61 @@ impl PairQuerier {
         Ok(to_binary(&pool_info).into()).into()
     }
 
-    fn query_simulation(&self, contract_addr: &Addr, offer_asset: LegacyAsset) -> QuerierResult {
+    fn query_config(&self, contract_addr: &Addr) -> QuerierResult {
+        // Only stableswap pairs have params; for other pairs we return `None`
+        let params = self.amps.get(contract_addr).map(|amp| {
+            to_binary(&StablePoolConfig {
+                amp: Decimal::from_ratio(*amp, 1u64),
+            })
+            .unwrap()
+        });
+
+        Ok(to_binary(&ConfigResponse {
+            block_time_last: 0,
+            params,
+        })
+        .into())
+        .into()
+    }
+
+    fn query_simulation(
+        &self,
+        contract_addr: &Addr,
+        offer_asset: LegacyAsset,
+        factory_querier: &FactoryQuerier,
+    ) -> QuerierResult {
+        let pair_info = match self.pair_infos.get(contract_addr) {
+            Some(pair_info) => pair_info,
+            None => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!("[mock]: pair info not set for pair {}", contract_addr),
+                    request: Default::default(),
+                })
+                .into();
+            }
+        };
+        let total_fee_bps = match factory_querier.total_fee_bps(&pair_info.pair_type) {
+            Some(total_fee_bps) => total_fee_bps,
+            None => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!(
+                        "[mock]: fee info not set for pair type {}",
+                        pair_info.pair_type
+                    ),
+                    request: Default::default(),
+                })
+                .into();
+            }
+        };
+
         let pool_info = match self.pool_infos.get(contract_addr) {
             Some(pool_info) => pool_info,
             None => {
                 return Err(SystemError::InvalidRequest {
-                    error: format!("[mock]: pool info not set for pair {}", contract_addr.to_string()),
+                    error: format!(
+                        "[mock]: pool info not set for pair {}",
+                        contract_addr.to_string()
+                    ),
                     request: Default::default(),
                 })
                 .into();
@@ -84,13 +159,36 @@ impl PairQuerier {
             .into();
         }
 
-        let total_fee_rate = Decimal::from_ratio(30u128, 10000u128); // 0.3%
-        match compute_swap(
-            offer_pool.amount,
-            ask_pool.amount,
-            offer_asset.amount,
-            total_fee_rate,
-        ) {
+        let total_fee_rate = Decimal::from_ratio(total_fee_bps, 10000u16);
+        let result = match pair_info.pair_type {
+            PairType::Stable {} => {
+                let amp = match self.amps.get(contract_addr) {
+                    Some(amp) => *amp,
+                    None => {
+                        return Err(SystemError::InvalidRequest {
+                            error: format!("[mock]: amp not set for pair {}", contract_addr),
+                            request: Default::default(),
+                        })
+                        .into();
+                    }
+                };
+                compute_stable_swap(
+                    offer_pool.amount,
+                    ask_pool.amount,
+                    offer_asset.amount,
+                    total_fee_rate,
+                    amp,
+                )
+            }
+            _ => compute_swap(
+                offer_pool.amount,
+                ask_pool.amount,
+                offer_asset.amount,
+                total_fee_rate,
+            ),
+        };
+
+        match result {
             Ok((return_amount, spread_amount, commission_amount)) => {
                 Ok(to_binary(&SimulationResponse {
                     return_amount,
@@ -115,4 +213,8 @@ impl PairQuerier {
     pub fn set_pool(&mut self, contract: &str, pool_info: PoolResponse) {
         self.pool_infos.insert(Addr::unchecked(contract), pool_info);
     }
+
+    pub fn set_amp(&mut self, contract: &str, amp: u64) {
+        self.amps.insert(Addr::unchecked(contract), amp);
+    }
 }
@@ -0,0 +1,97 @@
+use cosmwasm_std::{
+    from_binary, to_binary, ContractResult, QuerierResult, SystemError, SystemResult, Uint128,
+};
+
+use astroport::asset::Asset as LegacyAsset;
+use astroport::pair::SimulationResponse;
+use astroport::router::{QueryMsg, SimulateSwapOperationsResponse, SwapOperation};
+
+use super::factory_querier::FactoryQuerier;
+use super::pair_querier::PairQuerier;
+
+#[derive(Default)]
+pub struct RouterQuerier {}
+
+impl RouterQuerier {
+    /// Same as the actual router contract, each swap operation is simulated against the pair
+    /// registered with the factory for its assets
+    pub fn handle_query(
+        &self,
+        query: QueryMsg,
+        factory_querier: &FactoryQuerier,
+        pair_querier: &PairQuerier,
+    ) -> QuerierResult {
+        match query {
+            QueryMsg::SimulateSwapOperations {
+                offer_amount,
+                operations,
+            } => self.query_simulate_swap_operations(
+                offer_amount,
+                operations,
+                factory_querier,
+                pair_querier,
+            ),
+
+            q => Err(SystemError::UnsupportedRequest {
+                kind: format!("[mock]: {:?}", q),
+            })
+            .into(),
+        }
+    }
+
+    fn query_simulate_swap_operations(
+        &self,
+        offer_amount: Uint128,
+        operations: Vec<SwapOperation>,
+        factory_querier: &FactoryQuerier,
+        pair_querier: &PairQuerier,
+    ) -> QuerierResult {
+        let mut amount = offer_amount;
+        for operation in operations {
+            let (offer_asset_info, ask_asset_info) = match operation {
+                SwapOperation::AstroSwap {
+                    offer_asset_info,
+                    ask_asset_info,
+                } => (offer_asset_info, ask_asset_info),
+                op => {
+                    return Err(SystemError::UnsupportedRequest {
+                        kind: format!("[mock]: {:?}", op),
+                    })
+                    .into()
+                }
+            };
+
+            let pair_info = match factory_querier.pair(&[offer_asset_info.clone(), ask_asset_info])
+            {
+                Some(pair_info) => pair_info,
+                None => {
+                    return Err(SystemError::InvalidRequest {
+                        error: "[mock]: pair not set for swap operation".to_string(),
+                        request: Default::default(),
+                    })
+                    .into();
+                }
+            };
+
+            let res = pair_querier.handle_query(
+                &pair_info.contract_addr,
+                astroport::pair::QueryMsg::Simulation {
+                    offer_asset: LegacyAsset {
+                        info: offer_asset_info,
+                        amount,
+                    },
+                },
+                factory_querier,
+            );
+            amount = match res {
+                SystemResult::Ok(ContractResult::Ok(bin)) => {
+                    let simulation: SimulationResponse = from_binary(&bin).unwrap();
+                    simulation.return_amount
+                }
+                err => return err,
+            };
+        }
+
+        Ok(to_binary(&SimulateSwapOperationsResponse { amount }).into()).into()
+    }
+}
@@ -0,0 +1,119 @@
+use std::convert::TryInto;
+
+use cosmwasm_std::{Decimal, StdError, StdResult, Uint128, Uint256};
+
+// Code below is adapted from:
+// https://github.com/astroport-fi/astroport-core/blob/v1.0.0/contracts/pair_stable/src/math.rs
+//
+// The original uses the `uint` crate's `U256` type; we use `cosmwasm_std::Uint256` instead, which
+// behaves identically for our purpose
+
+const N_COINS: u8 = 2;
+const ITERATIONS: u8 = 32;
+
+/// Simulate a swap in a stableswap pool. Returns `(return_amount, spread_amount, commission_amount)`
+pub fn compute_stable_swap(
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    offer_amount: Uint128,
+    commission_rate: Decimal,
+    amp: u64,
+) -> StdResult<(Uint128, Uint128, Uint128)> {
+    let leverage = amp * u64::from(N_COINS);
+    let d = compute_d(leverage, offer_pool.u128(), ask_pool.u128())?;
+    let new_ask_pool = calc_y(leverage, offer_pool.u128() + offer_amount.u128(), d)?;
+
+    let return_amount = ask_pool.checked_sub(Uint128::new(new_ask_pool))?;
+    let spread_amount = offer_amount.saturating_sub(return_amount);
+    let commission_amount = return_amount * commission_rate;
+    let return_amount = return_amount.checked_sub(commission_amount)?;
+
+    Ok((return_amount, spread_amount, commission_amount))
+}
+
+/// Simulate providing liquidity to a non-empty stableswap pool. Returns the amount of shares minted
+pub fn compute_stable_provide(
+    pools: [Uint128; 2],
+    deposits: [Uint128; 2],
+    total_share: Uint128,
+    amp: u64,
+) -> StdResult<Uint128> {
+    let leverage = amp * u64::from(N_COINS);
+    let init_d = compute_d(leverage, pools[0].u128(), pools[1].u128())?;
+    let deposit_d = compute_d(
+        leverage,
+        pools[0].u128() + deposits[0].u128(),
+        pools[1].u128() + deposits[1].u128(),
+    )?;
+
+    Ok(total_share.multiply_ratio(deposit_d - init_d, init_d))
+}
+
+fn compute_d(leverage: u64, amount_a: u128, amount_b: u128) -> StdResult<u128> {
+    let n_coins = Uint256::from(N_COINS);
+    let amount_a_times_coins = Uint256::from(amount_a) * n_coins;
+    let amount_b_times_coins = Uint256::from(amount_b) * n_coins;
+    let sum_x = Uint256::from(amount_a) + Uint256::from(amount_b);
+    if sum_x.is_zero() {
+        return Ok(0);
+    }
+
+    let mut d = sum_x;
+    for _ in 0..ITERATIONS {
+        let d_product = d * d / amount_a_times_coins * d / amount_b_times_coins;
+        let d_previous = d;
+        d = calculate_step(d, leverage, sum_x, d_product);
+        if d == d_previous {
+            break;
+        }
+    }
+
+    to_u128(d)
+}
+
+fn calculate_step(
+    initial_d: Uint256,
+    leverage: u64,
+    sum_x: Uint256,
+    d_product: Uint256,
+) -> Uint256 {
+    let n_coins = Uint256::from(N_COINS);
+    let leverage_mul = Uint256::from(leverage) * sum_x;
+    let d_p_mul = d_product * n_coins;
+    let l_val = (leverage_mul + d_p_mul) * initial_d;
+    let leverage_sub = initial_d * Uint256::from(leverage - 1);
+    let n_coins_sum = d_product * (n_coins + Uint256::from(1u8));
+    let r_val = leverage_sub + n_coins_sum;
+    l_val / r_val
+}
+
+fn calc_y(leverage: u64, new_source_amount: u128, d_val: u128) -> StdResult<u128> {
+    let n_coins = Uint256::from(N_COINS);
+    let leverage = Uint256::from(leverage);
+    let new_source_amount = Uint256::from(new_source_amount);
+    let d_val = Uint256::from(d_val);
+
+    // c = D ** (n + 1) / (n ** n * prod' * A)
+    let c = d_val * d_val * d_val / (new_source_amount * n_coins * n_coins * leverage);
+    // b = sum' + D / A
+    let b = new_source_amount + d_val / leverage;
+
+    // Solve for y by approximating: y ** 2 + b * y = c
+    let mut y = d_val;
+    for _ in 0..ITERATIONS {
+        let y_prev = y;
+        y = (y * y + c) / (n_coins * y + b - d_val);
+        if y == y_prev {
+            break;
+        }
+    }
+
+    to_u128(y)
+}
+
+fn to_u128(value: Uint256) -> StdResult<u128> {
+    let value: Uint128 = value
+        .try_into()
+        .map_err(|_| StdError::generic_err("[mock]: value does not fit in u128"))?;
+    Ok(value.u128())
+}
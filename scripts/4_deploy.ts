@@ -11,6 +11,30 @@ const argv = yargs(process.argv)
       type: "string",
       demandOption: true,
     },
+    factory: {
+      alias: "f",
+      type: "string",
+      demandOption: true,
+    },
+    router: {
+      alias: "r",
+      type: "string",
+      demandOption: true,
+    },
+    owner: {
+      alias: "o",
+      type: "string",
+      demandOption: false,
+    },
+    "fee-bps": {
+      type: "number",
+      default: 0,
+      demandOption: false,
+    },
+    "fee-collector": {
+      type: "string",
+      demandOption: false,
+    },
     "code-id": {
       alias: "c",
       type: "number",
@@ -55,8 +79,16 @@ if (!process.env.MNEMONIC) {
 
 console.log(`network  : ${argv.network}`);
 console.log(`codeId   : ${argv["code-id"] == 0 ? "unspecified" : argv["code-id"]}`);
+console.log(`factory  : ${argv.factory}`);
+console.log(`router   : ${argv.router}`);
 console.log(`deployer : ${deployer.key.accAddress}`);
 
+// The owner and the fee collector default to the deployer
+const owner = argv.owner ?? deployer.key.accAddress;
+const feeCollector = argv["fee-collector"] ?? deployer.key.accAddress;
+console.log(`owner    : ${owner}`);
+console.log(`fee      : ${argv["fee-bps"]} bps, collected by ${feeCollector}`);
+
 (async () => {
   let codeId = argv["code-id"];
   if (codeId == 0) {
@@ -74,7 +106,13 @@ console.log(`deployer : ${deployer.key.accAddress}`);
   process.stdout.write("ready to instantiate! press any key to continue, CTRL+C to abort... ");
   await waitUntilKeypress();
   process.stdout.write("instantiating contract... ");
-  const contractAddress = await instantiateContract(terra, deployer, codeId, {});
+  const contractAddress = await instantiateContract(terra, deployer, codeId, {
+    factory: argv.factory,
+    router: argv.router,
+    owner,
+    fee_bps: argv["fee-bps"],
+    fee_collector: feeCollector,
+  });
   console.log(`success! address: ${contractAddress}`);
 
   process.exit(0);
@@ -12,6 +12,14 @@ type SimulateEnterResponse = {
   offer_asset: Asset;
   return_asset: Asset;
   mint_shares: Asset;
+  refund_assets: Asset[];
+  solver: {
+    iterations: number;
+    residual: string;
+    converged: boolean;
+  } | null;
+  protocol_fee: Asset[];
+  referral_fee: Asset[];
 };
 
 const terra = new LCDClient({
@@ -4,6 +4,8 @@ import { LCDClient, MnemonicKey, Wallet, MsgMigrateCode } from "@terra-money/ter
 import { waitUntilKeypress, storeCode, migrateContract } from "./3_helpers";
 
 const ASTROZAP_ADDR = "terra1s7wn47w5kcvn8yefj8y2ffev62a3yureaz4lvc";
+const FACTORY_ADDR = "terra15jsahkaf9p0qu8ye873p0u5z6g07wdad0tdq43";
+const ROUTER_ADDR = "terra13wf295fj9u209nknz2cgqmmna7ry3d3j5kv7t4";
 
 const terra = new LCDClient({
   URL: "https://bombay-lcd.terra.dev",
@@ -38,7 +40,13 @@ if (!process.env.MNEMONIC) {
   process.stdout.write("ready to migrate! press any key to continue, CTRL+C to abort... ");
   await waitUntilKeypress();
   process.stdout.write("migrating contract... ");
-  const txhash = await migrateContract(terra, deployer, ASTROZAP_ADDR, codeId);
+  const txhash = await migrateContract(terra, deployer, ASTROZAP_ADDR, codeId, {
+    factory: FACTORY_ADDR,
+    router: ROUTER_ADDR,
+    owner: deployer.key.accAddress,
+    fee_bps: 0,
+    fee_collector: deployer.key.accAddress,
+  });
   console.log(`success! txhash: ${txhash}`);
 
   process.exit(0);
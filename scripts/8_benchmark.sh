@@ -0,0 +1,50 @@
+#!/usr/bin/env bash
+#
+# Compare the optimized wasm binaries of two revisions of the contract: binary size, and the total
+# number of wasm instructions
+#
+# Usage, in `./scripts` directory:
+#
+#   ./8_benchmark.sh <base-revision> [<head-revision>]
+#
+# `head-revision` defaults to `HEAD`. Requires Docker (for `rust-optimizer`) and `wasm-opcodecnt`
+# from the WebAssembly Binary Toolkit (https://github.com/WebAssembly/wabt)
+
+set -euo pipefail
+
+OPTIMIZER="cosmwasm/rust-optimizer:0.12.5"
+
+BASE_REV="${1:?usage: $0 <base-revision> [<head-revision>]}"
+HEAD_REV="${2:-HEAD}"
+
+REPO_ROOT="$(git rev-parse --show-toplevel)"
+WORK_DIR="$(mktemp -d)"
+trap 'rm -rf "$WORK_DIR"; git -C "$REPO_ROOT" worktree prune' EXIT
+
+build() {
+  local rev="$1"
+  local dir="$WORK_DIR/$rev"
+  git -C "$REPO_ROOT" worktree add --detach --quiet "$dir" "$rev"
+  docker run --rm -v "$dir/contracts/astrozap":/code \
+    --mount type=volume,source=astrozap_benchmark_cache,target=/code/target \
+    --mount type=volume,source=registry_cache,target=/usr/local/cargo/registry \
+    "$OPTIMIZER" >/dev/null
+  echo "$dir/contracts/astrozap/artifacts/astrozap.wasm"
+}
+
+report() {
+  local rev="$1"
+  local wasm="$2"
+  local size
+  local opcodes
+  size="$(wc -c <"$wasm" | tr -d ' ')"
+  opcodes="$(wasm-opcodecnt "$wasm" | awk -F': ' '/^Total opcodes/ { print $2 }')"
+  printf "%-12s %12s %12s\n" "$rev" "$size" "$opcodes"
+}
+
+base_wasm="$(build "$BASE_REV")"
+head_wasm="$(build "$HEAD_REV")"
+
+printf "%-12s %12s %12s\n" "revision" "size (bytes)" "opcodes"
+report "$BASE_REV" "$base_wasm"
+report "$HEAD_REV" "$head_wasm"
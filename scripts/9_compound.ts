@@ -0,0 +1,102 @@
+import * as fs from "fs";
+import dotenv from "dotenv";
+import yargs from "yargs/yargs";
+import { LCDClient, MnemonicKey, Wallet, MsgExecuteContract } from "@terra-money/terra.js";
+import { waitUntilKeypress, sendTransaction } from "./3_helpers";
+
+// Compound the generator rewards of liquidity tokens staked in AstroZap with the `stake` hook back
+// into the pair, by executing the contract's `compound` message. Only the contract's owner, or one
+// of the keepers set in its config, can execute it
+//
+// Rewards that are not one of the pair's assets (e.g. proxy rewards, or ASTRO for a pair without
+// ASTRO) are swapped through the router. Provide a route for each of them in a JSON file containing
+// an array of routes, each an array of swap operations as accepted by the router
+
+const argv = yargs(process.argv)
+  .options({
+    contract: {
+      alias: "c",
+      type: "string",
+      demandOption: true,
+    },
+    pair: {
+      alias: "p",
+      type: "string",
+      demandOption: true,
+    },
+    routes: {
+      alias: "r",
+      type: "string",
+      demandOption: false,
+    },
+    "minimum-received": {
+      alias: "m",
+      type: "string",
+      demandOption: false,
+    },
+  })
+  .parseSync();
+
+type PairInfo = {
+  liquidity_token: string;
+};
+
+type PendingTokenResponse = {
+  pending: string;
+  pending_on_proxy: string | null;
+};
+
+const terra = new LCDClient({
+  URL: "https://bombay-lcd.terra.dev",
+  chainID: "bombay-12",
+  gasPrices: "0.155uusd",
+  gasAdjustment: 1.4,
+});
+
+let owner: Wallet;
+dotenv.config();
+if (!process.env.MNEMONIC) {
+  throw new Error("mnemonic not provided");
+} else {
+  owner = terra.wallet(
+    new MnemonicKey({
+      mnemonic: process.env.MNEMONIC,
+    }),
+  );
+}
+
+(async () => {
+  const routes = argv["routes"] ? JSON.parse(fs.readFileSync(argv["routes"], "utf8")) : [];
+
+  process.stdout.write("querying pending rewards... ");
+  const { liquidity_token }: PairInfo = await terra.wasm.contractQuery(argv["pair"], { pair: {} });
+  const { factory }: { factory: string } = await terra.wasm.contractQuery(argv["contract"], {
+    config: {},
+  });
+  const { generator_address }: { generator_address: string } = await terra.wasm.contractQuery(
+    factory,
+    { config: {} },
+  );
+  const pending: PendingTokenResponse = await terra.wasm.contractQuery(generator_address, {
+    pending_token: {
+      lp_token: liquidity_token,
+      user: argv["contract"],
+    },
+  });
+  console.log("done! pending:", pending);
+
+  process.stdout.write("ready to submit tx! press any key to continue, CTRL+C to abort... ");
+  await waitUntilKeypress();
+  process.stdout.write("submitting tx... ");
+  const { txhash } = await sendTransaction(terra, owner, [
+    new MsgExecuteContract(owner.key.accAddress, argv["contract"], {
+      compound: {
+        pair: argv["pair"],
+        routes,
+        minimum_received: argv["minimum-received"],
+      },
+    }),
+  ]);
+  console.log("done! txhash:", txhash);
+  process.exit(0);
+})();